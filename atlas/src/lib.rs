@@ -0,0 +1,310 @@
+///! Packs lots of little sprite images into a few big pages, so things drawn with different sprites
+///! can still share a texture (and get batched together).
+///!
+///! Every image gets padding around it, and its edge pixels extruded outwards, so filtering near
+///! the edge of one sprite never picks up its neighbour.
+///!
+///! Atlases can be built at load time (AtlasBuilder::add_dir + build), or built ahead of time with
+///! the atlas tool and loaded with Atlas::load. Either way, sprites ask for their UVs by name.
+extern crate image;
+extern crate toml;
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::Path;
+use image::{RgbaImage, ImageError};
+
+#[derive(Debug)]
+pub enum AtlasError {
+    Io(io::Error),
+    Image(ImageError),
+    TooBig(String), // An image that can't fit on a page even on its own
+    Meta(String), // Something wrong with a metadata file
+}
+
+impl From<io::Error> for AtlasError {
+    fn from(e: io::Error) -> AtlasError {
+        AtlasError::Io(e)
+    }
+}
+
+impl From<ImageError> for AtlasError {
+    fn from(e: ImageError) -> AtlasError {
+        AtlasError::Image(e)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+// Where an image ended up, in pixels from the top left of its page. Doesn't include the extrusion.
+pub struct Region {
+    pub page: usize,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Region {
+    // Turns a UV on the original image into one on the page. Both have v going up from the bottom,
+    // like GL has it once the image is uploaded flipped (glium's from_raw_rgba_reversed).
+    pub fn uv(&self, page_size: u32, uv: [f32; 2]) -> [f32; 2] {
+        let size = page_size as f32;
+        let u = (self.x as f32 + uv[0] * self.width as f32) / size;
+        let v = 1.0 - (self.y as f32 + (1.0 - uv[1]) * self.height as f32) / size;
+        [u, v]
+    }
+}
+
+pub struct Atlas {
+    pub page_size: u32, // Pages are square
+    pub pages: Vec<RgbaImage>,
+    regions: HashMap<String, Region>,
+}
+
+impl Atlas {
+    pub fn region(&self, name: &str) -> Option<Region> {
+        self.regions.get(name).cloned()
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<_> = self.regions.keys().map(|n| &n[..]).collect();
+        names.sort();
+        names
+    }
+
+    // See Region::uv
+    pub fn uv(&self, name: &str, uv: [f32; 2]) -> Option<[f32; 2]> {
+        self.regions.get(name).map(|r| r.uv(self.page_size, uv))
+    }
+
+    // Writes <name>_<page>.png for each page, and <name>.toml describing them, into dir
+    pub fn save<P: AsRef<Path>>(&self, dir: P, name: &str) -> Result<(), AtlasError> {
+        let dir = dir.as_ref();
+        try!(fs::create_dir_all(dir));
+        let mut pages = vec![];
+        for (i, page) in self.pages.iter().enumerate() {
+            let file = format!("{}_{}.png", name, i);
+            try!(page.save(dir.join(&file)));
+            pages.push(toml::Value::String(file));
+        }
+        let mut regions = BTreeMap::new();
+        for (name, r) in self.regions.iter() {
+            let mut t = BTreeMap::new();
+            t.insert("page".to_string(), toml::Value::Integer(r.page as i64));
+            t.insert("x".to_string(), toml::Value::Integer(r.x as i64));
+            t.insert("y".to_string(), toml::Value::Integer(r.y as i64));
+            t.insert("width".to_string(), toml::Value::Integer(r.width as i64));
+            t.insert("height".to_string(), toml::Value::Integer(r.height as i64));
+            regions.insert(name.clone(), toml::Value::Table(t));
+        }
+        let mut meta = BTreeMap::new();
+        meta.insert("page_size".to_string(), toml::Value::Integer(self.page_size as i64));
+        meta.insert("pages".to_string(), toml::Value::Array(pages));
+        meta.insert("regions".to_string(), toml::Value::Table(regions));
+
+        let mut f = try!(File::create(dir.join(name).with_extension("toml")));
+        try!(write!(f, "{}", toml::Value::Table(meta)));
+        Ok(())
+    }
+
+    // Loads what save wrote
+    pub fn load<P: AsRef<Path>>(dir: P, name: &str) -> Result<Atlas, AtlasError> {
+        let dir = dir.as_ref();
+        let mut s = String::new();
+        try!(try!(File::open(dir.join(name).with_extension("toml"))).read_to_string(&mut s));
+        let (page_size, files, regions) = try!(parse_meta(&s));
+        let mut pages = vec![];
+        for file in files {
+            pages.push(try!(image::open(dir.join(file))).to_rgba());
+        }
+        if let Some((name, _)) = regions.iter().find(|&(_, r)| r.page >= pages.len()) {
+            return Err(AtlasError::Meta(format!("{} is on a page that isn't there", name)));
+        }
+        Ok(Atlas {
+            page_size: page_size,
+            pages: pages,
+            regions: regions,
+        })
+    }
+}
+
+fn parse_meta(s: &str) -> Result<(u32, Vec<String>, HashMap<String, Region>), AtlasError> {
+    let mut parser = toml::Parser::new(s);
+    let table = match parser.parse() {
+        Some(t) => t,
+        None => {
+            let e = &parser.errors[0];
+            let (line, col) = parser.to_linecol(e.lo);
+            return Err(AtlasError::Meta(format!("{}:{}: {}", line + 1, col + 1, e.desc)));
+        }
+    };
+    let page_size = try!(table.get("page_size").and_then(|v| v.as_integer())
+        .ok_or(AtlasError::Meta("page_size should be a number".into())));
+    let files = try!(table.get("pages").and_then(|v| v.as_slice())
+        .ok_or(AtlasError::Meta("pages should be a list of files".into())));
+    let files = try!(files.iter().map(|f| f.as_str().map(|s| s.to_string()))
+        .collect::<Option<Vec<_>>>().ok_or(AtlasError::Meta("pages should be a list of files".into())));
+    let table_regions = try!(table.get("regions").and_then(|v| v.as_table())
+        .ok_or(AtlasError::Meta("regions should be a table".into())));
+
+    let mut regions = HashMap::new();
+    for (name, r) in table_regions.iter() {
+        let field = |f: &str| r.as_table().and_then(|t| t.get(f)).and_then(|v| v.as_integer()).map(|v| v as u32)
+            .ok_or(AtlasError::Meta(format!("regions.{}.{} should be a number", name, f)));
+        regions.insert(name.clone(), Region {
+            page: try!(field("page")) as usize,
+            x: try!(field("x")),
+            y: try!(field("y")),
+            width: try!(field("width")),
+            height: try!(field("height")),
+        });
+    }
+    Ok((page_size as u32, files, regions))
+}
+
+pub struct AtlasBuilder {
+    page_size: u32,
+    padding: u32, // Empty pixels between images
+    extrude: u32, // How far each image's edges get stretched out
+    images: Vec<(String, RgbaImage)>,
+}
+
+impl AtlasBuilder {
+    pub fn new(page_size: u32) -> AtlasBuilder {
+        AtlasBuilder {
+            page_size: page_size,
+            padding: 2,
+            extrude: 1,
+            images: vec![],
+        }
+    }
+
+    pub fn padding(mut self, padding: u32) -> AtlasBuilder {
+        self.padding = padding;
+        self
+    }
+
+    pub fn extrude(mut self, extrude: u32) -> AtlasBuilder {
+        self.extrude = extrude;
+        self
+    }
+
+    pub fn add<S: Into<String>>(&mut self, name: S, image: RgbaImage) {
+        self.images.push((name.into(), image));
+    }
+
+    // Adds every png under dir, named by its path from dir without the extension (ex. "enemies/bat")
+    pub fn add_dir<P: AsRef<Path>>(&mut self, dir: P) -> Result<(), AtlasError> {
+        let dir = dir.as_ref();
+        self.add_dir_under(dir, dir)
+    }
+
+    fn add_dir_under(&mut self, root: &Path, dir: &Path) -> Result<(), AtlasError> {
+        for entry in try!(fs::read_dir(dir)) {
+            let path = try!(entry).path();
+            if path.is_dir() {
+                try!(self.add_dir_under(root, &path));
+            } else if path.extension().map(|e| e == "png").unwrap_or(false) {
+                let name = path.strip_prefix(root).unwrap().with_extension("");
+                // Same names on every platform
+                let name = name.components().map(|c| c.as_os_str().to_string_lossy().into_owned()).collect::<Vec<_>>().join("/");
+                let image = try!(image::open(&path)).to_rgba();
+                self.add(name, image);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn build(self) -> Result<Atlas, AtlasError> {
+        let (size, padding, extrude) = (self.page_size, self.padding, self.extrude);
+        let mut images = self.images;
+        // Tallest first packs shelves tightest. Names break ties so the same images always give the same atlas.
+        images.sort_by(|a, b| match b.1.height().cmp(&a.1.height()) {
+            ::std::cmp::Ordering::Equal => a.0.cmp(&b.0),
+            o => o
+        });
+
+        let mut pages = vec![];
+        let mut regions = HashMap::new();
+        let mut packer = Shelves::new(size);
+        for (name, image) in images {
+            let (w, h) = (image.width() + extrude * 2, image.height() + extrude * 2);
+            if w > size || h > size {
+                return Err(AtlasError::TooBig(name));
+            }
+            let (x, y) = match packer.insert(w, h, padding) {
+                Some(spot) => spot,
+                None => {
+                    // Page's full, start another
+                    pages.push(RgbaImage::new(size, size));
+                    packer = Shelves::new(size);
+                    packer.insert(w, h, padding).unwrap()
+                }
+            };
+            if pages.is_empty() {
+                pages.push(RgbaImage::new(size, size));
+            }
+            let page = pages.len() - 1;
+            blit_extruded(&mut pages[page], &image, x, y, extrude);
+            regions.insert(name, Region {
+                page: page,
+                x: x + extrude,
+                y: y + extrude,
+                width: image.width(),
+                height: image.height(),
+            });
+        }
+        Ok(Atlas {
+            page_size: size,
+            pages: pages,
+            regions: regions,
+        })
+    }
+}
+
+// Copies image onto page with its top left at (x + extrude, y + extrude), then smears its edges out by extrude
+fn blit_extruded(page: &mut RgbaImage, image: &RgbaImage, x: u32, y: u32, extrude: u32) {
+    let (w, h) = (image.width() as i64, image.height() as i64);
+    let e = extrude as i64;
+    for py in -e..h + e {
+        for px in -e..w + e {
+            // The nearest pixel of the image
+            let sx = px.max(0).min(w - 1) as u32;
+            let sy = py.max(0).min(h - 1) as u32;
+            let pixel = *image.get_pixel(sx, sy);
+            page.put_pixel((x as i64 + e + px) as u32, (y as i64 + e + py) as u32, pixel);
+        }
+    }
+}
+
+// Rows of rectangles, each as tall as the first thing put in it
+struct Shelves {
+    size: u32,
+    shelves: Vec<(u32, u32, u32)>, // Y Height X
+}
+
+impl Shelves {
+    fn new(size: u32) -> Shelves {
+        Shelves {
+            size: size,
+            shelves: vec![],
+        }
+    }
+
+    // The top left of where a w x h rectangle goes, keeping padding away from everything else
+    fn insert(&mut self, w: u32, h: u32, padding: u32) -> Option<(u32, u32)> {
+        let size = self.size;
+        for shelf in self.shelves.iter_mut() {
+            if h <= shelf.1 && shelf.2 + w <= size {
+                let spot = (shelf.2, shelf.0);
+                shelf.2 += w + padding;
+                return Some(spot);
+            }
+        }
+        let y = self.shelves.last().map(|s| s.0 + s.1 + padding).unwrap_or(0);
+        if y + h > size { return None }
+        self.shelves.push((y, h, w + padding));
+        Some((0, y))
+    }
+}
@@ -0,0 +1,46 @@
+extern crate atlas;
+#[macro_use]
+extern crate clap;
+
+use clap::{Arg, App};
+
+// Packs a folder of sprites ahead of time, so the game doesn't have to at load time
+fn main() {
+    let matches = App::new("atlas")
+                        .author(crate_authors!())
+                        .version(crate_version!())
+                        .about("Packs sprite images into texture atlas pages")
+                        .arg(Arg::from_usage("<input> 'Folder of png images (ex. data/textures)'"))
+                        .arg(Arg::from_usage("<output> -o, --output=<DIR> 'Folder to write the pages and metadata to'"))
+                        .arg(Arg::from_usage("[name] -n, --name=[NAME] 'Name of the atlas files (default: atlas)'"))
+                        .arg(Arg::from_usage("[size] -s, --size=[PIXELS] 'Width and height of each page (default: 1024)'"))
+                        .arg(Arg::from_usage("[padding] -p, --padding=[PIXELS] 'Empty space between images (default: 2)'"))
+                        .arg(Arg::from_usage("[extrude] -e, --extrude=[PIXELS] 'How far edges get stretched out (default: 1)'"))
+                        .arg(Arg::from_usage("[verbose] -v, --verbose 'Lists where everything went'"))
+                .get_matches();
+
+    let size = matches.value_of("size").unwrap_or("1024").parse().expect("size should be a number");
+    let padding = matches.value_of("padding").unwrap_or("2").parse().expect("padding should be a number");
+    let extrude = matches.value_of("extrude").unwrap_or("1").parse().expect("extrude should be a number");
+    let name = matches.value_of("name").unwrap_or("atlas");
+    let output = matches.value_of("output").unwrap();
+
+    let mut builder = atlas::AtlasBuilder::new(size).padding(padding).extrude(extrude);
+    builder.add_dir(matches.value_of("input").unwrap()).expect("unable to read the input images");
+    let atlas = match builder.build() {
+        Ok(a) => a,
+        Err(atlas::AtlasError::TooBig(image)) => {
+            println!("{} doesn't fit on a {}x{} page", image, size, size);
+            std::process::exit(1);
+        },
+        Err(e) => panic!("{:?}", e)
+    };
+    atlas.save(output, name).expect("unable to write the atlas");
+
+    if matches.is_present("verbose") {
+        for n in atlas.names() {
+            println!("{} -> {:?}", n, atlas.region(n).unwrap());
+        }
+    }
+    println!("Packed {} images into {} page(s)", atlas.names().len(), atlas.pages.len());
+}
@@ -0,0 +1,98 @@
+extern crate atlas;
+extern crate image;
+
+use atlas::{AtlasBuilder, AtlasError, Atlas};
+use image::{RgbaImage, Rgba};
+
+fn solid(w: u32, h: u32, c: u8) -> RgbaImage {
+    RgbaImage::from_pixel(w, h, Rgba([c, c, c, 255]))
+}
+
+fn overlaps(a: &atlas::Region, b: &atlas::Region, gap: u32) -> bool {
+    a.page == b.page
+        && a.x < b.x + b.width + gap && b.x < a.x + a.width + gap
+        && a.y < b.y + b.height + gap && b.y < a.y + a.height + gap
+}
+
+#[test]
+fn packs_without_overlap() {
+    let mut builder = AtlasBuilder::new(64).padding(2).extrude(1);
+    for i in 0..10 {
+        builder.add(format!("s{}", i), solid(8 + i, 6 + i % 3, i as u8));
+    }
+    let atlas = builder.build().unwrap();
+    assert_eq!(atlas.pages.len(), 1);
+    let regions: Vec<_> = atlas.names().iter().map(|n| atlas.region(n).unwrap()).collect();
+    for (i, a) in regions.iter().enumerate() {
+        for b in regions[i + 1..].iter() {
+            // Extruded edges and padding have to fit between them too
+            assert!(!overlaps(a, b, 2 + 2), "{:?} and {:?} are too close", a, b);
+        }
+    }
+}
+
+#[test]
+fn edges_are_extruded() {
+    let mut image = solid(2, 2, 10);
+    image.put_pixel(0, 0, Rgba([200, 0, 0, 255]));
+    let mut builder = AtlasBuilder::new(16).padding(0).extrude(2);
+    builder.add("a", image);
+    let atlas = builder.build().unwrap();
+    let r = atlas.region("a").unwrap();
+    assert_eq!((r.x, r.y, r.width, r.height), (2, 2, 2, 2));
+    let page = &atlas.pages[0];
+    // The top left pixel smears up and left into the corner
+    assert_eq!(*page.get_pixel(0, 0), Rgba([200, 0, 0, 255]));
+    assert_eq!(*page.get_pixel(2, 0), Rgba([200, 0, 0, 255]));
+    assert_eq!(*page.get_pixel(5, 5), Rgba([10, 10, 10, 255]));
+    // Nothing past the extrusion
+    assert_eq!(*page.get_pixel(6, 6), Rgba([0, 0, 0, 0]));
+}
+
+#[test]
+fn spills_onto_more_pages() {
+    let mut builder = AtlasBuilder::new(16).padding(0).extrude(0);
+    for i in 0..5 {
+        builder.add(format!("s{}", i), solid(16, 8, 1));
+    }
+    let atlas = builder.build().unwrap();
+    assert_eq!(atlas.pages.len(), 3);
+    assert_eq!(atlas.region("s4").unwrap().page, 2);
+}
+
+#[test]
+fn too_big() {
+    let mut builder = AtlasBuilder::new(16).extrude(1);
+    builder.add("huge", solid(15, 4, 1));
+    match builder.build() {
+        Err(AtlasError::TooBig(ref name)) if name == "huge" => (),
+        other => panic!("Expected TooBig, got {:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn uvs_map_into_the_page() {
+    let mut builder = AtlasBuilder::new(16).padding(0).extrude(1);
+    builder.add("a", solid(4, 2, 1));
+    let atlas = builder.build().unwrap();
+    // At (1, 1), so it covers u 1/16 to 5/16, and rows 1 to 3 from the top
+    assert_eq!(atlas.uv("a", [0.0, 0.0]), Some([1.0 / 16.0, 13.0 / 16.0]));
+    assert_eq!(atlas.uv("a", [1.0, 1.0]), Some([5.0 / 16.0, 15.0 / 16.0]));
+    assert_eq!(atlas.uv("b", [0.0, 0.0]), None);
+}
+
+#[test]
+fn save_and_load() {
+    let dir = std::env::temp_dir().join("atlas_save_and_load");
+    let mut builder = AtlasBuilder::new(32);
+    builder.add("a", solid(4, 4, 50));
+    builder.add("dir/b", solid(3, 7, 90));
+    let atlas = builder.build().unwrap();
+    atlas.save(&dir, "test").unwrap();
+
+    let loaded = Atlas::load(&dir, "test").unwrap();
+    assert_eq!(loaded.page_size, 32);
+    assert_eq!(loaded.names(), vec!["a", "dir/b"]);
+    assert_eq!(loaded.region("dir/b"), atlas.region("dir/b"));
+    assert_eq!(loaded.pages[0].get_pixel(10, 10), atlas.pages[0].get_pixel(10, 10));
+}
@@ -0,0 +1,396 @@
+///! Bitmap fonts: AngelCode BMFont descriptors (text and binary), and fixed-grid pixel fonts
+use std::collections::HashMap;
+use std::path::Path;
+use std::io;
+use glium::backend::Facade;
+use glium::Texture2d;
+use rusttype::{Rect, point};
+
+#[derive(Debug)]
+pub enum BitmapError {
+    Io(io::Error),
+    Image(String),
+    // The descriptor is malformed somehow. Says what and where.
+    Syntax(String),
+    // A binary descriptor, but not one we understand
+    Version(u8),
+}
+
+impl From<io::Error> for BitmapError {
+    fn from(e: io::Error) -> BitmapError {
+        BitmapError::Io(e)
+    }
+}
+
+// Where a character lives on its page, and how to place it relative to the caret.
+// All values are in the font's native pixels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BitmapGlyph {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub xoffset: i32,
+    pub yoffset: i32, // From the top of the line
+    pub xadvance: i32,
+    pub page: usize,
+}
+
+// Everything in a BMFont file, minus the actual images
+#[derive(Clone, Debug)]
+pub struct BmFontDescriptor {
+    pub line_height: u32,
+    pub base: u32, // Distance from the top of a line to the baseline
+    pub scale_w: u32, // Page dimensions
+    pub scale_h: u32,
+    pub pages: Vec<String>, // Image files, relative to the descriptor
+    pub chars: HashMap<char, BitmapGlyph>,
+    pub kernings: HashMap<(char, char), i32>,
+}
+
+impl BmFontDescriptor {
+    fn empty() -> BmFontDescriptor {
+        BmFontDescriptor {
+            line_height: 0,
+            base: 0,
+            scale_w: 0,
+            scale_h: 0,
+            pages: vec![],
+            chars: HashMap::new(),
+            kernings: HashMap::new(),
+        }
+    }
+
+    // Tells the text and binary formats apart by the "BMF" magic
+    pub fn parse(data: &[u8]) -> Result<BmFontDescriptor, BitmapError> {
+        if data.starts_with(b"BMF") {
+            BmFontDescriptor::parse_binary(data)
+        } else {
+            match ::std::str::from_utf8(data) {
+                Ok(s) => BmFontDescriptor::parse_text(s),
+                Err(_) => Err(BitmapError::Syntax("descriptor is neither binary nor UTF-8 text".into()))
+            }
+        }
+    }
+
+    // The text format is lines of `tag key=value key="quoted value" ...`
+    pub fn parse_text(s: &str) -> Result<BmFontDescriptor, BitmapError> {
+        let mut desc = BmFontDescriptor::empty();
+        for (n, line) in s.lines().enumerate() {
+            let (tag, pairs) = match split_tag(line) {
+                Some(t) => t,
+                None => continue
+            };
+            let get = |key: &str| -> Result<i64, BitmapError> {
+                match pairs.get(key) {
+                    Some(v) => v.parse().map_err(|_| BitmapError::Syntax(format!("line {}: {}={} is not a number", n + 1, key, v))),
+                    None => Err(BitmapError::Syntax(format!("line {}: {} is missing {}", n + 1, tag, key)))
+                }
+            };
+            match tag {
+                "common" => {
+                    desc.line_height = try!(get("lineHeight")) as u32;
+                    desc.base = try!(get("base")) as u32;
+                    desc.scale_w = try!(get("scaleW")) as u32;
+                    desc.scale_h = try!(get("scaleH")) as u32;
+                },
+                "page" => {
+                    let id = try!(get("id")) as usize;
+                    let file = match pairs.get("file") {
+                        Some(f) => f.clone(),
+                        None => return Err(BitmapError::Syntax(format!("line {}: page is missing file", n + 1)))
+                    };
+                    if desc.pages.len() <= id {
+                        desc.pages.resize(id + 1, String::new());
+                    }
+                    desc.pages[id] = file;
+                },
+                "char" => {
+                    let c = try!(id_to_char(try!(get("id")) as u32));
+                    desc.chars.insert(c, BitmapGlyph {
+                        x: try!(get("x")) as u32,
+                        y: try!(get("y")) as u32,
+                        width: try!(get("width")) as u32,
+                        height: try!(get("height")) as u32,
+                        xoffset: try!(get("xoffset")) as i32,
+                        yoffset: try!(get("yoffset")) as i32,
+                        xadvance: try!(get("xadvance")) as i32,
+                        page: try!(get("page")) as usize,
+                    });
+                },
+                "kerning" => {
+                    let first = try!(id_to_char(try!(get("first")) as u32));
+                    let second = try!(id_to_char(try!(get("second")) as u32));
+                    desc.kernings.insert((first, second), try!(get("amount")) as i32);
+                },
+                _ => () // info, chars, kernings: nothing we need
+            }
+        }
+        Ok(desc)
+    }
+
+    // Binary format, version 3: "BMF", a version byte, then blocks of (type: u8, size: u32, data)
+    pub fn parse_binary(data: &[u8]) -> Result<BmFontDescriptor, BitmapError> {
+        if !data.starts_with(b"BMF") || data.len() < 4 {
+            return Err(BitmapError::Syntax("missing BMF header".into()));
+        }
+        if data[3] != 3 {
+            return Err(BitmapError::Version(data[3]));
+        }
+        let mut desc = BmFontDescriptor::empty();
+        let mut at = 4;
+        while at < data.len() {
+            if at + 5 > data.len() {
+                return Err(BitmapError::Syntax(format!("truncated block header at byte {}", at)));
+            }
+            let kind = data[at];
+            let size = read_u32(data, at + 1) as usize;
+            at += 5;
+            if at + size > data.len() {
+                return Err(BitmapError::Syntax(format!("block {} runs past the end of the file", kind)));
+            }
+            let block = &data[at..at + size];
+            match kind {
+                2 => { // common
+                    if block.len() < 8 {
+                        return Err(BitmapError::Syntax("common block is too short".into()));
+                    }
+                    desc.line_height = read_u16(block, 0) as u32;
+                    desc.base = read_u16(block, 2) as u32;
+                    desc.scale_w = read_u16(block, 4) as u32;
+                    desc.scale_h = read_u16(block, 6) as u32;
+                },
+                3 => { // pages, as null terminated strings
+                    for name in block.split(|b| *b == 0).filter(|n| !n.is_empty()) {
+                        desc.pages.push(String::from_utf8_lossy(name).into_owned());
+                    }
+                },
+                4 => { // chars, 20 bytes each
+                    for ch in block.chunks(20).filter(|ch| ch.len() == 20) {
+                        let c = try!(id_to_char(read_u32(ch, 0)));
+                        desc.chars.insert(c, BitmapGlyph {
+                            x: read_u16(ch, 4) as u32,
+                            y: read_u16(ch, 6) as u32,
+                            width: read_u16(ch, 8) as u32,
+                            height: read_u16(ch, 10) as u32,
+                            xoffset: read_u16(ch, 12) as i16 as i32,
+                            yoffset: read_u16(ch, 14) as i16 as i32,
+                            xadvance: read_u16(ch, 16) as i16 as i32,
+                            page: ch[18] as usize,
+                        });
+                    }
+                },
+                5 => { // kerning pairs, 10 bytes each
+                    for kp in block.chunks(10).filter(|kp| kp.len() == 10) {
+                        let first = try!(id_to_char(read_u32(kp, 0)));
+                        let second = try!(id_to_char(read_u32(kp, 4)));
+                        desc.kernings.insert((first, second), read_u16(kp, 8) as i16 as i32);
+                    }
+                },
+                _ => () // info: nothing we need
+            }
+            at += size;
+        }
+        Ok(desc)
+    }
+
+    // A pixel font drawn on a fixed grid. chars lists the characters in the image,
+    // left to right, top to bottom, one per cell.
+    pub fn grid<S: Into<String>>(page: S, image_size: (u32, u32), cell: (u32, u32), chars: &str) -> BmFontDescriptor {
+        let columns = ::std::cmp::max(image_size.0 / cell.0, 1);
+        let mut desc = BmFontDescriptor::empty();
+        desc.line_height = cell.1;
+        desc.base = cell.1;
+        desc.scale_w = image_size.0;
+        desc.scale_h = image_size.1;
+        desc.pages.push(page.into());
+        for (i, c) in chars.chars().enumerate() {
+            let i = i as u32;
+            desc.chars.insert(c, BitmapGlyph {
+                x: (i % columns) * cell.0,
+                y: (i / columns) * cell.1,
+                width: cell.0,
+                height: cell.1,
+                xoffset: 0,
+                yoffset: 0,
+                xadvance: cell.0 as i32,
+                page: 0,
+            });
+        }
+        desc
+    }
+
+    pub fn kerning(&self, first: char, second: char) -> i32 {
+        self.kernings.get(&(first, second)).cloned().unwrap_or(0)
+    }
+}
+
+// Splits a text descriptor line into its tag and key=value pairs, stripping quotes
+fn split_tag(line: &str) -> Option<(&str, HashMap<String, String>)> {
+    let line = line.trim();
+    let (tag, mut rest) = match line.find(char::is_whitespace) {
+        Some(i) => (&line[..i], line[i..].trim_left()),
+        None if !line.is_empty() => (line, ""),
+        None => return None
+    };
+    let mut pairs = HashMap::new();
+    while let Some(eq) = rest.find('=') {
+        let key = rest[..eq].trim().to_string();
+        rest = &rest[eq + 1..];
+        let value;
+        if rest.starts_with('"') {
+            let end = rest[1..].find('"').map(|e| e + 1).unwrap_or(rest.len());
+            value = rest[1..end].to_string();
+            rest = if end < rest.len() { &rest[end + 1..] } else { "" };
+        } else {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            value = rest[..end].to_string();
+            rest = &rest[end..];
+        }
+        pairs.insert(key, value);
+        rest = rest.trim_left();
+    }
+    Some((tag, pairs))
+}
+
+fn id_to_char(id: u32) -> Result<char, BitmapError> {
+    ::std::char::from_u32(id).ok_or(BitmapError::Syntax(format!("{} is not a valid character", id)))
+}
+
+fn read_u16(b: &[u8], at: usize) -> u16 {
+    b[at] as u16 | (b[at + 1] as u16) << 8
+}
+
+fn read_u32(b: &[u8], at: usize) -> u32 {
+    read_u16(b, at) as u32 | (read_u16(b, at + 2) as u32) << 16
+}
+
+// A glyph quad, ready to draw. Rects are y-down, like rusttype's.
+pub struct BitmapQuad {
+    pub page: usize,
+    pub uv: Rect<f32>,
+    pub screen: Rect<f32>,
+}
+
+// The descriptor, plus its pages uploaded to the GPU
+pub struct BitmapFont {
+    desc: BmFontDescriptor,
+    pages: Vec<Texture2d>,
+}
+
+impl BitmapFont {
+    // Loads a .fnt file (either format), and the page images next to it
+    pub fn load<F: Facade, P: AsRef<Path>>(f: &F, path: P) -> Result<BitmapFont, BitmapError> {
+        use std::fs::File;
+        use std::io::Read;
+
+        let mut data = vec![];
+        try!(try!(File::open(path.as_ref())).read_to_end(&mut data));
+        let desc = try!(BmFontDescriptor::parse(&data));
+        let dir = path.as_ref().parent().unwrap_or(Path::new(""));
+        BitmapFont::from_descriptor(f, desc, dir)
+    }
+
+    // Loads a fixed-grid pixel font out of a single image
+    pub fn load_grid<F: Facade, P: AsRef<Path>>(f: &F, path: P, cell: (u32, u32), chars: &str) -> Result<BitmapFont, BitmapError> {
+        let page = try!(load_page(f, path.as_ref()));
+        let desc = BmFontDescriptor::grid(path.as_ref().to_string_lossy().into_owned(), page.dimensions(), cell, chars);
+        Ok(BitmapFont {
+            desc: desc,
+            pages: vec![page],
+        })
+    }
+
+    // Page paths in the descriptor are relative to dir
+    pub fn from_descriptor<F: Facade, P: AsRef<Path>>(f: &F, desc: BmFontDescriptor, dir: P) -> Result<BitmapFont, BitmapError> {
+        let mut pages = Vec::with_capacity(desc.pages.len());
+        for page in desc.pages.iter() {
+            pages.push(try!(load_page(f, &dir.as_ref().join(page))));
+        }
+        Ok(BitmapFont {
+            desc: desc,
+            pages: pages,
+        })
+    }
+
+    pub fn descriptor(&self) -> &BmFontDescriptor {
+        &self.desc
+    }
+
+    pub fn page(&self, i: usize) -> &Texture2d {
+        &self.pages[i]
+    }
+
+    // Pixel fonts only stay crisp at whole multiples of their native size,
+    // so the requested pixel height gets rounded to one.
+    pub fn pixel_scale(&self, scale: f32) -> f32 {
+        (scale / self.desc.line_height as f32).round().max(1.0)
+    }
+
+    // Same rules as SimpleText's layout: '\n' breaks lines, and we wrap when a glyph would pass width.
+    // Missing characters are drawn as '?' if the font has one.
+    pub fn layout(&self, text: &str, scale: f32, width: u32) -> Vec<BitmapQuad> {
+        let k = self.pixel_scale(scale);
+        let advance_height = self.desc.line_height as f32 * k;
+        let (sw, sh) = (self.desc.scale_w.max(1) as f32, self.desc.scale_h.max(1) as f32);
+        let mut result = vec![];
+        let (mut x, mut y) = (0.0f32, 0.0f32);
+        let mut last = None;
+        for c in text.chars() {
+            if c.is_control() {
+                if c == '\n' {
+                    x = 0.0;
+                    y += advance_height;
+                    last = None;
+                }
+                continue;
+            }
+            let (c, g) = match self.desc.chars.get(&c) {
+                Some(g) => (c, *g),
+                None => match self.desc.chars.get(&'?') {
+                    Some(g) => ('?', *g),
+                    None => continue
+                }
+            };
+            if let Some(l) = last.take() {
+                x += self.desc.kerning(l, c) as f32 * k;
+            }
+            last = Some(c);
+            if x > 0.0 && x + (g.xoffset + g.width as i32) as f32 * k > width as f32 {
+                x = 0.0;
+                y += advance_height;
+                last = None;
+            }
+            if g.width > 0 && g.height > 0 {
+                let left = x + g.xoffset as f32 * k;
+                let top = y + g.yoffset as f32 * k;
+                result.push(BitmapQuad {
+                    page: g.page,
+                    uv: Rect {
+                        min: point(g.x as f32 / sw, g.y as f32 / sh),
+                        max: point((g.x + g.width) as f32 / sw, (g.y + g.height) as f32 / sh),
+                    },
+                    screen: Rect {
+                        min: point(left, top),
+                        max: point(left + g.width as f32 * k, top + g.height as f32 * k),
+                    },
+                });
+            }
+            x += g.xadvance as f32 * k;
+        }
+        result
+    }
+}
+
+// Pages are uploaded top row first, so v = 0 is the top of the image, same as the descriptor's y
+fn load_page<F: Facade>(f: &F, path: &Path) -> Result<Texture2d, BitmapError> {
+    use image;
+
+    let img = match image::open(path) {
+        Ok(img) => img.to_rgba(),
+        Err(e) => return Err(BitmapError::Image(format!("{}: {:?}", path.display(), e)))
+    };
+    let dims = img.dimensions();
+    let raw = ::glium::texture::RawImage2d::from_raw_rgba(img.into_raw(), dims);
+    Texture2d::new(f, raw).map_err(|e| BitmapError::Image(format!("{}: {:?}", path.display(), e)))
+}
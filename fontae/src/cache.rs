@@ -0,0 +1,397 @@
+///! Glyph caches. Every Text owns its own, so any number of them can share one Font.
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::rc::Rc;
+use glium::backend::Context;
+use glium::Texture2d;
+use rusttype::{self, Rect, Scale, point};
+use sdf::{distance_field, SDF_BASE_SCALE, SDF_SPREAD};
+use FontStack;
+
+// What gets stored in the cache texture
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CacheMode {
+    Coverage, // Plain glyph coverage, rasterized at the size it's drawn at
+    Distance, // Signed distance fields, rasterized once at SDF_BASE_SCALE
+}
+
+// Counters since the cache was made, plus a snapshot of how full it is. Meant for logging.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64, // Glyphs thrown out to make room for others
+    pub grows: u64,
+    pub dropped: u64, // Glyphs that couldn't fit even after all that, and went undrawn
+    pub glyphs: usize, // Glyphs cached right now
+    pub size: (u32, u32), // Texture size
+    pub occupancy: f32, // Fraction of the texture holding glyphs
+}
+
+impl CacheStats {
+    pub fn hit_rate(&self) -> f32 {
+        if self.hits + self.misses == 0 {
+            1.0
+        } else {
+            self.hits as f32 / (self.hits + self.misses) as f32
+        }
+    }
+}
+
+struct Shelf {
+    y: u32,
+    height: u32,
+    x: u32, // The next free spot
+    last_used: u64,
+}
+
+// Stacks rectangles into rows, tallest-so-far deciding each row's height.
+// Rows (shelves) are also the unit of eviction: when we're out of space, the
+// least recently used shelf gets emptied out in one go.
+pub struct ShelfPacker {
+    size: (u32, u32),
+    shelves: Vec<Shelf>,
+}
+
+impl ShelfPacker {
+    pub fn new(w: u32, h: u32) -> ShelfPacker {
+        ShelfPacker {
+            size: (w, h),
+            shelves: vec![],
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.shelves.clear();
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        self.size
+    }
+
+    // Shelves stay where they are, so this only ever grows
+    pub fn resize(&mut self, w: u32, h: u32) {
+        self.size = (::std::cmp::max(w, self.size.0), ::std::cmp::max(h, self.size.1));
+    }
+
+    // Returns the top left corner of the space reserved for a w x h rectangle
+    pub fn insert(&mut self, w: u32, h: u32) -> Option<(u32, u32)> {
+        if w > self.size.0 || h > self.size.1 { return None }
+        for shelf in self.shelves.iter_mut() {
+            if h <= shelf.height && shelf.x + w <= self.size.0 {
+                let spot = (shelf.x, shelf.y);
+                shelf.x += w;
+                return Some(spot);
+            }
+        }
+        let y = self.shelves.last().map(|s| s.y + s.height).unwrap_or(0);
+        if y + h > self.size.1 { return None }
+        self.shelves.push(Shelf {
+            y: y,
+            height: h,
+            x: w,
+            last_used: 0,
+        });
+        Some((0, y))
+    }
+
+    // Marks the shelf starting at y as used during frame
+    pub fn touch(&mut self, y: u32, frame: u64) {
+        if let Some(shelf) = self.shelves.iter_mut().find(|s| s.y == y) {
+            shelf.last_used = frame;
+        }
+    }
+
+    // Empties the least recently used shelf that could hold something h tall.
+    // Shelves used during frame are off limits. Returns the emptied shelf's y.
+    pub fn evict(&mut self, h: u32, frame: u64) -> Option<u32> {
+        let victim = self.shelves.iter_mut()
+            .filter(|s| s.height >= h && s.last_used < frame && s.x > 0)
+            .min_by_key(|s| s.last_used);
+        victim.map(|s| {
+            s.x = 0;
+            s.y
+        })
+    }
+
+    // Pixels in use, counting the unused height at the top of short glyphs
+    pub fn used_area(&self) -> u64 {
+        self.shelves.iter().map(|s| s.x as u64 * s.height as u64).sum()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct GlyphKey {
+    face: usize,
+    id: u32,
+    scale: u32, // Quarter pixels; always 0 for distance fields
+    offset: (u8, u8), // Subpixel position, in quarter pixels
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Entry {
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+    // Where the glyph sits relative to its origin, y-down.
+    // In pixels for coverage, and pixels at the base scale for distance fields.
+    bounds: Rect<f32>,
+}
+
+// Part of the cache texture, in texels. Turn it into texture coordinates with GlyphCache::uv,
+// once everything for the draw has been looked up (the texture may grow in between).
+#[derive(Clone, Copy, Debug)]
+pub struct CachedRect {
+    pub x: u32,
+    pub y: u32,
+    pub w: u32,
+    pub h: u32,
+}
+
+pub struct GlyphCache {
+    context: Rc<Context>,
+    mode: CacheMode,
+    font_id: usize, // Which Font the glyphs came from
+    texture: Texture2d,
+    pixels: Vec<u8>, // A copy of the texture, so growing keeps what's already there
+    max_size: u32,
+    packer: ShelfPacker,
+    entries: HashMap<GlyphKey, Option<Entry>>, // None for glyphs with nothing to draw (ex. space)
+    frame: u64,
+    stats: CacheStats,
+}
+
+impl GlyphCache {
+    pub fn new(context: &Rc<Context>, mode: CacheMode, font_id: usize, size: (u32, u32), max_size: u32) -> GlyphCache {
+        let pixels = vec![0u8; size.0 as usize * size.1 as usize];
+        GlyphCache {
+            context: context.clone(),
+            mode: mode,
+            font_id: font_id,
+            texture: make_texture(context, &pixels, size),
+            pixels: pixels,
+            max_size: max_size,
+            packer: ShelfPacker::new(size.0, size.1),
+            entries: HashMap::new(),
+            frame: 0,
+            stats: CacheStats::default(),
+        }
+    }
+
+    pub fn mode(&self) -> CacheMode {
+        self.mode
+    }
+
+    pub fn font_id(&self) -> usize {
+        self.font_id
+    }
+
+    pub fn texture(&self) -> &Texture2d {
+        &self.texture
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let (w, h) = self.packer.size();
+        CacheStats {
+            glyphs: self.entries.values().filter(|e| e.is_some()).count(),
+            size: (w, h),
+            occupancy: self.packer.used_area() as f32 / (w as f32 * h as f32),
+            ..self.stats
+        }
+    }
+
+    // Empties the cache, but keeps the counters
+    pub fn clear(&mut self) {
+        self.packer.clear();
+        self.entries.clear();
+    }
+
+    // Call once before looking up everything for a draw. Glyphs looked up since
+    // the last call are never evicted to make room for each other.
+    pub fn begin_frame(&mut self) {
+        self.frame += 1;
+    }
+
+    pub fn uv(&self, r: CachedRect) -> Rect<f32> {
+        let (tw, th) = self.packer.size();
+        let (tw, th) = (tw as f32, th as f32);
+        Rect {
+            min: point(r.x as f32 / tw, r.y as f32 / th),
+            max: point((r.x + r.w) as f32 / tw, (r.y + r.h) as f32 / th),
+        }
+    }
+
+    // Finds (or makes room for and rasterizes) glyph.
+    // Returns where it is in the cache, and where to draw it in layout space.
+    pub fn locate(&mut self, stack: &FontStack, face: usize, glyph: &rusttype::PositionedGlyph, scale: f32) -> Option<(CachedRect, Rect<f32>)> {
+        let pos = glyph.position();
+        let key = match self.mode {
+            CacheMode::Coverage => GlyphKey {
+                face: face,
+                id: glyph.id().0,
+                scale: (scale * 4.0).round() as u32,
+                offset: (((pos.x - pos.x.floor()) * 4.0) as u8, ((pos.y - pos.y.floor()) * 4.0) as u8),
+            },
+            CacheMode::Distance => GlyphKey {
+                face: face,
+                id: glyph.id().0,
+                scale: 0,
+                offset: (0, 0),
+            },
+        };
+
+        let cached = self.entries.get(&key).cloned();
+        let entry = match cached {
+            Some(e) => {
+                self.stats.hits += 1;
+                e
+            },
+            None => {
+                self.stats.misses += 1;
+                match self.insert(stack, key) {
+                    Ok(e) => {
+                        self.entries.insert(key, e);
+                        e
+                    },
+                    Err(()) => {
+                        self.stats.dropped += 1;
+                        return None;
+                    }
+                }
+            }
+        };
+
+        entry.map(|e| {
+            self.packer.touch(e.y, self.frame);
+            let (origin, k) = match self.mode {
+                CacheMode::Coverage => (point(pos.x.floor(), pos.y.floor()), 1.0),
+                CacheMode::Distance => (pos, scale / SDF_BASE_SCALE),
+            };
+            (CachedRect { x: e.x, y: e.y, w: e.w, h: e.h }, Rect {
+                min: point(origin.x + e.bounds.min.x * k, origin.y + e.bounds.min.y * k),
+                max: point(origin.x + e.bounds.max.x * k, origin.y + e.bounds.max.y * k),
+            })
+        })
+    }
+
+    // Err if there's no room for it, even after growing and evicting
+    fn insert(&mut self, stack: &FontStack, key: GlyphKey) -> Result<Option<Entry>, ()> {
+        let (w, h, data, bounds) = match self.rasterize(stack, key) {
+            Some(r) => r,
+            None => return Ok(None)
+        };
+        let (x, y) = try!(self.make_room(w, h));
+
+        for row in 0..h {
+            let dst = ((y + row) * self.packer.size().0 + x) as usize;
+            let src = (row * w) as usize;
+            self.pixels[dst..dst + w as usize].copy_from_slice(&data[src..src + w as usize]);
+        }
+        self.texture.main_level().write(::glium::Rect {
+            left: x,
+            bottom: y,
+            width: w,
+            height: h
+        }, ::glium::texture::RawImage2d {
+            data: Cow::Owned(data),
+            width: w,
+            height: h,
+            format: ::glium::texture::ClientFormat::U8
+        });
+        Ok(Some(Entry {
+            x: x,
+            y: y,
+            w: w,
+            h: h,
+            bounds: bounds,
+        }))
+    }
+
+    // Growing comes first. Once we're as big as we're allowed to get, we evict.
+    fn make_room(&mut self, w: u32, h: u32) -> Result<(u32, u32), ()> {
+        // Too big to ever fit, so don't throw everything out finding that out
+        let (cw, ch) = self.packer.size();
+        if w > ::std::cmp::max(cw, self.max_size) || h > ::std::cmp::max(ch, self.max_size) {
+            return Err(());
+        }
+        loop {
+            if let Some(spot) = self.packer.insert(w, h) {
+                return Ok(spot);
+            }
+            if self.grow() { continue }
+            match self.packer.evict(h, self.frame) {
+                Some(y) => {
+                    let before = self.entries.len();
+                    self.entries.retain(|_, e| match *e {
+                        Some(e) => e.y != y,
+                        None => true
+                    });
+                    self.stats.evictions += (before - self.entries.len()) as u64;
+                },
+                None => return Err(())
+            }
+        }
+    }
+
+    fn grow(&mut self) -> bool {
+        let (w, h) = self.packer.size();
+        let (nw, nh) = (::std::cmp::min(w * 2, self.max_size), ::std::cmp::min(h * 2, self.max_size));
+        // A cache that started out bigger than the max only ever gets to evict
+        if nw < w || nh < h || (nw, nh) == (w, h) { return false }
+
+        let mut pixels = vec![0u8; nw as usize * nh as usize];
+        for row in 0..h as usize {
+            pixels[row * nw as usize..row * nw as usize + w as usize].copy_from_slice(&self.pixels[row * w as usize..(row + 1) * w as usize]);
+        }
+        self.texture = make_texture(&self.context, &pixels, (nw, nh));
+        self.pixels = pixels;
+        self.packer.resize(nw, nh);
+        self.stats.grows += 1;
+        true
+    }
+
+    // Returns the glyph's size in texels, its texels (rows top to bottom), and its bounds
+    fn rasterize(&self, stack: &FontStack, key: GlyphKey) -> Option<(u32, u32, Vec<u8>, Rect<f32>)> {
+        let (scale, offset, pad) = match self.mode {
+            CacheMode::Coverage => (key.scale as f32 / 4.0, point(key.offset.0 as f32 / 4.0, key.offset.1 as f32 / 4.0), 1),
+            CacheMode::Distance => (SDF_BASE_SCALE, point(0.0, 0.0), SDF_SPREAD as u32),
+        };
+        let glyph = match stack.face(key.face).glyph(rusttype::GlyphId(key.id)) {
+            Some(g) => g.scaled(Scale::uniform(scale)).positioned(offset),
+            None => return None
+        };
+        let bb = match glyph.pixel_bounding_box() {
+            Some(bb) => bb,
+            None => return None
+        };
+        let (w, h) = (bb.width() as u32 + pad * 2, bb.height() as u32 + pad * 2);
+
+        let mut coverage = vec![0.0f32; (w * h) as usize];
+        glyph.draw(|gx, gy, v| {
+            coverage[((gy + pad) * w + gx + pad) as usize] = v;
+        });
+        let data = match self.mode {
+            CacheMode::Coverage => coverage.iter().map(|v| (v.max(0.0).min(1.0) * 255.0) as u8).collect(),
+            CacheMode::Distance => distance_field(&coverage, w as usize, h as usize, SDF_SPREAD),
+        };
+        let pad = pad as f32;
+        Some((w, h, data, Rect {
+            min: point(bb.min.x as f32 - pad, bb.min.y as f32 - pad),
+            max: point(bb.max.x as f32 + pad, bb.max.y as f32 + pad),
+        }))
+    }
+}
+
+fn make_texture(context: &Rc<Context>, pixels: &[u8], size: (u32, u32)) -> Texture2d {
+    Texture2d::with_format(
+        context,
+        ::glium::texture::RawImage2d {
+            data: Cow::Borrowed(pixels),
+            width: size.0,
+            height: size.1,
+            format: ::glium::texture::ClientFormat::U8
+        },
+        ::glium::texture::UncompressedFloatFormat::U8,
+        ::glium::texture::MipmapsOption::NoMipmap).unwrap()
+}
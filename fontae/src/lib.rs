@@ -3,13 +3,22 @@ extern crate cgmath;
 extern crate glium;
 extern crate rusttype;
 extern crate unicode_normalization;
+extern crate image;
+
+mod bitmap;
+mod sdf;
+mod cache;
+pub use bitmap::{BitmapFont, BitmapGlyph, BitmapQuad, BitmapError, BmFontDescriptor};
+pub use sdf::{SdfEffects, distance_field, SDF_BASE_SCALE, SDF_SPREAD};
+pub use cache::{GlyphCache, CacheMode, CacheStats, CachedRect, ShelfPacker};
 
 use std::convert::AsRef;
-use std::borrow::Cow;
 use std::path::Path;
+use std::cmp;
 use glium::backend::{Facade, Context};
 use std::rc::Rc;
-use glium::Texture2d;
+use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+use glium::{Texture2d, Program, Surface};
 
 ///! This crate is for the production of a nice pure-Rust font type for glium
 // Allotta code stolen from glium_text
@@ -24,43 +33,347 @@ fn get_nearest_po2(mut x: u32) -> u32 {
     x + 1
 }
 
+// Reads a single font face out of a file
+fn load_face<S: AsRef<Path>>(s: S) -> rusttype::Font<'static> {
+    use std::fs::File;
+    use std::io::Read;
+
+    let mut file = File::open(s.as_ref()).expect(&format!("Failed to open: {}", s.as_ref().display()));
+    let mut contents = vec![];
+    file.read_to_end(&mut contents).unwrap();
+    rusttype::FontCollection::from_bytes(contents).into_font().expect("Font file is not of single font")
+}
+
+// A primary face plus an ordered list of fallbacks (ex. a CJK font, then a symbol font).
+// A face's index in the stack is part of every glyph's cache key, which is how
+// every face gets to share the same cache texture.
+pub struct FontStack<'a> {
+    faces: Vec<rusttype::Font<'a>>,
+}
+
+impl<'a> FontStack<'a> {
+    pub fn new(primary: rusttype::Font<'a>) -> FontStack<'a> {
+        FontStack {
+            faces: vec![primary],
+        }
+    }
+
+    pub fn push(&mut self, fallback: rusttype::Font<'a>) {
+        self.faces.push(fallback);
+    }
+
+    pub fn len(&self) -> usize {
+        self.faces.len()
+    }
+
+    pub fn face(&self, i: usize) -> &rusttype::Font<'a> {
+        &self.faces[i]
+    }
 
-struct Font<'a> {
+    // Finds the first face that actually has a glyph for c. Faces hand back glyph 0 (.notdef)
+    // for characters they don't have, so those don't count.
+    // If nobody has it, we use the primary face's .notdef glyph, so that missing
+    // characters at least show up as a box instead of silently vanishing.
+    pub fn glyph_for(&self, c: char) -> Option<(usize, rusttype::Glyph)> {
+        for (i, face) in self.faces.iter().enumerate() {
+            match face.glyph(c) {
+                Some(g) if g.id() != rusttype::GlyphId(0) => return Some((i, g)),
+                _ => ()
+            }
+        }
+        self.faces[0].glyph(rusttype::GlyphId(0)).map(|g| (0, g))
+    }
+
+    // Vertical metrics always come from the primary face, so every face in a line
+    // sits on the same baseline.
+    pub fn v_metrics(&self, scale: rusttype::Scale) -> rusttype::VMetrics {
+        self.faces[0].v_metrics(scale)
+    }
+
+    // Kerning only makes sense between two glyphs of the same face
+    pub fn pair_kerning(&self, scale: rusttype::Scale, first: (usize, rusttype::GlyphId), second: (usize, rusttype::GlyphId)) -> f32 {
+        if first.0 == second.0 {
+            self.faces[first.0].pair_kerning(scale, first.1, second.1)
+        } else {
+            0.0
+        }
+    }
+}
+
+// Every Font gets an id, so a Text's cache can tell when it's being drawn with a different one
+static NEXT_FONT_ID: AtomicUsize = ATOMIC_USIZE_INIT;
+
+// How big glyph caches get before they start evicting
+pub const DEFAULT_MAX_CACHE_SIZE: u32 = 2048;
+
+pub struct Font<'a> {
     context: Rc<Context>,
-    font: rusttype::Font<'a>,
-    // TODO move cache to Text object, rather than Font
-    cache: rusttype::gpu_cache::Cache,
-    cache_texture: Texture2d
-} // Store information and the font texture
+    id: usize,
+    kind: FontKind<'a>,
+    program: Program,
+    cache_size: (u32, u32), // What new glyph caches start at
+    max_cache_size: u32,
+} // Store information about the font. The glyph caches live in each Text.
 
-impl<'a> Font<'a> {
-    pub fn new<F: Facade, S: AsRef<Path>>(f: F, s: S, cs: (u32, u32)) -> Font<'a> {
-        use std::fs::File;
-        use std::io::Read;
-
-        let mut file = File::open(s.as_ref()).expect(&format!("Failed to open: {}", s.as_ref().display()));
-        let mut contents = vec![];
-        file.read_to_end(&mut contents).unwrap();
-        let font = rusttype::FontCollection::from_bytes(contents).into_font().expect("Font file is not of single font");
-        let (tw, th) = (get_nearest_po2(cs.0), get_nearest_po2(cs.1));
+// Callers don't get to care which of these they have
+enum FontKind<'a> {
+    Outline(FontStack<'a>),
+    // Distance field glyphs, for text that gets zoomed or scaled
+    Distance {
+        stack: FontStack<'a>,
+        effects: SdfEffects,
+    },
+    Bitmap(BitmapFont),
+}
+
+impl Font<'static> {
+    // cs is the size glyph caches start out at. They grow from there as needed.
+    pub fn new<F: Facade, S: AsRef<Path>>(f: &F, s: S, cs: (u32, u32)) -> Font<'static> {
+        let program = Program::from_source(f, VERTEX_SHADER, FRAGMENT_SHADER, None).unwrap();
+        Font::with_kind(f, FontKind::Outline(FontStack::new(load_face(s))), program, cs)
+    }
+
+    // Like new, but glyphs are cached as distance fields, and get drawn crisply at any scale.
+    // See set_effects for outlines and such.
+    pub fn new_sdf<F: Facade, S: AsRef<Path>>(f: &F, s: S, cs: (u32, u32)) -> Font<'static> {
+        let program = Program::from_source(f, VERTEX_SHADER, sdf::SDF_FRAGMENT_SHADER, None).unwrap();
+        Font::with_kind(f, FontKind::Distance {
+            stack: FontStack::new(load_face(s)),
+            effects: SdfEffects::default(),
+        }, program, cs)
+    }
+
+    // Loads an AngelCode BMFont descriptor (text or binary) and its pages
+    pub fn from_bmfont<F: Facade, S: AsRef<Path>>(f: &F, s: S) -> Result<Font<'static>, BitmapError> {
+        Ok(Font::from_bitmap(f, try!(BitmapFont::load(f, s))))
+    }
+
+    // Loads a pixel font laid out on a fixed grid. See BmFontDescriptor::grid
+    pub fn from_grid<F: Facade, S: AsRef<Path>>(f: &F, s: S, cell: (u32, u32), chars: &str) -> Result<Font<'static>, BitmapError> {
+        Ok(Font::from_bitmap(f, try!(BitmapFont::load_grid(f, s, cell, chars))))
+    }
+
+    // Bitmap fonts draw straight from their pages, and don't use glyph caches
+    pub fn from_bitmap<F: Facade>(f: &F, bitmap: BitmapFont) -> Font<'static> {
+        let program = Program::from_source(f, VERTEX_SHADER, BITMAP_FRAGMENT_SHADER, None).unwrap();
+        Font::with_kind(f, FontKind::Bitmap(bitmap), program, (1, 1))
+    }
+
+    fn with_kind<F: Facade>(f: &F, kind: FontKind<'static>, program: Program, cs: (u32, u32)) -> Font<'static> {
         Font {
             context: f.get_context().clone(),
-            font: font,
-            cache: rusttype::gpu_cache::Cache::new(tw, th, 0.1, 0.1),
-            cache_texture: glium::texture::Texture2d::with_format(
-                f.get_context(),
-                glium::texture::RawImage2d {
-                    data: Cow::Owned(vec![128u8; tw as usize * th as usize]),
-                    width: tw,
-                    height: th,
-                    format: glium::texture::ClientFormat::U8
-                },
-                glium::texture::UncompressedFloatFormat::U8,
-                glium::texture::MipmapsOption::NoMipmap).unwrap()
+            id: NEXT_FONT_ID.fetch_add(1, Ordering::SeqCst),
+            kind: kind,
+            program: program,
+            cache_size: (cmp::min(get_nearest_po2(cs.0), DEFAULT_MAX_CACHE_SIZE), cmp::min(get_nearest_po2(cs.1), DEFAULT_MAX_CACHE_SIZE)),
+            max_cache_size: DEFAULT_MAX_CACHE_SIZE,
+        }
+    }
+
+    // Fallbacks are searched in the order they are added.
+    // Bitmap fonts don't have fallbacks; they draw '?' instead.
+    pub fn add_fallback<S: AsRef<Path>>(&mut self, s: S) {
+        match self.kind {
+            FontKind::Outline(ref mut stack) => stack.push(load_face(s)),
+            FontKind::Distance { ref mut stack, .. } => stack.push(load_face(s)),
+            FontKind::Bitmap(_) => panic!("Bitmap fonts can't have fallbacks: {}", s.as_ref().display())
+        }
+    }
+
+    pub fn with_fallback<S: AsRef<Path>>(mut self, s: S) -> Font<'static> {
+        self.add_fallback(s);
+        self
+    }
+}
+
+impl<'a> Font<'a> {
+    pub fn stack(&self) -> Option<&FontStack<'a>> {
+        match self.kind {
+            FontKind::Outline(ref stack) => Some(stack),
+            FontKind::Distance { ref stack, .. } => Some(stack),
+            FontKind::Bitmap(_) => None
+        }
+    }
+
+    pub fn is_bitmap(&self) -> bool {
+        match self.kind {
+            FontKind::Bitmap(_) => true,
+            _ => false
+        }
+    }
+
+    pub fn is_sdf(&self) -> bool {
+        match self.kind {
+            FontKind::Distance { .. } => true,
+            _ => false
+        }
+    }
+
+    // Only distance field fonts can do effects; everyone else ignores them
+    pub fn set_effects(&mut self, e: SdfEffects) {
+        if let FontKind::Distance { ref mut effects, .. } = self.kind {
+            *effects = e;
+        }
+    }
+
+    // Caches stop growing at this size (rounded up to a power of two), and evict instead
+    pub fn set_max_cache_size(&mut self, size: u32) {
+        self.max_cache_size = get_nearest_po2(size);
+        self.cache_size = (cmp::min(self.cache_size.0, self.max_cache_size), cmp::min(self.cache_size.1, self.max_cache_size));
+    }
+
+    // How much room text takes up when drawn, in pixels. Handy for aligning it.
+    pub fn measure<L: Layout>(&self, text: &L, scale: f32, width: u32) -> (f32, f32) {
+        match self.kind {
+            FontKind::Outline(ref stack) | FontKind::Distance { ref stack, .. } => {
+                let glyphs = text.layout_text(stack, rusttype::Scale::uniform(scale), width);
+                if glyphs.is_empty() { return (0.0, 0.0) }
+                let v_metrics = stack.v_metrics(rusttype::Scale::uniform(scale));
+                glyphs.iter().fold((0.0f32, 0.0f32), |(w, h), g| {
+                    let p = g.glyph.position();
+                    (w.max(p.x + g.glyph.unpositioned().h_metrics().advance_width), h.max(p.y - v_metrics.descent))
+                })
+            },
+            FontKind::Bitmap(ref bitmap) => {
+                text.layout_bitmap(bitmap, scale, width).iter().fold((0.0f32, 0.0f32), |(w, h), q| {
+                    (w.max(q.screen.max.x), h.max(q.screen.max.y))
+                })
+            }
+        }
+    }
+
+    // Gets text a cache that fits this font, making a new one if it was drawn with another font before
+    fn cache_for<'t, L: Layout>(&self, text: &'t mut Text<L>, mode: CacheMode) -> &'t mut GlyphCache {
+        let stale = match text.cache {
+            Some(ref c) => c.font_id() != self.id || c.mode() != mode,
+            None => true
+        };
+        if stale {
+            text.cache = Some(GlyphCache::new(&self.context, mode, self.id, self.cache_size, self.max_cache_size));
+        }
+        text.cache.as_mut().unwrap()
+    }
+
+    // Lays out text, caches whatever glyphs are missing, and draws it.
+    // The text is built in pixels with its top left corner on the origin, and y going up
+    // (so the lines go into negative y); matrix is responsible for putting it somewhere useful.
+    pub fn draw<S: Surface, L: Layout>(&self, surface: &mut S, text: &mut Text<L>, scale: f32, width: u32, matrix: [[f32; 4]; 4]) {
+        self.draw_in_viewport(surface, text, scale, width, matrix, None)
+    }
+
+    // Like draw, but only onto part of the surface (ex. one side of a split screen).
+    // matrix still maps to the whole of -1 to 1, which gets squeezed into viewport.
+    pub fn draw_in_viewport<S: Surface, L: Layout>(&self, surface: &mut S, text: &mut Text<L>, scale: f32, width: u32, matrix: [[f32; 4]; 4], viewport: Option<glium::Rect>) {
+        use glium::uniforms::{MagnifySamplerFilter, MinifySamplerFilter};
+
+        let color = text.content.color();
+        match self.kind {
+            FontKind::Outline(ref stack) => {
+                let glyphs = text.content.layout_text(stack, rusttype::Scale::uniform(scale), width);
+                let cache = self.cache_for(text, CacheMode::Coverage);
+                let vertices = cached_quads(cache, stack, &glyphs, scale, color);
+                draw_quads(&self.context, surface, &self.program, cache.texture(), MagnifySamplerFilter::Nearest, &vertices, matrix, viewport);
+            },
+            FontKind::Distance { ref stack, ref effects } => {
+                let glyphs = text.content.layout_text(stack, rusttype::Scale::uniform(scale), width);
+                let cache = self.cache_for(text, CacheMode::Distance);
+                let vertices = cached_quads(cache, stack, &glyphs, scale, color);
+                if vertices.is_empty() { return }
+
+                let tex = cache.texture();
+                let vb = glium::VertexBuffer::new(&self.context, &vertices).unwrap();
+                let uniforms = uniform! {
+                    matrix: matrix,
+                    tex: tex.sampled()
+                        .magnify_filter(MagnifySamplerFilter::Linear)
+                        .minify_filter(MinifySamplerFilter::Linear),
+                    texel: [1.0 / tex.get_width() as f32, 1.0 / tex.get_height().unwrap() as f32],
+                    spread: SDF_SPREAD as f32,
+                    outline_width: effects.outline_width,
+                    outline_color: effects.outline_color,
+                    glow_width: effects.glow_width,
+                    glow_color: effects.glow_color,
+                    shadow_offset: effects.shadow_offset,
+                    shadow_color: effects.shadow_color
+                };
+                let params = glium::DrawParameters {
+                    blend: glium::Blend::alpha_blending(),
+                    viewport: viewport,
+                    ..Default::default()
+                };
+                surface.draw(&vb, glium::index::NoIndices(glium::index::PrimitiveType::TrianglesList), &self.program, &uniforms, &params).unwrap();
+            },
+            FontKind::Bitmap(ref bitmap) => {
+                // One draw per page
+                let quads = text.content.layout_bitmap(bitmap, scale, width);
+                let mut pages: Vec<Vec<Vertex>> = (0..bitmap.descriptor().pages.len()).map(|_| vec![]).collect();
+                for q in quads.into_iter() {
+                    if let Some(page) = pages.get_mut(q.page) {
+                        page.extend_from_slice(&glyph_quad(q.uv, q.screen, color));
+                    }
+                }
+                for (i, vertices) in pages.iter().enumerate() {
+                    draw_quads(&self.context, surface, &self.program, bitmap.page(i), MagnifySamplerFilter::Nearest, vertices, matrix, viewport);
+                }
+            }
         }
     }
 }
 
+// Something to draw, and the glyph cache to draw it with
+pub struct Text<L: Layout> {
+    pub content: L,
+    cache: Option<GlyphCache>, // Made on the first draw
+}
+
+impl<L: Layout> Text<L> {
+    pub fn new(content: L) -> Text<L> {
+        Text {
+            content: content,
+            cache: None,
+        }
+    }
+
+    // None until the text has been drawn with a font that uses caches
+    pub fn cache_stats(&self) -> Option<CacheStats> {
+        self.cache.as_ref().map(|c| c.stats())
+    }
+
+    // Throws away the cache, ex. when a dialogue box is done with a scene
+    pub fn drop_cache(&mut self) {
+        self.cache = None;
+    }
+}
+
+// Looks every glyph up in the cache, and turns them into vertices
+fn cached_quads(cache: &mut GlyphCache, stack: &FontStack, glyphs: &[FacedGlyph], scale: f32, color: [f32; 4]) -> Vec<Vertex> {
+    cache.begin_frame();
+    let located: Vec<_> = glyphs.iter().filter_map(|g| cache.locate(stack, g.face, &g.glyph, scale)).collect();
+    let mut vertices = Vec::with_capacity(located.len() * 6);
+    // Texture coordinates only once everything's in, since the cache may have grown since
+    for (rect, screen) in located.into_iter() {
+        vertices.extend_from_slice(&glyph_quad(cache.uv(rect), screen, color));
+    }
+    vertices
+}
+
+fn draw_quads<S: Surface>(context: &Rc<Context>, surface: &mut S, program: &Program, tex: &Texture2d, filter: glium::uniforms::MagnifySamplerFilter, vertices: &[Vertex], matrix: [[f32; 4]; 4], viewport: Option<glium::Rect>) {
+    use glium::{VertexBuffer, index};
+
+    if vertices.is_empty() { return }
+    let vb = VertexBuffer::new(context, vertices).unwrap();
+    let uniforms = uniform! {
+        matrix: matrix,
+        tex: tex.sampled().magnify_filter(filter)
+    };
+    let params = glium::DrawParameters {
+        blend: glium::Blend::alpha_blending(),
+        viewport: viewport,
+        ..Default::default()
+    };
+    surface.draw(&vb, index::NoIndices(index::PrimitiveType::TrianglesList), program, &uniforms, &params).unwrap();
+}
+
 #[derive(Clone, Copy, Debug)]
 struct Vertex {
     position: [f32; 2],
@@ -70,20 +383,151 @@ struct Vertex {
 
 implement_vertex!(Vertex, position, tex_coords, color);
 
+// Two triangles covering a glyph. Layout rects have y going down, ours goes up.
+fn glyph_quad(uv: rusttype::Rect<f32>, screen: rusttype::Rect<f32>, color: [f32; 4]) -> [Vertex; 6] {
+    let (left, right) = (screen.min.x, screen.max.x);
+    let (top, bottom) = (-screen.min.y, -screen.max.y);
+    let tl = Vertex { position: [left, top], tex_coords: [uv.min.x, uv.min.y], color: color };
+    let tr = Vertex { position: [right, top], tex_coords: [uv.max.x, uv.min.y], color: color };
+    let bl = Vertex { position: [left, bottom], tex_coords: [uv.min.x, uv.max.y], color: color };
+    let br = Vertex { position: [right, bottom], tex_coords: [uv.max.x, uv.max.y], color: color };
+    [tl, tr, br, tl, br, bl]
+}
+
+// A glyph, along with which face in the stack it came from
+pub struct FacedGlyph<'a> {
+    pub face: usize,
+    pub glyph: rusttype::PositionedGlyph<'a>,
+}
+
 // TODO Make laying out text a bit more modular.
-trait Layout {
+pub trait Layout {
     // Method ripped straight from gpu_cache example
-    fn layout_text<'a>(font: &'a Font, scale: rusttype::Scale, width: u32, text: &str) -> Vec<rusttype::PositionedGlyph<'a>>;
+    fn layout_text<'a>(&self, stack: &'a FontStack, scale: rusttype::Scale, width: u32) -> Vec<FacedGlyph<'a>>;
+    // Bitmap fonts are scaled in whole multiples of their native size, see BitmapFont::pixel_scale
+    fn layout_bitmap(&self, font: &BitmapFont, scale: f32, width: u32) -> Vec<BitmapQuad>;
+    fn color(&self) -> [f32; 4];
 }
 
-struct SimpleText {
-    color: [f32; 4], // Color of text
-    text: String, // Text to layout
+pub struct SimpleText {
+    pub color: [f32; 4], // Color of text
+    pub text: String, // Text to layout
 
 } // Holds the actual text we want to draw, and various settings we want to associate with the text
 // Lays out the text directly
 
+impl SimpleText {
+    pub fn new<S: Into<String>>(text: S, color: [f32; 4]) -> SimpleText {
+        SimpleText {
+            color: color,
+            text: text.into(),
+        }
+    }
+}
+
+impl Layout for SimpleText {
+    fn layout_text<'a>(&self, stack: &'a FontStack, scale: rusttype::Scale, width: u32) -> Vec<FacedGlyph<'a>> {
+        use unicode_normalization::UnicodeNormalization;
+        use rusttype::point;
+
+        let mut result = Vec::new();
+        let v_metrics = stack.v_metrics(scale);
+        let advance_height = v_metrics.ascent - v_metrics.descent + v_metrics.line_gap;
+        let mut caret = point(0.0, v_metrics.ascent);
+        let mut last_glyph = None;
+        for c in self.text.nfc() {
+            if c.is_control() {
+                if c == '\n' {
+                    caret = point(0.0, caret.y + advance_height);
+                    last_glyph = None;
+                }
+                continue;
+            }
+            let (face, base_glyph) = match stack.glyph_for(c) {
+                Some(g) => g,
+                None => continue
+            };
+            if let Some(last) = last_glyph.take() {
+                caret.x += stack.pair_kerning(scale, last, (face, base_glyph.id()));
+            }
+            last_glyph = Some((face, base_glyph.id()));
+            let mut glyph = base_glyph.scaled(scale).positioned(caret);
+            if let Some(bb) = glyph.pixel_bounding_box() {
+                if bb.max.x > width as i32 {
+                    caret = point(0.0, caret.y + advance_height);
+                    glyph = glyph.into_unpositioned().positioned(caret);
+                    last_glyph = None;
+                }
+            }
+            caret.x += glyph.unpositioned().h_metrics().advance_width;
+            result.push(FacedGlyph {
+                face: face,
+                glyph: glyph,
+            });
+        }
+        result
+    }
+
+    fn layout_bitmap(&self, font: &BitmapFont, scale: f32, width: u32) -> Vec<BitmapQuad> {
+        font.layout(&self.text, scale, width)
+    }
+
+    fn color(&self) -> [f32; 4] {
+        self.color
+    }
+}
+
 // Notice we DON'T provide a Program to draw it. We simply will tell you how we produce everything, and
 // it's your job to implement a proper shader to draw it. Sorry, but we don't want to require anything
 // that needs a Facade, or Context
 // NOTE Changed. We will provide a Program, and we WILL require a Context
+const VERTEX_SHADER: &'static str = r#"
+#version 140
+
+uniform mat4 matrix;
+
+in vec2 position;
+in vec2 tex_coords;
+in vec4 color;
+
+out vec2 v_tex_coords;
+out vec4 v_color;
+
+void main() {
+  gl_Position = matrix * vec4(position, 0.0, 1.0);
+  v_tex_coords = tex_coords;
+  v_color = color;
+}
+"#;
+
+// The cache texture only stores coverage, in the red channel
+const FRAGMENT_SHADER: &'static str = r#"
+#version 140
+
+uniform sampler2D tex;
+
+in vec2 v_tex_coords;
+in vec4 v_color;
+
+out vec4 f_color;
+
+void main() {
+  f_color = v_color * vec4(1.0, 1.0, 1.0, texture(tex, v_tex_coords).r);
+}
+"#;
+
+// Bitmap pages are plain RGBA images
+const BITMAP_FRAGMENT_SHADER: &'static str = r#"
+#version 140
+
+uniform sampler2D tex;
+
+in vec2 v_tex_coords;
+in vec4 v_color;
+
+out vec4 f_color;
+
+void main() {
+  f_color = v_color * texture(tex, v_tex_coords);
+}
+"#;
@@ -0,0 +1,111 @@
+///! Signed distance field glyphs, for text that stays crisp at any scale
+// Glyphs are rasterized once at this size, and scaled by the shader from there on
+pub const SDF_BASE_SCALE: f32 = 48.0;
+// How far (in pixels at the base size) the field reaches past a glyph's edge.
+// This also caps how wide outlines, glows and shadow offsets can get.
+pub const SDF_SPREAD: usize = 6;
+
+// Turns a coverage bitmap into a distance field. 0.5 is the edge, with inside going up to 1.0
+// and outside down to 0.0, both reaching their limit at spread pixels away.
+// Brute force, but glyphs are small and we only do this once per glyph.
+pub fn distance_field(coverage: &[f32], w: usize, h: usize, spread: usize) -> Vec<u8> {
+    let inside = |x: isize, y: isize| -> bool {
+        x >= 0 && y >= 0 && (x as usize) < w && (y as usize) < h && coverage[y as usize * w + x as usize] >= 0.5
+    };
+    let s = spread as isize;
+    let mut field = Vec::with_capacity(w * h);
+    for y in 0..h as isize {
+        for x in 0..w as isize {
+            let me = inside(x, y);
+            let mut best = spread as f32;
+            for dy in -s..s + 1 {
+                for dx in -s..s + 1 {
+                    if inside(x + dx, y + dy) != me {
+                        let d = ((dx * dx + dy * dy) as f32).sqrt();
+                        if d < best { best = d }
+                    }
+                }
+            }
+            let signed = if me { best } else { -best };
+            let v = 0.5 + signed / (2.0 * spread as f32);
+            field.push((v.max(0.0).min(1.0) * 255.0) as u8);
+        }
+    }
+    field
+}
+
+// Shader effects for distance field text.
+// Widths and offsets are in pixels at SDF_BASE_SCALE, and max out at SDF_SPREAD.
+// A width of 0.0 (or a transparent color) turns an effect off.
+#[derive(Clone, Copy, Debug)]
+pub struct SdfEffects {
+    pub outline_width: f32,
+    pub outline_color: [f32; 4],
+    pub glow_width: f32,
+    pub glow_color: [f32; 4],
+    pub shadow_offset: [f32; 2], // x right, y down
+    pub shadow_color: [f32; 4],
+}
+
+impl Default for SdfEffects {
+    fn default() -> SdfEffects {
+        SdfEffects {
+            outline_width: 0.0,
+            outline_color: [0.0, 0.0, 0.0, 1.0],
+            glow_width: 0.0,
+            glow_color: [1.0, 1.0, 1.0, 0.0],
+            shadow_offset: [0.0, 0.0],
+            shadow_color: [0.0, 0.0, 0.0, 0.0],
+        }
+    }
+}
+
+// Smoothing comes from fwidth, so edges stay one screen pixel soft no matter the zoom.
+// Everything that isn't the fill is composited underneath it.
+pub const SDF_FRAGMENT_SHADER: &'static str = r#"
+#version 140
+
+uniform sampler2D tex;
+uniform vec2 texel; // Size of one atlas texel in uv
+uniform float spread;
+uniform float outline_width;
+uniform vec4 outline_color;
+uniform float glow_width;
+uniform vec4 glow_color;
+uniform vec2 shadow_offset;
+uniform vec4 shadow_color;
+
+in vec2 v_tex_coords;
+in vec4 v_color;
+
+out vec4 f_color;
+
+vec4 over(vec4 top, vec4 bottom) {
+  float a = top.a + bottom.a * (1.0 - top.a);
+  if (a <= 0.0) return vec4(0.0);
+  return vec4((top.rgb * top.a + bottom.rgb * bottom.a * (1.0 - top.a)) / a, a);
+}
+
+void main() {
+  float dist = texture(tex, v_tex_coords).r;
+  float smoothing = 0.7 * fwidth(dist);
+  // Pixel widths to distance units
+  float outline = outline_width / (2.0 * spread);
+  float glow = glow_width / (2.0 * spread);
+
+  float fill = smoothstep(0.5 - smoothing, 0.5 + smoothing, dist);
+  float edge = 0.5 - outline;
+  float outlined = smoothstep(edge - smoothing, edge + smoothing, dist);
+  vec4 body = vec4(outline_color.rgb, outline_color.a * outlined * step(0.0001, outline));
+  body = over(vec4(v_color.rgb, v_color.a * fill), body);
+
+  float glowing = smoothstep(edge - glow, edge, dist) * step(0.0001, glow);
+  vec4 under = vec4(glow_color.rgb, glow_color.a * glowing);
+
+  float shadow_dist = texture(tex, v_tex_coords - shadow_offset * texel).r;
+  float shadowed = smoothstep(edge - smoothing, edge + smoothing, shadow_dist);
+  under = over(under, vec4(shadow_color.rgb, shadow_color.a * shadowed));
+
+  f_color = over(body, under);
+}
+"#;
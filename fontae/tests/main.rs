@@ -1 +1,148 @@
+extern crate fontae;
+extern crate rusttype;
 
+use fontae::{BmFontDescriptor, BitmapGlyph, BitmapError};
+
+const TEXT_FNT: &'static str = r#"info face="Pixel Sans" size=8 bold=0 italic=0 charset="" unicode=1 stretchH=100 smooth=0 aa=1 padding=0,0,0,0 spacing=1,1
+common lineHeight=10 base=8 scaleW=64 scaleH=32 pages=1 packed=0
+page id=0 file="pixel sans_0.png"
+chars count=2
+char id=65   x=0     y=0     width=5     height=7     xoffset=0     yoffset=1     xadvance=6     page=0  chnl=15
+char id=86   x=6     y=0     width=5     height=7     xoffset=-1    yoffset=1     xadvance=5     page=0  chnl=15
+kernings count=1
+kerning first=65  second=86  amount=-1
+"#;
+
+#[test]
+fn parse_text_bmfont() {
+    let desc = BmFontDescriptor::parse(TEXT_FNT.as_bytes()).unwrap();
+    assert_eq!(desc.line_height, 10);
+    assert_eq!(desc.base, 8);
+    assert_eq!((desc.scale_w, desc.scale_h), (64, 32));
+    assert_eq!(desc.pages, vec!["pixel sans_0.png".to_string()]);
+    assert_eq!(desc.chars[&'V'], BitmapGlyph {
+        x: 6, y: 0, width: 5, height: 7, xoffset: -1, yoffset: 1, xadvance: 5, page: 0
+    });
+    assert_eq!(desc.kerning('A', 'V'), -1);
+    assert_eq!(desc.kerning('V', 'A'), 0);
+}
+
+#[test]
+fn parse_binary_bmfont() {
+    fn block(kind: u8, data: Vec<u8>) -> Vec<u8> {
+        let len = data.len() as u32;
+        let mut b = vec![kind, len as u8, (len >> 8) as u8, (len >> 16) as u8, (len >> 24) as u8];
+        b.extend(data);
+        b
+    }
+    let mut data = b"BMF\x03".to_vec();
+    data.extend(block(2, vec![10, 0, 8, 0, 64, 0, 32, 0, 1, 0, 0, 0, 0, 0, 0]));
+    data.extend(block(3, b"font_0.png\0".to_vec()));
+    data.extend(block(4, vec![
+        65, 0, 0, 0, // id
+        2, 0, 3, 0, 5, 0, 7, 0, // x y width height
+        0xff, 0xff, 1, 0, 6, 0, // xoffset (-1) yoffset xadvance
+        0, 15 // page chnl
+    ]));
+    data.extend(block(5, vec![65, 0, 0, 0, 65, 0, 0, 0, 0xfe, 0xff]));
+
+    let desc = BmFontDescriptor::parse(&data).unwrap();
+    assert_eq!(desc.line_height, 10);
+    assert_eq!(desc.pages, vec!["font_0.png".to_string()]);
+    assert_eq!(desc.chars[&'A'], BitmapGlyph {
+        x: 2, y: 3, width: 5, height: 7, xoffset: -1, yoffset: 1, xadvance: 6, page: 0
+    });
+    assert_eq!(desc.kerning('A', 'A'), -2);
+}
+
+#[test]
+fn reject_unknown_binary_version() {
+    match BmFontDescriptor::parse(b"BMF\x02") {
+        Err(BitmapError::Version(2)) => (),
+        other => panic!("expected a version error, got {:?}", other)
+    }
+}
+
+#[test]
+fn grid_font_cells() {
+    let desc = BmFontDescriptor::grid("grid.png", (32, 16), (8, 8), "ABCDEF");
+    assert_eq!(desc.line_height, 8);
+    assert_eq!(desc.chars[&'A'].x, 0);
+    assert_eq!(desc.chars[&'D'].x, 24);
+    assert_eq!((desc.chars[&'E'].x, desc.chars[&'E'].y), (0, 8));
+    assert_eq!(desc.chars[&'F'].xadvance, 8);
+}
+
+#[test]
+fn distance_field_edges() {
+    use fontae::distance_field;
+
+    // A 4x4 solid square in the middle of a 12x12 bitmap
+    let mut coverage = vec![0.0f32; 144];
+    for y in 4..8 {
+        for x in 4..8 {
+            coverage[y * 12 + x] = 1.0;
+        }
+    }
+    let field = distance_field(&coverage, 12, 12, 4);
+    assert!(field[5 * 12 + 5] > 128); // Inside
+    assert!(field[0] == 0); // Further out than the spread
+    assert!(field[5 * 12 + 3] < 128 && field[5 * 12 + 3] > field[5 * 12 + 1]); // Outside, falling off
+}
+
+#[test]
+fn shelf_packer_fills_rows() {
+    use fontae::ShelfPacker;
+
+    let mut packer = ShelfPacker::new(16, 16);
+    assert_eq!(packer.insert(8, 8), Some((0, 0)));
+    assert_eq!(packer.insert(8, 4), Some((8, 0)));
+    assert_eq!(packer.insert(8, 8), Some((0, 8)));
+    assert_eq!(packer.insert(16, 1), None);
+    assert_eq!(packer.insert(17, 1), None);
+}
+
+#[test]
+fn shelf_packer_evicts_least_recently_used() {
+    use fontae::ShelfPacker;
+
+    let mut packer = ShelfPacker::new(8, 8);
+    assert_eq!(packer.insert(8, 4), Some((0, 0)));
+    assert_eq!(packer.insert(8, 4), Some((0, 4)));
+    packer.touch(0, 2);
+    packer.touch(4, 1);
+    assert_eq!(packer.insert(8, 4), None);
+    // Frame 2 still needs the top shelf, so the bottom one goes
+    assert_eq!(packer.evict(4, 2), Some(4));
+    assert_eq!(packer.insert(8, 4), Some((0, 4)));
+    packer.touch(4, 2);
+    // Everything's in use this frame
+    assert_eq!(packer.evict(4, 2), None);
+    assert_eq!(packer.used_area(), 64);
+}
+
+// Gudea only covers Latin, and DejaVu Sans covers a lot more
+fn test_face(name: &str) -> rusttype::Font<'static> {
+    use std::fs::File;
+    use std::io::Read;
+
+    let mut bytes = vec![];
+    File::open(format!("tests/fonts/{}", name)).unwrap().read_to_end(&mut bytes).unwrap();
+    rusttype::FontCollection::from_bytes(bytes).into_font().unwrap()
+}
+
+#[test]
+fn font_stack_falls_back() {
+    use fontae::FontStack;
+    use rusttype::GlyphId;
+
+    let mut stack = FontStack::new(test_face("Gudea-Regular.ttf"));
+    stack.push(test_face("DejaVuSans.ttf"));
+    assert_eq!(stack.glyph_for('a').unwrap().0, 0);
+    let (face, glyph) = stack.glyph_for('Ж').unwrap();
+    assert_eq!(face, 1);
+    assert!(glyph.id() != GlyphId(0));
+    // Nobody has this one, so it's the primary face's .notdef
+    let (face, glyph) = stack.glyph_for('\u{10fffd}').unwrap();
+    assert_eq!((face, glyph.id()), (0, GlyphId(0)));
+}
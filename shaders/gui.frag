@@ -0,0 +1,13 @@
+#version 140
+// Untextured panels get a 1x1 white texture, so everything goes through here
+
+in vec4 v_color;
+in vec2 v_tex_coords;
+
+uniform sampler2D tex;
+
+out vec4 color;
+
+void main() {
+  color = v_color * texture(tex, v_tex_coords);
+}
@@ -0,0 +1,24 @@
+#version 330 core
+
+in vec2 position;
+in vec4 color;
+
+// Per instance
+in vec2 i_position;
+in vec2 i_pivot;
+in float i_rotation;
+in vec2 i_scale;
+in vec4 i_tint;
+
+out vec4 v_color;
+
+uniform mat4 vp;
+
+void main() {
+  // Same as graphics::pivot_matrix: scale and turn around the pivot, then put the pivot down
+  float a = radians(i_rotation);
+  vec2 p = (position - i_pivot) * i_scale;
+  p = vec2(p.x * cos(a) - p.y * sin(a), p.x * sin(a) + p.y * cos(a)) + i_position;
+  v_color = color * i_tint;
+  gl_Position = vp * vec4(p, 0, 1);
+}
@@ -0,0 +1,16 @@
+#version 330 core
+
+in vec2 position;
+in vec4 color;
+in vec2 tex_coords;
+
+out vec4 v_color;
+out vec2 v_tex_coords;
+
+uniform mat4 mvp;
+
+void main() {
+  v_tex_coords = tex_coords;
+  v_color = color;
+  gl_Position = mvp * vec4(position, 0, 1);
+}
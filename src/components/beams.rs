@@ -0,0 +1,175 @@
+///! Beams for the X/Y/Z attacks in Klay mode. Unlike bullets, a beam is one long thing stuck to whoever fires it,
+///! reaching out as far as it's grown so far and hurting everything along the way.
+///!
+///! A beam texture is three columns (start cap, body, end cap) and a row per animation frame, top to bottom.
+///! The caps stay their own size, and the body stretches to fill in between.
+use cgmath::{Point2, Vector2};
+use specs;
+use graphics::{Vertex, Index};
+use super::{Depth, RenderLayer};
+
+#[derive(Clone, Debug)]
+pub struct Beam {
+    pub direction: f32, // Degrees, counterclockwise from the right. Turns with the entity.
+    pub offset: Vector2<f32>, // Where it starts, from the entity's origin. Turns with the entity.
+    pub length: f32, // How far it reaches right now
+    pub max_length: f32,
+    pub extend_speed: f32, // How fast it grows while firing and shrinks after. 0 is all at once.
+    pub width: f32,
+    pub damage: f32, // A second, to everything it touches
+    pub firing: bool,
+    pub texture: Option<String>, // Under "data/textures". Plain colored without one.
+    pub color: [f32; 4],
+    pub cap: f32, // How long each cap is
+    pub frames: u32,
+    pub frame_time: f32, // Seconds
+    pub depth: Depth,
+    time: f32,
+}
+
+impl Beam {
+    pub fn new(max_length: f32, width: f32) -> Beam {
+        Beam {
+            direction: 90.0,
+            offset: Vector2::new(0.0, 0.0),
+            length: 0.0,
+            max_length: max_length,
+            extend_speed: 0.0,
+            width: width,
+            damage: 0.0,
+            firing: true,
+            texture: None,
+            color: [1.0; 4],
+            cap: width,
+            frames: 1,
+            frame_time: 0.1,
+            depth: Depth::new(RenderLayer::Bullets, 0.0),
+            time: 0.0,
+        }
+    }
+
+    pub fn textured<S: Into<String>>(mut self, texture: S, frames: u32, frame_time: f32) -> Beam {
+        self.texture = Some(texture.into());
+        self.frames = frames.max(1);
+        self.frame_time = frame_time;
+        self
+    }
+
+    // Grows it while it's firing, and shrinks it back once it stops
+    pub fn update(&mut self, dt: f32) {
+        self.time += dt;
+        let step = if self.extend_speed > 0.0 { self.extend_speed * dt } else { self.max_length };
+        self.length = if self.firing {
+            (self.length + step).min(self.max_length)
+        } else {
+            (self.length - step).max(0.0)
+        };
+    }
+
+    // Nothing to see or hit
+    pub fn is_out(&self) -> bool {
+        self.length > 0.0
+    }
+
+    // Start and end, in the world. origin and rotation are the entity's.
+    pub fn segment(&self, origin: Point2<f32>, rotation: f32) -> (Point2<f32>, Point2<f32>) {
+        let angle = (self.direction + rotation).to_radians();
+        let (sin, cos) = rotation.to_radians().sin_cos();
+        let start = origin + Vector2::new(self.offset.x * cos - self.offset.y * sin, self.offset.x * sin + self.offset.y * cos);
+        (start, start + Vector2::new(angle.cos(), angle.sin()) * self.length)
+    }
+
+    // The caps and body, in the world, ready for RenderInstruction::DrawWorld
+    pub fn mesh(&self, start: Point2<f32>, end: Point2<f32>) -> (Vec<Vertex>, Vec<Index>) {
+        let along = end - start;
+        let dir = if self.length > 0.0 { along / self.length } else { Vector2::new(1.0, 0.0) };
+        let side = Vector2::new(-dir.y, dir.x) * (self.width / 2.0);
+        let cap = self.cap.min(self.length / 2.0);
+
+        let frame = if self.frame_time > 0.0 { (self.time / self.frame_time) as u32 % self.frames } else { 0 };
+        let rows = self.frames as f32;
+        let (v0, v1) = (1.0 - (frame + 1) as f32 / rows, 1.0 - frame as f32 / rows);
+
+        let (mut verts, mut indices) = (Vec::with_capacity(12), Vec::with_capacity(18));
+        let pieces = [(start, start + dir * cap, 0.0), (start + dir * cap, end - dir * cap, 1.0 / 3.0), (end - dir * cap, end, 2.0 / 3.0)];
+        for &(from, to, u) in pieces.iter() {
+            let base = verts.len() as Index;
+            let u1 = u + 1.0 / 3.0;
+            verts.push(Vertex { position: [(from - side).x, (from - side).y], color: self.color, tex_coords: [u, v0] });
+            verts.push(Vertex { position: [(to - side).x, (to - side).y], color: self.color, tex_coords: [u1, v0] });
+            verts.push(Vertex { position: [(to + side).x, (to + side).y], color: self.color, tex_coords: [u1, v1] });
+            verts.push(Vertex { position: [(from + side).x, (from + side).y], color: self.color, tex_coords: [u, v1] });
+            indices.extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+        }
+        (verts, indices)
+    }
+}
+
+impl specs::Component for Beam {
+    type Storage = specs::HashMapStorage<Beam>;
+}
+
+#[derive(Clone, Copy, Debug)]
+// Something a beam touched this frame
+pub struct Hit {
+    pub beam: specs::Entity,
+    pub target: specs::Entity,
+    pub damage: f32, // Already scaled down to this frame
+    pub at: Point2<f32>, // The point on the beam nearest the target
+}
+
+#[derive(Clone, Debug, Default)]
+// A world resource. The BeamSystem fills it every frame, for combat to deal with.
+pub struct BeamHits(pub Vec<Hit>);
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Point2, Vector2};
+    use super::Beam;
+
+    #[test]
+    fn extends_and_retracts() {
+        let mut b = Beam::new(100.0, 8.0);
+        b.extend_speed = 400.0;
+        b.update(0.125);
+        assert_eq!(b.length, 50.0);
+        b.update(0.5);
+        assert_eq!(b.length, 100.0);
+        b.firing = false;
+        b.update(0.125);
+        assert_eq!(b.length, 50.0);
+        b.update(1.0);
+        assert!(!b.is_out());
+    }
+
+    #[test]
+    fn segment_turns() {
+        let mut b = Beam::new(10.0, 2.0);
+        b.direction = 0.0;
+        b.update(0.0);
+        let (start, end) = b.segment(Point2::new(1.0, 1.0), 90.0);
+        assert_eq!(start, Point2::new(1.0, 1.0));
+        assert!((end.x - 1.0).abs() < 0.001 && (end.y - 11.0).abs() < 0.001);
+
+        // The offset turns along with the beam
+        b.offset = Vector2::new(2.0, 0.0);
+        let (start, end) = b.segment(Point2::new(1.0, 1.0), 90.0);
+        assert!((start.x - 1.0).abs() < 0.001 && (start.y - 3.0).abs() < 0.001);
+        assert!((end.x - 1.0).abs() < 0.001 && (end.y - 13.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn caps_keep_their_size() {
+        let mut b = Beam::new(100.0, 10.0).textured("beam.png", 4, 0.1);
+        b.direction = 0.0;
+        b.update(0.15); // Second frame
+        let (start, end) = b.segment(Point2::new(0.0, 0.0), 0.0);
+        let (verts, indices) = b.mesh(start, end);
+        assert_eq!(indices.len(), 18);
+        // Start cap, then the stretched body
+        assert_eq!(verts[1].position, [10.0, -5.0]);
+        assert_eq!(verts[6].position, [90.0, 5.0]);
+        assert_eq!(verts[0].tex_coords, [0.0, 0.5]);
+        assert_eq!(verts[3].tex_coords, [0.0, 0.75]);
+    }
+}
@@ -0,0 +1,507 @@
+///! Bullet patterns for Klay mode. A BulletEmitter fires a pattern over and over, and the BulletSystem
+///! moves the bullets along and recycles them once they're gone.
+///!
+///! Patterns live in "data/bullets/<name>.toml", one table each:
+///!
+///! [flower]
+///! shape = "ring" # single, ring, spread, spiral or aimed
+///! count = 12 # Bullets per shot
+///! bullet = "orb" # What they look like, from "data/entities/<bullet>.ent.toml"
+///! direction = 90.0 # Degrees, counterclockwise from the right. Turns with the entity.
+///! rotate = 7.5 # Added to the direction after every shot
+///! delay = 0.5 # Seconds before the first shot
+///! interval = 0.25 # Seconds between shots
+///! repeat = 8 # Shots, then it's done. 0 keeps going forever.
+///! speed = 120.0
+///! acceleration = [[0.0, 0.0], [1.0, -100.0]] # Over the bullet's life, in seconds. Or just a number.
+///! angular = 30.0 # Degrees a second the bullet curves by. Same as acceleration.
+///! lifetime = 6.0
+///! child = "burst" # Each bullet starts firing this pattern...
+///! child_after = 1.0 # ...this long after it's fired
+///! split = true # and disappears once its child pattern is done
+///!
+///! Shapes take some extra settings:
+///!   spread  width = 60.0        How wide the fan is, in degrees
+///!   spiral  a = 0.0  b = 4.0    Bullets start r = a + bT out from the middle, T being how far it's turned so far (radians)
+///!   aimed   width = 30.0        Like spread, but pointed at the emitter's target instead of along direction
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::Arc;
+use cgmath::{Point2, Vector2};
+use toml::{self, Value};
+use specs;
+use super::Curve;
+
+#[derive(Debug)]
+pub enum PatternError {
+    Io(io::Error),
+    Parse(String),
+    Value(String),
+    Missing(String), // A child pattern that isn't in the file
+    Loop(String), // A pattern that ends up being its own child
+}
+
+impl From<io::Error> for PatternError {
+    fn from(e: io::Error) -> PatternError {
+        PatternError::Io(e)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Shape {
+    Single,
+    Ring,
+    Spread(f32), // Width
+    Spiral(f32, f32), // A B
+    Aimed(f32), // Width
+}
+
+#[derive(Clone, Debug)]
+pub struct Pattern {
+    pub name: String,
+    pub shape: Shape,
+    pub count: u32,
+    pub bullet: String,
+    pub direction: f32,
+    pub rotate: f32,
+    pub delay: f32,
+    pub interval: f32,
+    pub repeat: u32,
+    pub speed: f32,
+    pub acceleration: Curve<f32>,
+    pub angular: Curve<f32>,
+    pub lifetime: f32,
+    pub child: Option<Arc<Pattern>>,
+    pub child_after: f32,
+    pub split: bool,
+}
+
+impl Pattern {
+    fn new(name: &str) -> Pattern {
+        Pattern {
+            name: name.into(),
+            shape: Shape::Single,
+            count: 1,
+            bullet: "bullet".into(),
+            direction: 90.0,
+            rotate: 0.0,
+            delay: 0.0,
+            interval: 0.0,
+            repeat: 1,
+            speed: 100.0,
+            acceleration: Curve::constant(0.0),
+            angular: Curve::constant(0.0),
+            lifetime: 10.0,
+            child: None,
+            child_after: 0.0,
+            split: false,
+        }
+    }
+
+    // Where the bullets of the nth shot go. facing is which way the emitter's turned, and aim which way its target is.
+    pub fn shot(&self, n: u32, facing: f32, aim: Option<f32>) -> Vec<Shot> {
+        let turned = self.rotate * n as f32;
+        let count = self.count.max(1);
+        let even = |base: f32| (0..count).map(|i| base + 360.0 * i as f32 / count as f32).collect::<Vec<_>>();
+        let fan = |base: f32, width: f32| if count == 1 {
+            vec![base]
+        } else {
+            (0..count).map(|i| base - width / 2.0 + width * i as f32 / (count - 1) as f32).collect()
+        };
+
+        let base = facing + self.direction + turned;
+        let (angles, r) = match self.shape {
+            Shape::Single => (vec![base], 0.0),
+            Shape::Ring => (even(base), 0.0),
+            Shape::Spread(width) => (fan(base, width), 0.0),
+            // r = a + bT
+            Shape::Spiral(a, b) => (even(base), a + b * turned.abs().to_radians()),
+            Shape::Aimed(width) => (fan(aim.map(|a| a + turned).unwrap_or(base), width), 0.0),
+        };
+        angles.into_iter().map(|angle| {
+            let dir = Vector2::new(angle.to_radians().cos(), angle.to_radians().sin());
+            Shot {
+                angle: angle,
+                offset: dir * r,
+            }
+        }).collect()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+// One bullet, about to be fired
+pub struct Shot {
+    pub angle: f32, // Degrees
+    pub offset: Vector2<f32>, // From where it's fired from
+}
+
+#[derive(Clone, Debug, Default)]
+// Every pattern from a file, by name
+pub struct PatternLibrary {
+    patterns: HashMap<String, Arc<Pattern>>,
+}
+
+impl PatternLibrary {
+    pub fn load(name: &str) -> Result<PatternLibrary, PatternError> {
+        let path = Path::new("data").join("bullets").join(name).with_extension("toml");
+        let mut s = String::new();
+        try!(try!(File::open(&path)).read_to_string(&mut s));
+        PatternLibrary::parse(&s)
+    }
+
+    pub fn parse(s: &str) -> Result<PatternLibrary, PatternError> {
+        let mut parser = toml::Parser::new(s);
+        let table = match parser.parse() {
+            Some(t) => t,
+            None => {
+                let e = &parser.errors[0];
+                let (line, col) = parser.to_linecol(e.lo);
+                return Err(PatternError::Parse(format!("{}:{}: {}", line + 1, col + 1, e.desc)));
+            }
+        };
+
+        // Children are only names until everything's parsed
+        let mut raw = HashMap::new();
+        for (name, v) in table.iter() {
+            raw.insert(name.clone(), try!(parse_pattern(name, v)));
+        }
+        let mut lib = PatternLibrary::default();
+        for name in raw.keys() {
+            try!(lib.resolve(name, &raw, &mut HashSet::new()));
+        }
+        Ok(lib)
+    }
+
+    fn resolve(&mut self, name: &str, raw: &HashMap<String, (Pattern, Option<String>)>, seen: &mut HashSet<String>)
+            -> Result<Arc<Pattern>, PatternError> {
+        if let Some(p) = self.patterns.get(name) {
+            return Ok(p.clone());
+        }
+        if !seen.insert(name.into()) {
+            return Err(PatternError::Loop(name.into()));
+        }
+        let &(ref pattern, ref child) = try!(raw.get(name).ok_or(PatternError::Missing(name.into())));
+        let mut pattern = pattern.clone();
+        if let Some(ref child) = *child {
+            pattern.child = Some(try!(self.resolve(child, raw, seen)));
+        }
+        let pattern = Arc::new(pattern);
+        self.patterns.insert(name.into(), pattern.clone());
+        Ok(pattern)
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<Pattern>> {
+        self.patterns.get(name).cloned()
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.patterns.keys().map(|k| &k[..]).collect()
+    }
+}
+
+fn number(v: &Value) -> Option<f32> {
+    v.as_float().map(|f| f as f32).or(v.as_integer().map(|i| i as f32))
+}
+
+// A number, or [[time, value], ...]
+fn curve(v: &Value) -> Option<Curve<f32>> {
+    if let Some(n) = number(v) {
+        return Some(Curve::constant(n));
+    }
+    let mut keys = vec![];
+    for key in match v.as_slice() { Some(s) => s, None => return None } {
+        match key.as_slice() {
+            Some(k) if k.len() == 2 => match (number(&k[0]), number(&k[1])) {
+                (Some(t), Some(v)) => keys.push((t, v)),
+                _ => return None
+            },
+            _ => return None
+        }
+    }
+    if keys.is_empty() { None } else { Some(Curve::new(keys)) }
+}
+
+// Whole numbers that can't be negative
+fn count(v: &Value) -> Option<u32> {
+    v.as_integer().and_then(|i| if i >= 0 && i <= u32::max_value() as i64 { Some(i as u32) } else { None })
+}
+
+fn parse_pattern(name: &str, v: &Value) -> Result<(Pattern, Option<String>), PatternError> {
+    let t = try!(v.as_table().ok_or(PatternError::Value(format!("{} should be a table", name))));
+    let mut p = Pattern::new(name);
+    let mut child = None;
+    let mut shape = "single";
+    let (mut width, mut a, mut b) = (0.0, 0.0, 0.0);
+    for (k, v) in t.iter() {
+        let bad = || PatternError::Value(format!("{}.{} isn't right", name, k));
+        match &k[..] {
+            "shape" => shape = try!(v.as_str().ok_or(bad())),
+            "width" => width = try!(number(v).ok_or(bad())),
+            "a" => a = try!(number(v).ok_or(bad())),
+            "b" => b = try!(number(v).ok_or(bad())),
+            "count" => p.count = try!(count(v).ok_or(bad())),
+            "bullet" => p.bullet = try!(v.as_str().ok_or(bad())).into(),
+            "direction" => p.direction = try!(number(v).ok_or(bad())),
+            "rotate" => p.rotate = try!(number(v).ok_or(bad())),
+            "delay" => p.delay = try!(number(v).ok_or(bad())),
+            "interval" => p.interval = try!(number(v).ok_or(bad())),
+            "repeat" => p.repeat = try!(count(v).ok_or(bad())),
+            "speed" => p.speed = try!(number(v).ok_or(bad())),
+            "acceleration" => p.acceleration = try!(curve(v).ok_or(bad())),
+            "angular" => p.angular = try!(curve(v).ok_or(bad())),
+            "lifetime" => p.lifetime = try!(number(v).ok_or(bad())),
+            "child" => child = Some(try!(v.as_str().ok_or(bad())).to_string()),
+            "child_after" => p.child_after = try!(number(v).ok_or(bad())),
+            "split" => p.split = try!(v.as_bool().ok_or(bad())),
+            _ => return Err(PatternError::Value(format!("Unknown setting {}.{}", name, k)))
+        }
+    }
+    p.shape = match shape {
+        "single" => Shape::Single,
+        "ring" => Shape::Ring,
+        "spread" => Shape::Spread(width),
+        "spiral" => Shape::Spiral(a, b),
+        "aimed" => Shape::Aimed(width),
+        _ => return Err(PatternError::Value(format!("{}.shape should be single, ring, spread, spiral or aimed", name)))
+    };
+    if p.repeat == 0 && p.interval <= 0.0 {
+        return Err(PatternError::Value(format!("{} repeats forever, so it needs an interval", name)));
+    }
+    Ok((p, child))
+}
+
+#[derive(Clone, Debug)]
+// A pattern partway through being fired
+pub struct Firing {
+    pub pattern: Arc<Pattern>,
+    time: f32,
+    shots: u32,
+}
+
+impl Firing {
+    pub fn new(pattern: Arc<Pattern>) -> Firing {
+        Firing {
+            pattern: pattern,
+            time: 0.0,
+            shots: 0,
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.pattern.repeat != 0 && self.shots >= self.pattern.repeat
+    }
+
+    // Every bullet that's due by now
+    pub fn update(&mut self, dt: f32, facing: f32, aim: Option<f32>) -> Vec<Shot> {
+        self.time += dt;
+        let mut shots = vec![];
+        while !self.is_done() && self.time >= self.pattern.delay + self.pattern.interval * self.shots as f32 {
+            shots.extend(self.pattern.shot(self.shots, facing, aim));
+            self.shots += 1;
+        }
+        shots
+    }
+}
+
+#[derive(Clone, Debug)]
+// Fires a pattern from its entity
+pub struct BulletEmitter {
+    pub firing: Firing,
+    pub target: Option<specs::Entity>, // What aimed patterns shoot at
+    pub active: bool,
+    pub remove_when_done: bool,
+}
+
+impl BulletEmitter {
+    pub fn new(pattern: Arc<Pattern>) -> BulletEmitter {
+        BulletEmitter {
+            firing: Firing::new(pattern),
+            target: None,
+            active: true,
+            remove_when_done: false,
+        }
+    }
+
+    pub fn aiming_at(mut self, target: specs::Entity) -> BulletEmitter {
+        self.target = Some(target);
+        self
+    }
+
+    // Starts the pattern over, with a new one if it's given
+    pub fn restart(&mut self, pattern: Option<Arc<Pattern>>) {
+        let pattern = pattern.unwrap_or(self.firing.pattern.clone());
+        self.firing = Firing::new(pattern);
+    }
+}
+
+impl specs::Component for BulletEmitter {
+    type Storage = specs::HashMapStorage<BulletEmitter>;
+}
+
+#[derive(Clone, Debug)]
+// A fired bullet. Its Spatial gets put wherever pos says.
+pub struct Bullet {
+    pub pattern: Arc<Pattern>, // The one it was fired by
+    pub pos: Point2<f32>, // Center, in the world
+    pub angle: f32, // Degrees
+    pub speed: f32,
+    pub age: f32,
+    pub target: Option<specs::Entity>, // Passed on to its child pattern
+    pub child: Option<Firing>,
+}
+
+impl Bullet {
+    pub fn new(pattern: Arc<Pattern>, pos: Point2<f32>, angle: f32) -> Bullet {
+        Bullet {
+            speed: pattern.speed,
+            pattern: pattern,
+            pos: pos,
+            angle: angle,
+            age: 0.0,
+            target: None,
+            child: None,
+        }
+    }
+
+    // Moves it along, and returns whatever its child pattern fires
+    pub fn update(&mut self, dt: f32, aim: Option<f32>) -> Vec<Shot> {
+        self.age += dt;
+        self.speed += self.pattern.acceleration.at(self.age) * dt;
+        self.angle += self.pattern.angular.at(self.age) * dt;
+        let rad = self.angle.to_radians();
+        self.pos = self.pos + Vector2::new(rad.cos(), rad.sin()) * self.speed * dt;
+
+        if self.child.is_none() && self.age >= self.pattern.child_after {
+            self.child = self.pattern.child.clone().map(Firing::new);
+        }
+        let angle = self.angle;
+        match self.child {
+            // The child's pointed the way the bullet's going
+            Some(ref mut c) => c.update(dt, angle - 90.0, aim),
+            None => vec![]
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        let split = self.pattern.split && self.child.as_ref().map(|c| c.is_done()).unwrap_or(false);
+        self.age >= self.pattern.lifetime || split
+    }
+}
+
+impl specs::Component for Bullet {
+    type Storage = specs::VecStorage<Bullet>;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use super::{PatternLibrary, PatternError, Pattern, Shape, Firing, Bullet};
+    use cgmath::Point2;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 0.001
+    }
+
+    #[test]
+    fn parse_and_nest() {
+        let lib = PatternLibrary::parse(r#"
+            [flower]
+            shape = "ring"
+            count = 8
+            interval = 0.5
+            repeat = 0
+            child = "burst"
+            child_after = 1.0
+            split = true
+
+            [burst]
+            shape = "spread"
+            width = 90.0
+            count = 3
+            acceleration = [[0.0, 0.0], [1.0, 50.0]]
+        "#).unwrap();
+        let flower = lib.get("flower").unwrap();
+        assert_eq!(flower.shape, Shape::Ring);
+        assert_eq!(flower.count, 8);
+        let burst = flower.child.clone().unwrap();
+        assert_eq!(burst.shape, Shape::Spread(90.0));
+        assert!(close(burst.acceleration.at(0.5), 25.0));
+        assert_eq!(burst.name, "burst");
+    }
+
+    #[test]
+    fn bad_libraries() {
+        match PatternLibrary::parse("[a]\nchild = \"b\"\n[b]\nchild = \"a\"") {
+            Err(PatternError::Loop(_)) => (),
+            other => panic!("{:?}", other)
+        }
+        match PatternLibrary::parse("[a]\nchild = \"nope\"") {
+            Err(PatternError::Missing(_)) => (),
+            other => panic!("{:?}", other)
+        }
+        assert!(PatternLibrary::parse("[a]\nrepeat = 0").is_err());
+        assert!(PatternLibrary::parse("[a]\nshape = \"square\"").is_err());
+        match PatternLibrary::parse("[a]\ncount = -1") {
+            Err(PatternError::Value(_)) => (),
+            other => panic!("{:?}", other)
+        }
+        match PatternLibrary::parse("[a]\nrepeat = -2\ninterval = 1") {
+            Err(PatternError::Value(_)) => (),
+            other => panic!("{:?}", other)
+        }
+    }
+
+    #[test]
+    fn shapes() {
+        let ring = Pattern { shape: Shape::Ring, count: 4, direction: 0.0, ..Pattern::new("ring") };
+        let angles: Vec<f32> = ring.shot(0, 0.0, None).iter().map(|s| s.angle).collect();
+        assert_eq!(angles, vec![0.0, 90.0, 180.0, 270.0]);
+
+        let spread = Pattern { shape: Shape::Spread(60.0), count: 3, ..Pattern::new("spread") };
+        let angles: Vec<f32> = spread.shot(0, 0.0, None).iter().map(|s| s.angle).collect();
+        assert_eq!(angles, vec![60.0, 90.0, 120.0]);
+
+        // Straight at whatever's being aimed at
+        let aimed = Pattern { shape: Shape::Aimed(0.0), ..Pattern::new("aimed") };
+        assert!(close(aimed.shot(0, 0.0, Some(45.0))[0].angle, 45.0));
+        assert!(close(aimed.shot(0, 0.0, None)[0].angle, 90.0));
+
+        // r = a + bT
+        let spiral = Pattern { shape: Shape::Spiral(2.0, 1.0), rotate: 90.0, direction: 0.0, ..Pattern::new("spiral") };
+        let shot = spiral.shot(2, 0.0, None)[0];
+        assert!(close(shot.angle, 180.0));
+        assert!(close(shot.offset.x, -(2.0 + ::std::f32::consts::PI)));
+    }
+
+    #[test]
+    fn firing_over_time() {
+        let p = Arc::new(Pattern { count: 2, delay: 0.5, interval: 0.25, repeat: 3, ..Pattern::new("p") });
+        let mut f = Firing::new(p);
+        assert!(f.update(0.25, 0.0, None).is_empty());
+        assert_eq!(f.update(0.25, 0.0, None).len(), 2);
+        // A long frame catches up on everything it missed
+        assert_eq!(f.update(1.0, 0.0, None).len(), 4);
+        assert!(f.is_done());
+        assert!(f.update(1.0, 0.0, None).is_empty());
+    }
+
+    #[test]
+    fn bullets_move_and_split() {
+        let child = Arc::new(Pattern { shape: Shape::Ring, count: 6, ..Pattern::new("child") });
+        let p = Arc::new(Pattern {
+            speed: 10.0,
+            child: Some(child),
+            child_after: 1.0,
+            split: true,
+            ..Pattern::new("p")
+        });
+        let mut b = Bullet::new(p, Point2::new(0.0, 0.0), 0.0);
+        assert!(b.update(0.5, None).is_empty());
+        assert!(close(b.pos.x, 5.0));
+        assert!(!b.is_done());
+        assert_eq!(b.update(0.5, None).len(), 6);
+        assert!(b.is_done());
+    }
+}
@@ -0,0 +1,140 @@
+///! Shapes for things to be hit by, and the math for hitting them
+use cgmath::{Point2, Vector2, InnerSpace};
+use specs;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ColliderShape {
+    Circle(f32), // Radius
+    Box(f32, f32), // Half width, half height. Doesn't turn with the entity.
+}
+
+#[derive(Clone, Copy, Debug)]
+// Something that can be hit. Sits around the entity's origin.
+pub struct Collider {
+    pub shape: ColliderShape,
+    pub offset: Vector2<f32>, // From the entity's origin
+}
+
+impl Collider {
+    pub fn circle(radius: f32) -> Collider {
+        Collider {
+            shape: ColliderShape::Circle(radius),
+            offset: Vector2::new(0.0, 0.0),
+        }
+    }
+
+    pub fn rect(width: f32, height: f32) -> Collider {
+        Collider {
+            shape: ColliderShape::Box(width / 2.0, height / 2.0),
+            offset: Vector2::new(0.0, 0.0),
+        }
+    }
+
+    pub fn at(mut self, offset: Vector2<f32>) -> Collider {
+        self.offset = offset;
+        self
+    }
+
+    // Whether a capsule (the segment a b, fattened by radius) touches it. origin is where the entity's origin is in the world.
+    pub fn hit_by_capsule(&self, origin: Point2<f32>, a: Point2<f32>, b: Point2<f32>, radius: f32) -> bool {
+        let center = origin + self.offset;
+        match self.shape {
+            ColliderShape::Circle(r) => segment_distance(center, a, b) <= r + radius,
+            ColliderShape::Box(hw, hh) => {
+                let (min, max) = (Point2::new(center.x - hw, center.y - hh), Point2::new(center.x + hw, center.y + hh));
+                capsule_hits_box(a, b, radius, min, max)
+            }
+        }
+    }
+}
+
+impl specs::Component for Collider {
+    type Storage = specs::VecStorage<Collider>;
+}
+
+// The point on the segment a b nearest to p
+pub fn closest_on_segment(p: Point2<f32>, a: Point2<f32>, b: Point2<f32>) -> Point2<f32> {
+    let ab = b - a;
+    let len2 = ab.magnitude2();
+    if len2 == 0.0 { return a }
+    let t = ((p - a).dot(ab) / len2).max(0.0).min(1.0);
+    a + ab * t
+}
+
+pub fn segment_distance(p: Point2<f32>, a: Point2<f32>, b: Point2<f32>) -> f32 {
+    (p - closest_on_segment(p, a, b)).magnitude()
+}
+
+// Clips the segment against each pair of sides in turn. Whatever's left is inside.
+fn segment_hits_box(a: Point2<f32>, b: Point2<f32>, min: Point2<f32>, max: Point2<f32>) -> bool {
+    let d = b - a;
+    let (mut t0, mut t1) = (0.0f32, 1.0f32);
+    for &(start, dir, lo, hi) in [(a.x, d.x, min.x, max.x), (a.y, d.y, min.y, max.y)].iter() {
+        if dir == 0.0 {
+            if start < lo || start > hi { return false }
+        } else {
+            let (mut near, mut far) = ((lo - start) / dir, (hi - start) / dir);
+            if near > far { ::std::mem::swap(&mut near, &mut far) }
+            t0 = t0.max(near);
+            t1 = t1.min(far);
+            if t0 > t1 { return false }
+        }
+    }
+    true
+}
+
+pub fn capsule_hits_box(a: Point2<f32>, b: Point2<f32>, radius: f32, min: Point2<f32>, max: Point2<f32>) -> bool {
+    if segment_hits_box(a, b, min, max) {
+        return true;
+    }
+    // Otherwise the closest they get is at one of the segment's ends or one of the box's corners
+    let clamp = |p: Point2<f32>| Point2::new(p.x.max(min.x).min(max.x), p.y.max(min.y).min(max.y));
+    let corners = [min, Point2::new(max.x, min.y), max, Point2::new(min.x, max.y)];
+    let nearest = [a, b].iter().map(|&p| (p - clamp(p)).magnitude())
+        .chain(corners.iter().map(|&c| segment_distance(c, a, b)))
+        .fold(::std::f32::INFINITY, f32::min);
+    nearest <= radius
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Point2, Vector2};
+    use super::{Collider, closest_on_segment, segment_distance, capsule_hits_box};
+
+    #[test]
+    fn segments() {
+        let (a, b) = (Point2::new(0.0, 0.0), Point2::new(10.0, 0.0));
+        assert_eq!(closest_on_segment(Point2::new(5.0, 3.0), a, b), Point2::new(5.0, 0.0));
+        assert_eq!(closest_on_segment(Point2::new(-5.0, 3.0), a, b), a);
+        assert_eq!(segment_distance(Point2::new(13.0, 4.0), a, b), 5.0);
+        // A segment that's just a point
+        assert_eq!(segment_distance(Point2::new(3.0, 4.0), a, a), 5.0);
+    }
+
+    #[test]
+    fn boxes() {
+        let (min, max) = (Point2::new(0.0, 0.0), Point2::new(4.0, 4.0));
+        // Straight through
+        assert!(capsule_hits_box(Point2::new(-5.0, 2.0), Point2::new(10.0, 2.0), 0.0, min, max));
+        // Starting inside
+        assert!(capsule_hits_box(Point2::new(2.0, 2.0), Point2::new(2.0, 20.0), 0.0, min, max));
+        // Passing by, with and without enough width to touch
+        assert!(!capsule_hits_box(Point2::new(-5.0, 6.0), Point2::new(10.0, 6.0), 1.0, min, max));
+        assert!(capsule_hits_box(Point2::new(-5.0, 6.0), Point2::new(10.0, 6.0), 2.5, min, max));
+        // Stopping short of it
+        assert!(!capsule_hits_box(Point2::new(-5.0, 2.0), Point2::new(-2.0, 2.0), 1.0, min, max));
+        // Diagonally past a corner
+        assert!(!capsule_hits_box(Point2::new(5.0, 10.0), Point2::new(10.0, 5.0), 1.0, min, max));
+    }
+
+    #[test]
+    fn colliders() {
+        let c = Collider::circle(3.0).at(Vector2::new(10.0, 0.0));
+        let origin = Point2::new(0.0, 0.0);
+        assert!(c.hit_by_capsule(origin, Point2::new(10.0, -20.0), Point2::new(10.0, 20.0), 0.5));
+        assert!(!c.hit_by_capsule(origin, Point2::new(0.0, -20.0), Point2::new(0.0, 20.0), 0.5));
+        assert!(c.hit_by_capsule(origin, Point2::new(0.0, -20.0), Point2::new(0.0, 20.0), 7.5));
+        let r = Collider::rect(4.0, 4.0);
+        assert!(r.hit_by_capsule(Point2::new(5.0, 5.0), Point2::new(0.0, 0.0), Point2::new(10.0, 10.0), 0.0));
+    }
+}
@@ -1,14 +1,37 @@
 use specs;
-use cgmath::{Point2, Vector3, Basis3, Decomposed};
+use cgmath::{Point2, Vector2};
 use graphics::{Vertex, Index};
-use image::DynamicImage;
+
+mod tilemap;
+mod particles;
+mod bullets;
+mod collision;
+mod beams;
+pub use self::particles::{Emitter, Particle, ParticleDef, ParticleError, Curve, Lerp};
+pub use self::bullets::{Pattern, PatternLibrary, PatternError, Shape, Shot, Firing, BulletEmitter, Bullet};
+pub use self::collision::{Collider, ColliderShape, closest_on_segment, segment_distance, capsule_hits_box};
+pub use self::beams::{Beam, Hit, BeamHits};
+pub use self::tilemap::{Tilemap, TileLayer, Tileset, TileCollision, CHUNK_SIZE, FLIP_HORIZONTAL, FLIP_VERTICAL, FLIP_DIAGONAL};
 
 #[derive(Clone)]
 // This object physically exists at a point.
 pub struct Spatial {
     pub pos: Point2<f32>,
-    pub origin: Point2<f32>, // Relative to the bottom left corner
-    pub transform: Decomposed<Vector3<f32>, Basis3<f32>>, // NOTE NEVER CHANGE disp! The render system already sets it.
+    pub origin: Point2<f32>, // Relative to the bottom left corner. Turning and scaling happen around it.
+    pub rotation: f32, // Degrees, counterclockwise
+    pub scale: Vector2<f32>, // X Y
+}
+
+impl Spatial {
+    // Unturned and unscaled
+    pub fn new(pos: Point2<f32>, origin: Point2<f32>) -> Spatial {
+        Spatial {
+            pos: pos,
+            origin: origin,
+            rotation: 0.0,
+            scale: Vector2::new(1.0, 1.0),
+        }
+    }
 }
 
 impl specs::Component for Spatial {
@@ -23,9 +46,152 @@ pub enum VisualType {
         // TODO Make sure the common interface supports Sprites (animated stuff), too.
     },
     // TODO Hide using a common interface
-    Still(Vec<Vertex>, Option<Vec<Index>>, Option<DynamicImage>)
+    // Vertices Indices Texture (under "data/textures", loaded by the renderer)
+    Still(Vec<Vertex>, Option<Vec<Index>>, Option<String>)
 }
 
 impl specs::Component for VisualType {
     type Storage = specs::VecStorage<VisualType>;
 }
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+// Where a Text sits relative to its entity's position
+pub enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Clone)]
+// Words attached to an entity. Damage numbers, name tags, HUD counters...
+pub struct Text {
+    pub text: String,
+    pub font: String, // Name of the font in the renderer's FontLibrary (ex. "basic")
+    pub size: f32, // Pixel height
+    pub color: [f32; 4],
+    pub align: Align,
+}
+
+impl specs::Component for Text {
+    type Storage = specs::VecStorage<Text>;
+}
+
+#[derive(Clone, Debug)]
+// Looks at the world for the renderer. The CameraSystem moves it around, and the RenderSystem tells the renderer where it is.
+pub struct Camera {
+    pub view: String, // Which of the renderer's views it moves. "main" unless it's split screen.
+    pub target: Option<specs::Entity>, // Followed around, if there is one
+    pub position: Point2<f32>, // The world point looked at, before any shake
+    pub follow_speed: f32, // How quickly it catches up to the target. Higher is snappier, 0 snaps right to it.
+    pub dead_zone: (f32, f32), // Half the width and height of the box the target can wander in without the camera moving
+    pub bounds: Option<(Point2<f32>, Point2<f32>)>, // Bottom left and top right of the level. Nothing past them gets shown.
+    pub viewport: (f32, f32), // Pixels it fills on screen
+    pub zoom: Vector2<f32>, // X Y
+    pub rotation: f32, // Degrees, counterclockwise
+    pub trauma: f32, // 0 to 1. How much it shakes, squared.
+    pub shake_decay: f32, // Trauma lost per second
+    pub shake_offset: f32, // The furthest a shake moves it, in pixels
+    pub shake_angle: f32, // The furthest a shake turns it, in degrees
+    pub shown_at: Point2<f32>, // Where it's really looking this frame, shake and all
+    pub shown_angle: f32,
+}
+
+impl Camera {
+    pub fn new(viewport: (f32, f32)) -> Camera {
+        Camera {
+            view: "main".into(),
+            target: None,
+            position: Point2::new(viewport.0 / 2.0, viewport.1 / 2.0),
+            follow_speed: 5.0,
+            dead_zone: (0.0, 0.0),
+            bounds: None,
+            viewport: viewport,
+            zoom: Vector2::new(1.0, 1.0),
+            rotation: 0.0,
+            trauma: 0.0,
+            shake_decay: 1.5,
+            shake_offset: 12.0,
+            shake_angle: 3.0,
+            shown_at: Point2::new(viewport.0 / 2.0, viewport.1 / 2.0),
+            shown_angle: 0.0,
+        }
+    }
+
+    pub fn following(mut self, target: specs::Entity) -> Camera {
+        self.target = Some(target);
+        self
+    }
+
+    // For split screen. viewport should match the view's size, or bounds won't work out.
+    pub fn in_view<S: Into<String>>(mut self, view: S) -> Camera {
+        self.view = view.into();
+        self
+    }
+
+    // Adds trauma. Hits, explosions, that kind of thing.
+    pub fn shake(&mut self, amount: f32) {
+        self.trauma = (self.trauma + amount).min(1.0);
+    }
+}
+
+impl specs::Component for Camera {
+    type Storage = specs::HashMapStorage<Camera>;
+}
+
+#[derive(Clone, Debug)]
+// Only drawn in these views (ex. a player's own HUD in their half of the screen). Without one, it shows up everywhere.
+pub struct ViewMask(pub Vec<String>);
+
+impl specs::Component for ViewMask {
+    type Storage = specs::HashMapStorage<ViewMask>;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+// What gets drawn over what. Later layers go on top.
+pub enum RenderLayer {
+    Background,
+    World,
+    Bullets,
+    Effects,
+    Foreground,
+}
+
+impl RenderLayer {
+    pub fn all() -> [RenderLayer; 5] {
+        [RenderLayer::Background, RenderLayer::World, RenderLayer::Bullets, RenderLayer::Effects, RenderLayer::Foreground]
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+// Where an entity goes in the drawing order. Without one, it's in World at depth 0.
+pub struct Depth {
+    pub layer: RenderLayer,
+    pub depth: f32, // Within the layer. Higher goes on top.
+}
+
+impl Depth {
+    pub fn new(layer: RenderLayer, depth: f32) -> Depth {
+        Depth {
+            layer: layer,
+            depth: depth,
+        }
+    }
+}
+
+impl Default for Depth {
+    fn default() -> Depth {
+        Depth::new(RenderLayer::World, 0.0)
+    }
+}
+
+impl specs::Component for Depth {
+    type Storage = specs::VecStorage<Depth>;
+}
+
+#[derive(Clone, Copy, Debug)]
+// Multiplies the colors of whatever the entity draws. RGBA.
+pub struct Tint(pub [f32; 4]);
+
+impl specs::Component for Tint {
+    type Storage = specs::VecStorage<Tint>;
+}
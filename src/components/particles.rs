@@ -0,0 +1,435 @@
+///! Particle effects. An Emitter sits on an entity and spits out particles, which fly off on their own
+///! (they don't follow the entity around once they're out).
+///!
+///! Effects are described in "data/particles/<name>.toml":
+///!
+///! texture = "spark.png" # Under "data/textures". Plain squares without one.
+///! blend = "additive" # or "alpha"
+///! rate = 20.0 # Per second, for as long as it's emitting
+///! bursts = [{ time = 0.0, count = 30 }] # Seconds in, and how many at once
+///! duration = 0.5 # Stops emitting after this long. Leave it out to go on forever.
+///! lifetime = [0.3, 0.6] # Seconds, picked between the two
+///! speed = [100, 200]
+///! direction = 90.0 # Degrees, counterclockwise from the right. Turns with the entity.
+///! spread = 45.0 # How wide the cone they fly out in is, in degrees
+///! gravity = [0, -300]
+///! drag = 2.0 # Speed lost per second, as a fraction of itself
+///! color = [[0.0, 1.0, 1.0, 0.8, 1.0], [1.0, 1.0, 0.3, 0.0, 0.0]] # Over the lifetime: time then RGBA
+///! size = [[0.0, 6.0], [1.0, 2.0]] # Over the lifetime: time then size
+///!
+///! (TOML won't mix integers and floats in one array, so the curves need their decimal points.)
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use cgmath::{Point2, Vector2};
+use rand::{self, Rng, XorShiftRng};
+use toml::{self, Value};
+use specs;
+use graphics::{Vertex, Index};
+use systems::BlendMode;
+
+#[derive(Debug)]
+pub enum ParticleError {
+    Io(io::Error),
+    Parse(String),
+    Value(String),
+}
+
+impl From<io::Error> for ParticleError {
+    fn from(e: io::Error) -> ParticleError {
+        ParticleError::Io(e)
+    }
+}
+
+pub trait Lerp: Copy {
+    fn lerp(self, other: Self, t: f32) -> Self;
+}
+
+impl Lerp for f32 {
+    fn lerp(self, other: f32, t: f32) -> f32 {
+        self + (other - self) * t
+    }
+}
+
+impl Lerp for [f32; 4] {
+    fn lerp(self, other: [f32; 4], t: f32) -> [f32; 4] {
+        [self[0].lerp(other[0], t), self[1].lerp(other[1], t), self[2].lerp(other[2], t), self[3].lerp(other[3], t)]
+    }
+}
+
+#[derive(Clone, Debug)]
+// Straight lines between keys. Times go from 0 (just spawned) to 1 (about to die).
+pub struct Curve<T: Lerp> {
+    keys: Vec<(f32, T)>,
+}
+
+impl<T: Lerp> Curve<T> {
+    pub fn new(mut keys: Vec<(f32, T)>) -> Curve<T> {
+        assert!(!keys.is_empty(), "A curve needs at least one key");
+        keys.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        Curve { keys: keys }
+    }
+
+    pub fn constant(v: T) -> Curve<T> {
+        Curve::new(vec![(0.0, v)])
+    }
+
+    pub fn at(&self, t: f32) -> T {
+        let first = self.keys[0];
+        if t <= first.0 { return first.1 }
+        for w in self.keys.windows(2) {
+            let (a, b) = (w[0], w[1]);
+            if t <= b.0 {
+                return a.1.lerp(b.1, (t - a.0) / (b.0 - a.0));
+            }
+        }
+        self.keys[self.keys.len() - 1].1
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ParticleDef {
+    pub texture: Option<String>,
+    pub blend: BlendMode,
+    pub rate: f32,
+    pub bursts: Vec<(f32, u32)>, // Time Count
+    pub duration: Option<f32>,
+    pub lifetime: (f32, f32),
+    pub speed: (f32, f32),
+    pub direction: f32,
+    pub spread: f32,
+    pub gravity: Vector2<f32>,
+    pub drag: f32,
+    pub color: Curve<[f32; 4]>,
+    pub size: Curve<f32>,
+    pub max: usize, // Alive at once, from one emitter
+}
+
+impl Default for ParticleDef {
+    fn default() -> ParticleDef {
+        ParticleDef {
+            texture: None,
+            blend: BlendMode::Alpha,
+            rate: 0.0,
+            bursts: vec![],
+            duration: None,
+            lifetime: (1.0, 1.0),
+            speed: (0.0, 0.0),
+            direction: 90.0,
+            spread: 360.0,
+            gravity: Vector2::new(0.0, 0.0),
+            drag: 0.0,
+            color: Curve::constant([1.0; 4]),
+            size: Curve::constant(4.0),
+            max: 1000,
+        }
+    }
+}
+
+impl ParticleDef {
+    pub fn load(name: &str) -> Result<ParticleDef, ParticleError> {
+        let path = Path::new("data").join("particles").join(name).with_extension("toml");
+        let mut s = String::new();
+        try!(try!(File::open(&path)).read_to_string(&mut s));
+        ParticleDef::parse(&s)
+    }
+
+    pub fn parse(s: &str) -> Result<ParticleDef, ParticleError> {
+        let mut parser = toml::Parser::new(s);
+        let table = match parser.parse() {
+            Some(t) => t,
+            None => {
+                let e = &parser.errors[0];
+                let (line, col) = parser.to_linecol(e.lo);
+                return Err(ParticleError::Parse(format!("{}:{}: {}", line + 1, col + 1, e.desc)));
+            }
+        };
+
+        let mut def = ParticleDef::default();
+        for (k, v) in table.iter() {
+            let bad = || ParticleError::Value(format!("{} isn't right", k));
+            match &k[..] {
+                "texture" => def.texture = Some(try!(v.as_str().ok_or(bad())).into()),
+                "blend" => def.blend = match v.as_str() {
+                    Some("alpha") => BlendMode::Alpha,
+                    Some("additive") => BlendMode::Additive,
+                    _ => return Err(ParticleError::Value("blend should be \"alpha\" or \"additive\"".into()))
+                },
+                "rate" => def.rate = try!(number(v).ok_or(bad())),
+                "bursts" => for b in try!(v.as_slice().ok_or(bad())) {
+                    let time = try!(b.lookup("time").and_then(number).ok_or(bad()));
+                    let count = try!(b.lookup("count").and_then(|c| c.as_integer()).ok_or(bad()));
+                    def.bursts.push((time, count as u32));
+                },
+                "duration" => def.duration = Some(try!(number(v).ok_or(bad()))),
+                "lifetime" => def.lifetime = try!(range(v).ok_or(bad())),
+                "speed" => def.speed = try!(range(v).ok_or(bad())),
+                "direction" => def.direction = try!(number(v).ok_or(bad())),
+                "spread" => def.spread = try!(number(v).and_then(|s| if s >= 0.0 { Some(s) } else { None }).ok_or(bad())),
+                "gravity" => {
+                    let (x, y) = try!(range(v).ok_or(bad()));
+                    def.gravity = Vector2::new(x, y);
+                },
+                "drag" => def.drag = try!(number(v).ok_or(bad())),
+                "max" => def.max = try!(v.as_integer().ok_or(bad())) as usize,
+                "color" => {
+                    let mut keys = vec![];
+                    for key in try!(v.as_slice().ok_or(bad())) {
+                        let key = try!(numbers(key).ok_or(bad()));
+                        if key.len() != 5 { return Err(bad()) }
+                        keys.push((key[0], [key[1], key[2], key[3], key[4]]));
+                    }
+                    if keys.is_empty() { return Err(bad()) }
+                    def.color = Curve::new(keys);
+                },
+                "size" => {
+                    let mut keys = vec![];
+                    for key in try!(v.as_slice().ok_or(bad())) {
+                        let key = try!(numbers(key).ok_or(bad()));
+                        if key.len() != 2 { return Err(bad()) }
+                        keys.push((key[0], key[1]));
+                    }
+                    if keys.is_empty() { return Err(bad()) }
+                    def.size = Curve::new(keys);
+                },
+                _ => return Err(ParticleError::Value(format!("Unknown setting {}", k)))
+            }
+        }
+        Ok(def)
+    }
+}
+
+fn number(v: &Value) -> Option<f32> {
+    v.as_float().map(|f| f as f32).or(v.as_integer().map(|i| i as f32))
+}
+
+fn numbers(v: &Value) -> Option<Vec<f32>> {
+    v.as_slice().and_then(|s| s.iter().map(number).collect())
+}
+
+// [min, max], or just the one number
+fn range(v: &Value) -> Option<(f32, f32)> {
+    match number(v) {
+        Some(n) => Some((n, n)),
+        None => numbers(v).and_then(|n| if n.len() == 2 { Some((n[0], n[1])) } else { None })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Particle {
+    pub pos: Point2<f32>, // World
+    pub vel: Vector2<f32>,
+    pub age: f32,
+    pub lifetime: f32,
+}
+
+#[derive(Clone)]
+pub struct Emitter {
+    pub def: ParticleDef,
+    pub offset: Vector2<f32>, // From the entity's position
+    pub emitting: bool,
+    pub remove_when_done: bool, // Takes the entity with it once it's done and its particles are gone
+    time: f32,
+    owed: f32, // Particles the rate owes, that haven't made up a whole one yet
+    next_burst: usize,
+    extra: u32, // Bursts asked for with burst()
+    particles: Vec<Particle>,
+    rng: XorShiftRng,
+}
+
+impl Emitter {
+    pub fn new(def: ParticleDef) -> Emitter {
+        Emitter {
+            def: def,
+            offset: Vector2::new(0.0, 0.0),
+            emitting: true,
+            remove_when_done: false,
+            time: 0.0,
+            owed: 0.0,
+            next_burst: 0,
+            extra: 0,
+            particles: vec![],
+            rng: rand::weak_rng(),
+        }
+    }
+
+    // For one-shot effects (explosions, sparks on a hit): gets rid of itself when it's over
+    pub fn once(def: ParticleDef) -> Emitter {
+        Emitter {
+            remove_when_done: true,
+            ..Emitter::new(def)
+        }
+    }
+
+    // Throws out count more, next update
+    pub fn burst(&mut self, count: u32) {
+        self.extra += count;
+    }
+
+    pub fn particles(&self) -> &[Particle] {
+        &self.particles
+    }
+
+    // Won't emit anything else, and everything it did is gone.
+    // Without a rate there's nothing left to emit once the bursts are out, duration or not.
+    pub fn is_done(&self) -> bool {
+        let stopped = !self.emitting || self.def.rate <= 0.0 || self.def.duration.map(|d| self.time >= d).unwrap_or(false);
+        stopped && self.next_burst >= self.def.bursts.len() && self.extra == 0 && self.particles.is_empty()
+    }
+
+    // at is where the entity is, and angle which way it's turned
+    pub fn update(&mut self, dt: f32, at: Point2<f32>, angle: f32) {
+        let drag = (-self.def.drag * dt).exp();
+        let gravity = self.def.gravity;
+        for p in self.particles.iter_mut() {
+            p.age += dt;
+            p.vel = (p.vel + gravity * dt) * drag;
+            p.pos = p.pos + p.vel * dt;
+        }
+        self.particles.retain(|p| p.age < p.lifetime);
+
+        let mut count = self.extra;
+        self.extra = 0;
+        let active = self.emitting && self.def.duration.map(|d| self.time < d).unwrap_or(true);
+        if active {
+            self.owed += self.def.rate * dt;
+            count += self.owed as u32;
+            self.owed = self.owed.fract();
+        }
+        self.time += dt;
+        while self.next_burst < self.def.bursts.len() && self.def.bursts[self.next_burst].0 <= self.time {
+            if self.emitting {
+                count += self.def.bursts[self.next_burst].1;
+            }
+            self.next_burst += 1;
+        }
+
+        let origin = at + self.offset;
+        for _ in 0..count {
+            if self.particles.len() >= self.def.max { break }
+            let p = self.spawn(origin, angle);
+            self.particles.push(p);
+        }
+    }
+
+    fn spawn(&mut self, origin: Point2<f32>, angle: f32) -> Particle {
+        let def = &self.def;
+        let half = def.spread / 2.0;
+        let dir = (def.direction + angle + self.rng.gen_range(-half, half + ::std::f32::EPSILON)).to_radians();
+        let speed = between(&mut self.rng, def.speed);
+        Particle {
+            pos: origin,
+            vel: Vector2::new(dir.cos(), dir.sin()) * speed,
+            age: 0.0,
+            lifetime: between(&mut self.rng, def.lifetime).max(0.001),
+        }
+    }
+
+    // Quads for every particle, in the world, ready for RenderInstruction::DrawWorld
+    pub fn mesh(&self) -> (Vec<Vertex>, Vec<Index>) {
+        let (mut verts, mut indices) = (Vec::with_capacity(self.particles.len() * 4), Vec::with_capacity(self.particles.len() * 6));
+        for p in self.particles.iter() {
+            let t = p.age / p.lifetime;
+            let color = self.def.color.at(t);
+            let half = self.def.size.at(t) / 2.0;
+            let base = verts.len() as Index;
+            for &(dx, dy, u, v) in [(-1.0, -1.0, 0.0, 0.0), (1.0, -1.0, 1.0, 0.0), (1.0, 1.0, 1.0, 1.0), (-1.0, 1.0, 0.0, 1.0)].iter() {
+                verts.push(Vertex { position: [p.pos.x + dx * half, p.pos.y + dy * half], color: color, tex_coords: [u, v] });
+            }
+            indices.extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+        }
+        (verts, indices)
+    }
+}
+
+fn between(rng: &mut XorShiftRng, r: (f32, f32)) -> f32 {
+    if r.1 > r.0 { rng.gen_range(r.0, r.1) } else { r.0 }
+}
+
+impl specs::Component for Emitter {
+    type Storage = specs::HashMapStorage<Emitter>;
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Point2;
+    use super::{Curve, ParticleDef, Emitter};
+
+    #[test]
+    fn curves() {
+        let c = Curve::new(vec![(1.0, 0.0), (0.0, 10.0), (0.5, 20.0)]);
+        assert_eq!(c.at(-1.0), 10.0);
+        assert_eq!(c.at(0.25), 15.0);
+        assert_eq!(c.at(0.75), 10.0);
+        assert_eq!(c.at(2.0), 0.0);
+    }
+
+    #[test]
+    fn parse() {
+        let def = ParticleDef::parse(r#"
+            rate = 10
+            bursts = [{ time = 0.0, count = 5 }]
+            lifetime = [0.5, 1.0]
+            speed = 50
+            blend = "additive"
+            color = [[0.0, 1.0, 1.0, 1.0, 1.0], [1.0, 1.0, 0.0, 0.0, 0.0]]
+        "#).unwrap();
+        assert_eq!(def.rate, 10.0);
+        assert_eq!(def.bursts, vec![(0.0, 5)]);
+        assert_eq!(def.speed, (50.0, 50.0));
+        assert_eq!(def.color.at(0.5), [1.0, 0.5, 0.5, 0.5]);
+        assert!(ParticleDef::parse("speed = \"fast\"").is_err());
+        assert!(ParticleDef::parse("sped = 5").is_err());
+        assert!(ParticleDef::parse("spread = -10").is_err());
+    }
+
+    #[test]
+    fn bursts_and_lifetime() {
+        let def = ParticleDef {
+            bursts: vec![(0.0, 8)],
+            lifetime: (0.5, 0.5),
+            duration: Some(0.1),
+            ..ParticleDef::default()
+        };
+        let mut e = Emitter::once(def);
+        e.update(0.016, Point2::new(0.0, 0.0), 0.0);
+        assert_eq!(e.particles().len(), 8);
+        assert_eq!(e.mesh().1.len(), 8 * 6);
+        for _ in 0..40 {
+            e.update(0.016, Point2::new(0.0, 0.0), 0.0);
+        }
+        assert!(e.particles().is_empty());
+        assert!(e.is_done());
+    }
+
+    #[test]
+    fn shipped_one_shots_finish() {
+        for name in ["sparks", "explosion"].iter() {
+            let mut e = Emitter::once(ParticleDef::load(name).unwrap());
+            e.update(0.016, Point2::new(0.0, 0.0), 0.0);
+            assert!(!e.particles().is_empty());
+            assert!(!e.is_done());
+            for _ in 0..120 {
+                e.update(0.016, Point2::new(0.0, 0.0), 0.0);
+            }
+            assert!(e.is_done(), "{} never finished", name);
+        }
+    }
+
+    #[test]
+    fn rate_adds_up() {
+        let def = ParticleDef {
+            rate: 10.0,
+            lifetime: (10.0, 10.0),
+            ..ParticleDef::default()
+        };
+        let mut e = Emitter::new(def);
+        for _ in 0..10 {
+            e.update(0.05, Point2::new(0.0, 0.0), 0.0);
+        }
+        // Half a second at 10 a second, give or take the rounding
+        assert!(e.particles().len() == 5 || e.particles().len() == 4);
+        // Going forever, so never done
+        assert!(!e.is_done());
+    }
+}
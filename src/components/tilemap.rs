@@ -0,0 +1,223 @@
+///! Levels made out of tiles. A Tilemap is a few layers of tile ids over one grid; the renderer
+///! draws it in chunks, and only the chunks that are on screen.
+///!
+///! Tile ids work like Tiled's: 0 is nothing, and anything else is counted from the first tile of
+///! the first tileset, straight on into the next tileset.
+use specs;
+use std::collections::HashMap;
+
+// Tiled keeps flips in the top bits of a tile id
+pub const FLIP_HORIZONTAL: u32 = 0x80000000;
+pub const FLIP_VERTICAL: u32 = 0x40000000;
+pub const FLIP_DIAGONAL: u32 = 0x20000000; // NOTE Not drawn yet
+const FLIPS: u32 = FLIP_HORIZONTAL | FLIP_VERTICAL | FLIP_DIAGONAL;
+
+// Tiles along each side of a chunk
+pub const CHUNK_SIZE: u32 = 16;
+
+#[derive(Clone, Debug)]
+pub struct Tileset {
+    pub first_id: u32, // The id of its first tile
+    pub texture: String, // Under "data/textures"
+    pub image_size: (u32, u32),
+    pub tile_size: (u32, u32),
+    pub margin: u32, // Around the edge of the image
+    pub spacing: u32, // Between tiles
+    pub columns: u32,
+    pub tile_count: u32,
+    // Anything set on a tile in the editor (ex. solid = "true"). Keyed by tile number inside the tileset.
+    pub properties: HashMap<u32, HashMap<String, String>>,
+}
+
+impl Tileset {
+    pub fn contains(&self, id: u32) -> bool {
+        let id = id & !FLIPS;
+        id >= self.first_id && id < self.first_id + self.tile_count
+    }
+
+    pub fn property(&self, id: u32, name: &str) -> Option<&str> {
+        let id = id & !FLIPS;
+        if !self.contains(id) { return None }
+        self.properties.get(&(id - self.first_id)).and_then(|p| p.get(name)).map(|p| &p[..])
+    }
+
+    // Left Top Right Bottom, with v going up from the bottom of the image (it's uploaded flipped)
+    pub fn uv(&self, id: u32) -> [f32; 4] {
+        let n = (id & !FLIPS) - self.first_id;
+        let (col, row) = (n % self.columns, n / self.columns);
+        let x = (self.margin + col * (self.tile_size.0 + self.spacing)) as f32;
+        let y = (self.margin + row * (self.tile_size.1 + self.spacing)) as f32;
+        let (w, h) = (self.image_size.0 as f32, self.image_size.1 as f32);
+        let (mut left, mut right) = (x / w, (x + self.tile_size.0 as f32) / w);
+        let (mut top, mut bottom) = (1.0 - y / h, 1.0 - (y + self.tile_size.1 as f32) / h);
+        if id & FLIP_HORIZONTAL != 0 {
+            ::std::mem::swap(&mut left, &mut right);
+        }
+        if id & FLIP_VERTICAL != 0 {
+            ::std::mem::swap(&mut top, &mut bottom);
+        }
+        [left, top, right, bottom]
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TileLayer {
+    pub name: String,
+    pub visible: bool,
+    tiles: Vec<u32>, // Rows from the top, like Tiled
+}
+
+#[derive(Clone, Debug)]
+// The bottom left corner of the map sits on the entity's Spatial
+pub struct Tilemap {
+    width: u32, // In tiles
+    height: u32,
+    pub tile_size: (f32, f32), // In world units
+    pub tilesets: Vec<Tileset>,
+    layers: Vec<TileLayer>,
+    version: u64, // Goes up with every change, so the renderer knows to rebuild
+}
+
+impl Tilemap {
+    pub fn new(width: u32, height: u32, tile_size: (f32, f32), tilesets: Vec<Tileset>) -> Tilemap {
+        Tilemap {
+            width: width,
+            height: height,
+            tile_size: tile_size,
+            tilesets: tilesets,
+            layers: vec![],
+            version: 0,
+        }
+    }
+
+    // tiles go row by row from the top left. Panics if there aren't width * height of them.
+    pub fn add_layer<S: Into<String>>(&mut self, name: S, tiles: Vec<u32>) {
+        assert_eq!(tiles.len(), (self.width * self.height) as usize, "A layer should have a tile for every spot on the map");
+        self.layers.push(TileLayer {
+            name: name.into(),
+            visible: true,
+            tiles: tiles,
+        });
+        self.version += 1;
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    pub fn layers(&self) -> &[TileLayer] {
+        &self.layers
+    }
+
+    pub fn layer_named(&self, name: &str) -> Option<usize> {
+        self.layers.iter().position(|l| l.name == name)
+    }
+
+    pub fn set_visible(&mut self, layer: usize, visible: bool) {
+        self.layers[layer].visible = visible;
+        self.version += 1;
+    }
+
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    // x from the left, y from the top. 0 off the edge.
+    pub fn get(&self, layer: usize, x: u32, y: u32) -> u32 {
+        if x >= self.width || y >= self.height { return 0 }
+        self.layers[layer].tiles[(y * self.width + x) as usize]
+    }
+
+    pub fn set(&mut self, layer: usize, x: u32, y: u32, id: u32) {
+        if x >= self.width || y >= self.height { return }
+        self.layers[layer].tiles[(y * self.width + x) as usize] = id;
+        self.version += 1;
+    }
+
+    pub fn tileset_for(&self, id: u32) -> Option<usize> {
+        if id == 0 { return None }
+        self.tilesets.iter().position(|t| t.contains(id))
+    }
+
+    pub fn property(&self, id: u32, name: &str) -> Option<&str> {
+        self.tileset_for(id).and_then(|t| self.tilesets[t].property(id, name))
+    }
+
+    // Chunks across and up
+    pub fn chunks(&self) -> (u32, u32) {
+        ((self.width + CHUNK_SIZE - 1) / CHUNK_SIZE, (self.height + CHUNK_SIZE - 1) / CHUNK_SIZE)
+    }
+
+    // Which tiles are solid, going by a tile property (ex. "solid"). A tile in any layer counts.
+    pub fn collision(&self, property: &str) -> TileCollision {
+        let mut solid = vec![false; (self.width * self.height) as usize];
+        for layer in self.layers.iter() {
+            for (i, &id) in layer.tiles.iter().enumerate() {
+                if self.property(id, property).map(is_true).unwrap_or(false) {
+                    solid[i] = true;
+                }
+            }
+        }
+        TileCollision {
+            width: self.width,
+            height: self.height,
+            tile_size: self.tile_size,
+            solid: solid,
+        }
+    }
+}
+
+fn is_true(s: &str) -> bool {
+    s == "true" || s == "1"
+}
+
+impl specs::Component for Tilemap {
+    type Storage = specs::HashMapStorage<Tilemap>;
+}
+
+#[derive(Clone, Debug)]
+// Where things can't go, a tile at a time. Sits on the same entity as its Tilemap, and works in the map's
+// own units (from its bottom left corner, y going up).
+pub struct TileCollision {
+    width: u32,
+    height: u32,
+    tile_size: (f32, f32),
+    solid: Vec<bool>, // Rows from the top, like the map
+}
+
+impl TileCollision {
+    // x from the left, y from the top, like Tilemap::get. Off the map counts as solid, so nothing falls out.
+    pub fn is_solid(&self, x: i64, y: i64) -> bool {
+        if x < 0 || y < 0 || x >= self.width as i64 || y >= self.height as i64 { return true }
+        self.solid[(y as u32 * self.width + x as u32) as usize]
+    }
+
+    // The tile a point's in
+    pub fn tile_at(&self, x: f32, y: f32) -> (i64, i64) {
+        let tx = (x / self.tile_size.0).floor() as i64;
+        let ty = self.height as i64 - 1 - (y / self.tile_size.1).floor() as i64;
+        (tx, ty)
+    }
+
+    pub fn solid_at(&self, x: f32, y: f32) -> bool {
+        let (tx, ty) = self.tile_at(x, y);
+        self.is_solid(tx, ty)
+    }
+
+    // Whether a box (bottom left corner and size) touches any solid tile
+    pub fn overlaps(&self, x: f32, y: f32, w: f32, h: f32) -> bool {
+        // Just short of the far edges, so a box sitting exactly on a tile boundary doesn't count as in the next tile
+        let (left, top) = self.tile_at(x, y + h - 0.001);
+        let (right, bottom) = self.tile_at(x + w - 0.001, y);
+        for ty in top..bottom + 1 {
+            for tx in left..right + 1 {
+                if self.is_solid(tx, ty) { return true }
+            }
+        }
+        false
+    }
+}
+
+impl specs::Component for TileCollision {
+    type Storage = specs::HashMapStorage<TileCollision>;
+}
@@ -0,0 +1,158 @@
+///! Keeps track of the fonts the game uses, by name
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use glium::{Surface, Rect};
+use glium::backend::Facade;
+use cgmath::{Matrix4, Vector3};
+use fontae::{self, Font, SimpleText};
+use components::Align;
+
+// Starting size of each text's glyph cache. They grow by themselves.
+const CACHE_SIZE: (u32, u32) = (256, 256);
+
+// Text never wraps on its own; use newlines
+pub const NO_WRAP: u32 = ::std::i32::MAX as u32;
+
+// Fonts live in "data/fonts". A name (ex. "basic") is looked up as "basic.fnt" (a bitmap font)
+// first, then "basic.ttf".
+pub struct FontLibrary {
+    fonts: HashMap<String, Font<'static>>,
+    failed: HashSet<String>, // Not tried again
+    errors: Vec<String>, // Waiting for someone with a logger
+}
+
+impl FontLibrary {
+    pub fn new() -> FontLibrary {
+        FontLibrary {
+            fonts: HashMap::new(),
+            failed: HashSet::new(),
+            errors: vec![],
+        }
+    }
+
+    // For fonts that need more setup than load does (fallbacks, SDF, grid fonts...)
+    pub fn insert<S: Into<String>>(&mut self, name: S, font: Font<'static>) {
+        let name = name.into();
+        self.failed.remove(&name);
+        self.fonts.insert(name, font);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Font<'static>> {
+        self.fonts.get(name)
+    }
+
+    // Gets a font, loading it the first time it's asked for.
+    // A font that can't be loaded is None from then on, and what went wrong goes in errors.
+    pub fn load<F: Facade>(&mut self, f: &F, name: &str) -> Option<&Font<'static>> {
+        if !self.fonts.contains_key(name) && !self.failed.contains(name) {
+            let base = Path::new("data").join("fonts").join(name);
+            let (bitmap, outline) = (base.with_extension("fnt"), base.with_extension("ttf"));
+            let font = if bitmap.exists() {
+                Font::from_bmfont(f, &bitmap).map_err(|e| format!("Failed to load bitmap font {}: {:?}", bitmap.display(), e))
+            } else if outline.exists() {
+                Ok(Font::new(f, &outline, CACHE_SIZE))
+            } else {
+                Err(format!("No font called {} (looked for {} and {})", name, bitmap.display(), outline.display()))
+            };
+            match font {
+                Ok(font) => { self.fonts.insert(name.to_string(), font); },
+                Err(e) => {
+                    self.failed.insert(name.to_string());
+                    self.errors.push(e);
+                }
+            }
+        }
+        self.fonts.get(name)
+    }
+
+    // Everything that went wrong loading since the last time this was called
+    pub fn take_errors(&mut self) -> Vec<String> {
+        ::std::mem::replace(&mut self.errors, vec![])
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+// What a text's glyph cache belongs to
+pub enum TextKey {
+    Entity(u32), // ID. The entity's text can change and keep its glyphs.
+    Content(String), // For texts that don't have anything better to go by (ex. GUI labels)
+}
+
+// A glyph cache for every text being drawn, so texts sharing a font don't keep throwing each other's glyphs out.
+// Texts that go a frame without being drawn lose theirs.
+pub struct TextCaches {
+    texts: HashMap<(String, TextKey), (fontae::Text<SimpleText>, bool)>, // By font and key. The bool is whether it's been drawn this frame.
+}
+
+impl TextCaches {
+    pub fn new() -> TextCaches {
+        TextCaches {
+            texts: HashMap::new(),
+        }
+    }
+
+    // The text to draw content with
+    pub fn get(&mut self, font: &str, key: TextKey, content: SimpleText) -> &mut fontae::Text<SimpleText> {
+        let entry = self.texts.entry((font.to_string(), key)).or_insert_with(|| (fontae::Text::new(SimpleText::new("", [1.0; 4])), false));
+        entry.0.content = content;
+        entry.1 = true;
+        &mut entry.0
+    }
+
+    pub fn end_frame(&mut self) {
+        self.texts.retain(|_, t| t.1);
+        for t in self.texts.values_mut() {
+            t.1 = false;
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.texts.len()
+    }
+}
+
+// Draws a line of text lined up against the origin of each matrix, the way align says.
+// Each matrix goes with the viewport to draw it in (None for the whole surface).
+pub fn draw_text<F: Facade, S: Surface>(f: &F, surface: &mut S, fonts: &mut FontLibrary, texts: &mut TextCaches, font_name: &str,
+                                        key: TextKey, content: SimpleText, size: f32, align: Align, matrices: &[(Matrix4<f32>, Option<Rect>)]) {
+    use cgmath::conv::*;
+
+    // Missing fonts get logged by whoever owns the library
+    let font = match fonts.load(f, font_name) {
+        Some(font) => font,
+        None => return
+    };
+    let (width, _) = font.measure(&content, size, NO_WRAP);
+    let align_m = Matrix4::from_translation(Vector3::new(match align {
+        Align::Left => 0.0,
+        Align::Center => -width / 2.0,
+        Align::Right => -width,
+    }, 0.0, 0.0));
+    let cached = texts.get(font_name, key, content);
+    for &(m, viewport) in matrices.iter() {
+        font.draw_in_viewport(surface, cached, size, NO_WRAP, array4x4(m * align_m), viewport);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fontae::SimpleText;
+    use super::{TextCaches, TextKey};
+
+    #[test]
+    fn texts_keep_their_own_caches() {
+        let mut caches = TextCaches::new();
+        caches.get("basic", TextKey::Entity(1), SimpleText::new("12", [1.0; 4]));
+        caches.get("basic", TextKey::Entity(2), SimpleText::new("Bob", [1.0; 4]));
+        caches.get("basic", TextKey::Entity(1), SimpleText::new("13", [1.0; 4]));
+        assert_eq!(caches.len(), 2);
+        caches.end_frame();
+
+        // Only the first one gets drawn again
+        assert_eq!(caches.get("basic", TextKey::Entity(1), SimpleText::new("14", [1.0; 4])).content.text, "14");
+        caches.end_frame();
+        assert_eq!(caches.len(), 1);
+        caches.end_frame();
+        assert_eq!(caches.len(), 0);
+    }
+}
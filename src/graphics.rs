@@ -2,7 +2,7 @@
 
 pub type Index = u32;
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 // A Vec<Vertex> is stored for each combination of action and frame
 pub struct Vertex {
     pub position: [f32; 2],
@@ -11,3 +11,98 @@ pub struct Vertex {
 }
 
 implement_vertex!(Vertex, position, color, tex_coords);
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+// What's different about each copy of an instanced mesh. Goes through pivot_matrix in the shader.
+pub struct Instance {
+    pub i_position: [f32; 2], // Where the pivot goes
+    pub i_pivot: [f32; 2],
+    pub i_rotation: f32, // Degrees, counterclockwise
+    pub i_scale: [f32; 2],
+    pub i_tint: [f32; 4], // Multiplies the vertex colors
+}
+
+implement_vertex!(Instance, i_position, i_pivot, i_rotation, i_scale, i_tint);
+
+use cgmath::{Matrix4, Point2, Vector2, Deg, EuclideanSpace};
+
+// Turns by degrees (counterclockwise) and scales separately along x and y, both around pivot, then
+// puts pivot down at at. Models use it with their origin as the pivot, and views with what they look at.
+// T(at) * R * S * T(-pivot), so the pivot is the one point that stays put relative to at.
+pub fn pivot_matrix(at: Point2<f32>, pivot: Point2<f32>, degrees: f32, scale: Vector2<f32>) -> Matrix4<f32> {
+    Matrix4::from_translation(at.to_vec().extend(0.0))
+        * Matrix4::from_angle_z(Deg(degrees))
+        * Matrix4::from_nonuniform_scale(scale.x, scale.y, 1.0)
+        * Matrix4::from_translation(-pivot.to_vec().extend(0.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Matrix4, Point2, Vector2, Vector4};
+    use super::pivot_matrix;
+
+    fn assert_close(a: Matrix4<f32>, b: Matrix4<f32>) {
+        let (a, b): ([[f32; 4]; 4], [[f32; 4]; 4]) = (a.into(), b.into());
+        for c in 0..4 {
+            for r in 0..4 {
+                assert!((a[c][r] - b[c][r]).abs() < 1e-5, "{:?} isn't {:?}", a, b);
+            }
+        }
+    }
+
+    fn apply(m: Matrix4<f32>, x: f32, y: f32) -> (f32, f32) {
+        let p = m * Vector4::new(x, y, 0.0, 1.0);
+        (p.x, p.y)
+    }
+
+    #[test]
+    fn identity() {
+        let m = pivot_matrix(Point2::new(0.0, 0.0), Point2::new(0.0, 0.0), 0.0, Vector2::new(1.0, 1.0));
+        assert_close(m, Matrix4::from_scale(1.0));
+        // Any pivot is fine, as long as it's put right back
+        let m = pivot_matrix(Point2::new(5.0, 7.0), Point2::new(5.0, 7.0), 0.0, Vector2::new(1.0, 1.0));
+        assert_close(m, Matrix4::from_scale(1.0));
+    }
+
+    #[test]
+    fn nonuniform_scale_around_pivot() {
+        let m = pivot_matrix(Point2::new(10.0, 10.0), Point2::new(10.0, 10.0), 0.0, Vector2::new(2.0, 3.0));
+        // Columns, cgmath style
+        assert_close(m, Matrix4::new(
+            2.0, 0.0, 0.0, 0.0,
+            0.0, 3.0, 0.0, 0.0,
+            0.0, 0.0, 1.0, 0.0,
+            -10.0, -20.0, 0.0, 1.0,
+        ));
+        let (x, y) = apply(m, 10.0, 10.0);
+        assert!((x - 10.0).abs() < 1e-5 && (y - 10.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn rotation_around_pivot() {
+        // A quarter turn around (1, 0) takes (2, 0) up to (1, 1)
+        let m = pivot_matrix(Point2::new(1.0, 0.0), Point2::new(1.0, 0.0), 90.0, Vector2::new(1.0, 1.0));
+        assert_close(m, Matrix4::new(
+            0.0, 1.0, 0.0, 0.0,
+            -1.0, 0.0, 0.0, 0.0,
+            0.0, 0.0, 1.0, 0.0,
+            1.0, -1.0, 0.0, 1.0,
+        ));
+        let (x, y) = apply(m, 2.0, 0.0);
+        assert!((x - 1.0).abs() < 1e-5 && (y - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn scale_happens_before_rotation() {
+        // Stretched along x first, then turned, so the stretch ends up along y
+        let m = pivot_matrix(Point2::new(3.0, 4.0), Point2::new(0.0, 0.0), 90.0, Vector2::new(2.0, 1.0));
+        assert_close(m, Matrix4::new(
+            0.0, 2.0, 0.0, 0.0,
+            -1.0, 0.0, 0.0, 0.0,
+            0.0, 0.0, 1.0, 0.0,
+            3.0, 4.0, 0.0, 1.0,
+        ));
+        let (x, y) = apply(m, 1.0, 1.0);
+        assert!((x - 2.0).abs() < 1e-5 && (y - 6.0).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,33 @@
+///! Everything drawn over the game in screen space: menus, HUDs, dialogue boxes...
+mod render;
+mod widgets;
+mod theme;
+
+pub use self::render::{GuiRenderer, GuiInstruction, GuiPipeIn, GuiPipeOut, create_gui_channel};
+pub use self::widgets::{Gui, GuiEvent, Widget, Label, Button, Toggle, Slider, List, Container, Direction, Grid, ScrollPanel};
+pub use self::theme::{Theme, Style, Frame, ThemeError};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+// A rectangle in GUI space. GUI space is in pixels, from the top left corner of the window, with y going down.
+// The game's View has no say in it.
+pub struct GuiRect {
+    pub x: f32,
+    pub y: f32,
+    pub w: f32,
+    pub h: f32,
+}
+
+impl GuiRect {
+    pub fn new(x: f32, y: f32, w: f32, h: f32) -> GuiRect {
+        GuiRect {
+            x: x,
+            y: y,
+            w: w,
+            h: h,
+        }
+    }
+
+    pub fn contains(&self, x: f32, y: f32) -> bool {
+        x >= self.x && y >= self.y && x < self.x + self.w && y < self.y + self.h
+    }
+}
@@ -0,0 +1,283 @@
+use std::sync::mpsc::{Sender, Receiver, channel};
+use std::collections::HashMap;
+use cgmath::{Matrix4, Ortho, Vector3};
+use glium::{Surface, Program, Texture2d, VertexBuffer, Rect, index};
+use glium::backend::Facade;
+use graphics::Vertex;
+use components::Align;
+use font::{self, FontLibrary, TextCaches, TextKey};
+use fontae::SimpleText;
+use systems::load_shaders;
+use super::GuiRect;
+
+pub type GuiPipeIn = Sender<GuiInstruction>;
+pub type GuiPipeOut = Receiver<GuiInstruction>;
+
+pub fn create_gui_channel() -> (GuiPipeIn, GuiPipeOut) {
+    channel()
+}
+
+// The GUI is immediate: everything that should be on screen gets sent again every frame.
+// Instructions are drawn in the order they're sent, so later ones go on top.
+#[allow(dead_code)]
+#[derive(Clone)]
+pub enum GuiInstruction {
+    // Rect Color
+    Panel(GuiRect, [f32; 4]),
+    // TextureName Rect UVRect(x, y, w, h; 0 to 1, from the top left) Tint
+    Image(String, GuiRect, [f32; 4], [f32; 4]),
+    // Text FontName Size Color Alignment X Y (top of the first line)
+    Text(String, String, f32, [f32; 4], Align, f32, f32),
+    // TextureName Rect Borders(left, top, right, bottom; in texture pixels) Tint Fallback
+    // Borders are drawn as they are, and the middle gets stretched to fill Rect
+    // Fallback is the color of the plain box drawn instead if the texture can't be loaded
+    Slice(String, GuiRect, [f32; 4], [f32; 4], [f32; 4]),
+    // Only draw inside Rect from now on; None draws everywhere again
+    Clip(Option<GuiRect>),
+}
+
+// Draws GuiInstructions into the GUI texture, which gets laid over the game's.
+pub struct GuiRenderer {
+    receiver: Receiver<GuiInstruction>,
+    projection: Ortho<f32>,
+    height: u32, // For flipping clip rects, since GL counts from the bottom
+    clip: Option<GuiRect>,
+    // Made on the first draw, since that's when we get a Facade
+    program: Option<Program>,
+    white: Option<Texture2d>,
+    // Loaded from "data/textures" as they're asked for. None if it couldn't be.
+    textures: HashMap<String, Option<Texture2d>>,
+    fonts: FontLibrary,
+    texts: TextCaches,
+    errors: Vec<String>, // Waiting for someone with a logger
+}
+
+impl GuiRenderer {
+    pub fn new(r: Receiver<GuiInstruction>, wsize: (u32, u32)) -> GuiRenderer {
+        GuiRenderer {
+            receiver: r,
+            projection: screen_projection(wsize.0, wsize.1),
+            height: wsize.1,
+            clip: None,
+            program: None,
+            white: None,
+            textures: HashMap::new(),
+            fonts: FontLibrary::new(),
+            texts: TextCaches::new(),
+            errors: vec![],
+        }
+    }
+
+    // Sets screen size
+    pub fn size(&mut self, w: u32, h: u32) {
+        self.projection = screen_projection(w, h);
+        self.height = h;
+    }
+
+    pub fn fonts_mut(&mut self) -> &mut FontLibrary {
+        &mut self.fonts
+    }
+
+    // Everything that went wrong loading since the last time this was called. Fonts keep their own.
+    pub fn take_errors(&mut self) -> Vec<String> {
+        ::std::mem::replace(&mut self.errors, vec![])
+    }
+
+    pub fn draw<F: Facade, S: Surface>(&mut self, f: &F, surface: &mut S) {
+        if self.program.is_none() {
+            let (vert_shd_src, frag_shd_src) = load_shaders("gui").unwrap();
+            self.program = Some(Program::from_source(f, &vert_shd_src, &frag_shd_src, None).unwrap());
+            self.white = Some(Texture2d::new(f, vec![vec![(255u8, 255u8, 255u8, 255u8)]]).unwrap());
+        }
+
+        surface.clear_color(0.0, 0.0, 0.0, 0.0);
+        self.clip = None;
+        let proj_m: Matrix4<f32> = self.projection.clone().into();
+        while let Ok(inst) = self.receiver.try_recv() {
+            match inst {
+                GuiInstruction::Panel(rect, color) => {
+                    let white = self.white.as_ref().unwrap();
+                    let scissor = self.scissor();
+                    draw_quad(f, surface, self.program.as_ref().unwrap(), white, proj_m, scissor, rect, [0.0, 0.0, 1.0, 1.0], color);
+                },
+                GuiInstruction::Image(name, rect, uv, tint) => {
+                    self.cache_texture(f, &name);
+                    let scissor = self.scissor();
+                    // A missing image is just its tint
+                    let tex = self.textures[&name].as_ref().unwrap_or(self.white.as_ref().unwrap());
+                    draw_quad(f, surface, self.program.as_ref().unwrap(), tex, proj_m, scissor, rect, uv, tint);
+                },
+                GuiInstruction::Slice(name, rect, border, tint, fallback) => {
+                    self.cache_texture(f, &name);
+                    let scissor = self.scissor();
+                    match self.textures[&name] {
+                        Some(ref tex) => for &(r, uv) in slices(rect, border, tex.dimensions()).iter() {
+                            draw_quad(f, surface, self.program.as_ref().unwrap(), tex, proj_m, scissor, r, uv, tint);
+                        },
+                        None => {
+                            let white = self.white.as_ref().unwrap();
+                            draw_quad(f, surface, self.program.as_ref().unwrap(), white, proj_m, scissor, rect, [0.0, 0.0, 1.0, 1.0], fallback);
+                        }
+                    }
+                },
+                GuiInstruction::Clip(rect) => self.clip = rect,
+                GuiInstruction::Text(text, font_name, size, color, align, x, y) => {
+                    // fontae builds text with y going up, and GUI space has it going down
+                    let text_m = Matrix4::from_translation(Vector3::new(x, y, 0.0)) * Matrix4::from_nonuniform_scale(1.0, -1.0, 1.0);
+                    let key = TextKey::Content(text.clone());
+                    // NOTE fontae doesn't know about clipping, so text isn't clipped
+                    font::draw_text(f, surface, &mut self.fonts, &mut self.texts, &font_name, key,
+                                    SimpleText::new(text, color), size, align, &[(proj_m * text_m, None)]);
+                }
+            }
+        }
+        self.texts.end_frame();
+    }
+
+    // Loads a texture the first time it's used, remembering if it couldn't be so it's only reported once
+    fn cache_texture<F: Facade>(&mut self, f: &F, name: &str) {
+        if !self.textures.contains_key(name) {
+            let tex = match load_texture(f, name) {
+                Ok(tex) => Some(tex),
+                Err(e) => {
+                    self.errors.push(e);
+                    None
+                }
+            };
+            self.textures.insert(name.to_string(), tex);
+        }
+    }
+
+    // The clip rect in framebuffer pixels
+    fn scissor(&self) -> Option<Rect> {
+        self.clip.map(|c| {
+            let left = c.x.max(0.0);
+            let top = c.y.max(0.0);
+            let bottom = (c.y + c.h).max(top).min(self.height as f32);
+            Rect {
+                left: left as u32,
+                bottom: self.height - bottom as u32,
+                width: (c.x + c.w - left).max(0.0) as u32,
+                height: (bottom - top).max(0.0) as u32,
+            }
+        })
+    }
+}
+
+// (0, 0) is the top left corner
+fn screen_projection(w: u32, h: u32) -> Ortho<f32> {
+    Ortho {
+        left: 0.0,
+        right: w as f32,
+        bottom: h as f32,
+        top: 0.0,
+        near: 0.0,
+        far: 5.0
+    }
+}
+
+// GUI textures are uploaded top row first, so uv (0, 0) is the top left of the image
+fn load_texture<F: Facade>(f: &F, name: &str) -> Result<Texture2d, String> {
+    use std::path::Path;
+    use image;
+    use glium::texture::RawImage2d;
+
+    let path = Path::new("data").join("textures").join(name);
+    let img = match image::open(&path) {
+        Ok(img) => img.to_rgba(),
+        Err(e) => return Err(format!("Failed to load texture {}: {}", path.display(), e))
+    };
+    let dims = img.dimensions();
+    Ok(Texture2d::new(f, RawImage2d::from_raw_rgba(img.into_raw(), dims)).unwrap())
+}
+
+// Cuts rect (and the texture) into 9 pieces along the borders, leaving out any empty ones.
+// If rect is smaller than the borders, they get squished to fit.
+fn slices(rect: GuiRect, border: [f32; 4], tex_size: (u32, u32)) -> Vec<(GuiRect, [f32; 4])> {
+    let (tw, th) = (tex_size.0 as f32, tex_size.1 as f32);
+    let squish_x = (rect.w / (border[0] + border[2])).min(1.0);
+    let squish_y = (rect.h / (border[1] + border[3])).min(1.0);
+    let (l, r) = (border[0] * squish_x, border[2] * squish_x);
+    let (t, b) = (border[1] * squish_y, border[3] * squish_y);
+
+    // Screen and texture edges of the three columns and rows
+    let xs = [(rect.x, l, 0.0, border[0]),
+              (rect.x + l, rect.w - l - r, border[0], tw - border[0] - border[2]),
+              (rect.x + rect.w - r, r, tw - border[2], border[2])];
+    let ys = [(rect.y, t, 0.0, border[1]),
+              (rect.y + t, rect.h - t - b, border[1], th - border[1] - border[3]),
+              (rect.y + rect.h - b, b, th - border[3], border[3])];
+
+    let mut out = vec![];
+    for &(y, h, v, vh) in ys.iter() {
+        for &(x, w, u, uw) in xs.iter() {
+            if w <= 0.0 || h <= 0.0 { continue }
+            out.push((GuiRect::new(x, y, w, h), [u / tw, v / th, uw / tw, vh / th]));
+        }
+    }
+    out
+}
+
+fn draw_quad<F: Facade, S: Surface>(f: &F, surface: &mut S, program: &Program, tex: &Texture2d, proj_m: Matrix4<f32>, scissor: Option<Rect>, r: GuiRect, uv: [f32; 4], color: [f32; 4]) {
+    use cgmath::conv::*;
+    use glium::{Blend, DrawParameters};
+    use glium::uniforms::MagnifySamplerFilter;
+
+    let (u0, v0, u1, v1) = (uv[0], uv[1], uv[0] + uv[2], uv[1] + uv[3]);
+    let vertices = [
+        Vertex { position: [r.x, r.y], color: color, tex_coords: [u0, v0] },
+        Vertex { position: [r.x + r.w, r.y], color: color, tex_coords: [u1, v0] },
+        Vertex { position: [r.x + r.w, r.y + r.h], color: color, tex_coords: [u1, v1] },
+        Vertex { position: [r.x, r.y + r.h], color: color, tex_coords: [u0, v1] },
+    ];
+    let vb = VertexBuffer::new(f, &vertices).unwrap();
+    let ib = index::IndexBuffer::new(f, index::PrimitiveType::TrianglesList, &[0u32, 1, 2, 0, 2, 3]).unwrap();
+    let uniforms = uniform! {
+        matrix: array4x4(proj_m),
+        tex: tex.sampled().magnify_filter(MagnifySamplerFilter::Nearest)
+    };
+    let params = DrawParameters {
+        blend: Blend::alpha_blending(),
+        scissor: scissor,
+        ..Default::default()
+    };
+    surface.draw(&vb, &ib, program, &uniforms, &params).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use gui::GuiRect;
+    use super::slices;
+
+    #[test]
+    fn nine_slices() {
+        // A 16x16 texture with 4 pixel borders, stretched over 100x50
+        let s = slices(GuiRect::new(10.0, 20.0, 100.0, 50.0), [4.0, 4.0, 4.0, 4.0], (16, 16));
+        assert_eq!(s.len(), 9);
+        // Top left corner keeps its size
+        assert_eq!(s[0], (GuiRect::new(10.0, 20.0, 4.0, 4.0), [0.0, 0.0, 0.25, 0.25]));
+        // The middle takes up the rest
+        assert_eq!(s[4], (GuiRect::new(14.0, 24.0, 92.0, 42.0), [0.25, 0.25, 0.5, 0.5]));
+        // Bottom right corner
+        assert_eq!(s[8], (GuiRect::new(106.0, 66.0, 4.0, 4.0), [0.75, 0.75, 0.25, 0.25]));
+    }
+
+    #[test]
+    fn three_slices() {
+        // No top or bottom border, so only the one row
+        let s = slices(GuiRect::new(0.0, 0.0, 40.0, 10.0), [8.0, 0.0, 8.0, 0.0], (32, 8));
+        assert_eq!(s.len(), 3);
+        assert_eq!(s[0], (GuiRect::new(0.0, 0.0, 8.0, 10.0), [0.0, 0.0, 0.25, 1.0]));
+        assert_eq!(s[1], (GuiRect::new(8.0, 0.0, 24.0, 10.0), [0.25, 0.0, 0.5, 1.0]));
+        assert_eq!(s[2], (GuiRect::new(32.0, 0.0, 8.0, 10.0), [0.75, 0.0, 0.25, 1.0]));
+    }
+
+    #[test]
+    fn squished_borders() {
+        // Narrower than both borders together, so they share what there is and the middle disappears
+        let s = slices(GuiRect::new(0.0, 0.0, 4.0, 20.0), [4.0, 0.0, 4.0, 0.0], (16, 16));
+        assert_eq!(s.len(), 2);
+        assert_eq!(s[0].0, GuiRect::new(0.0, 0.0, 2.0, 20.0));
+        assert_eq!(s[1].0, GuiRect::new(2.0, 0.0, 2.0, 20.0));
+    }
+}
@@ -0,0 +1,278 @@
+///! How widgets look, loaded from "data/themes" so menus can be reskinned without touching code.
+///!
+///! A theme is a TOML file. The [default] table sets everything, and a table per widget type
+///! ([label], [button], [toggle], [slider], [list], [container], [grid]) overrides whatever it mentions:
+///!
+///! [default]
+///! font = "basic"
+///! text_size = 16
+///! text_color = [1.0, 1.0, 1.0, 1.0]
+///! padding = 6
+///! spacing = 4
+///! panel_color = [0.1, 0.1, 0.15, 0.85]
+///! focus_color = [0.3, 0.3, 0.5, 0.95]
+///! accent_color = [0.9, 0.6, 0.2, 1.0]
+///!
+///! [button]
+///! frame = { texture = "ui/button.png", border = [4, 4, 4, 4] } # left, top, right, bottom
+///! focus_frame = { texture = "ui/button_focus.png", border = [4, 4, 4, 4], tint = [1.0, 1.0, 1.0, 1.0] }
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use toml::{self, Value, Table};
+use super::{GuiRect, GuiInstruction};
+
+// A texture drawn with its borders kept as they are and its middle stretched.
+// Set the top and bottom borders to 0 for a horizontal three-slice, or left and right for a vertical one.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Frame {
+    pub texture: String, // Relative to "data/textures"
+    pub border: [f32; 4], // Left Top Right Bottom, in texture pixels
+    pub tint: [f32; 4],
+}
+
+impl Frame {
+    pub fn nine<S: Into<String>>(texture: S, left: f32, top: f32, right: f32, bottom: f32) -> Frame {
+        Frame {
+            texture: texture.into(),
+            border: [left, top, right, bottom],
+            tint: [1.0, 1.0, 1.0, 1.0],
+        }
+    }
+
+    // Stretches only horizontally between the two ends
+    pub fn three_horizontal<S: Into<String>>(texture: S, left: f32, right: f32) -> Frame {
+        Frame::nine(texture, left, 0.0, right, 0.0)
+    }
+
+    // Stretches only vertically between the two ends
+    pub fn three_vertical<S: Into<String>>(texture: S, top: f32, bottom: f32) -> Frame {
+        Frame::nine(texture, 0.0, top, 0.0, bottom)
+    }
+
+    // fallback is the color of the plain box drawn if the texture can't be loaded
+    pub fn instruction(&self, rect: GuiRect, fallback: [f32; 4]) -> GuiInstruction {
+        GuiInstruction::Slice(self.texture.clone(), rect, self.border, self.tint, fallback)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Style {
+    pub font: String,
+    pub text_size: f32,
+    pub text_color: [f32; 4],
+    pub padding: f32, // Between a widget's edge and its contents
+    pub spacing: f32, // Between widgets in a container
+    pub panel_color: [f32; 4],
+    pub focus_color: [f32; 4], // Background of whatever has focus
+    pub accent_color: [f32; 4], // Slider fill, toggle knob, selected list item
+    pub frame: Option<Frame>, // Drawn instead of a plain panel_color box
+    pub focus_frame: Option<Frame>, // When focused. Falls back to frame tinted with focus_color.
+}
+
+impl Default for Style {
+    fn default() -> Style {
+        Style {
+            font: "basic".into(),
+            text_size: 16.0,
+            text_color: [1.0, 1.0, 1.0, 1.0],
+            padding: 6.0,
+            spacing: 4.0,
+            panel_color: [0.1, 0.1, 0.15, 0.85],
+            focus_color: [0.3, 0.3, 0.5, 0.95],
+            accent_color: [0.9, 0.6, 0.2, 1.0],
+            frame: None,
+            focus_frame: None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ThemeError {
+    Io(io::Error),
+    Parse(String),
+    Value(String), // Something had the wrong type
+}
+
+impl From<io::Error> for ThemeError {
+    fn from(e: io::Error) -> ThemeError {
+        ThemeError::Io(e)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Theme {
+    pub default: Style,
+    widgets: HashMap<String, Style>,
+}
+
+impl Theme {
+    // Loads "data/themes/<name>.toml"
+    pub fn load(name: &str) -> Result<Theme, ThemeError> {
+        let path = Path::new("data").join("themes").join(name).with_extension("toml");
+        let mut s = String::new();
+        try!(try!(File::open(&path)).read_to_string(&mut s));
+        Theme::parse(&s)
+    }
+
+    pub fn parse(s: &str) -> Result<Theme, ThemeError> {
+        let mut parser = toml::Parser::new(s);
+        let table = match parser.parse() {
+            Some(t) => t,
+            None => {
+                let e = &parser.errors[0];
+                let (line, col) = parser.to_linecol(e.lo);
+                return Err(ThemeError::Parse(format!("{}:{}: {}", line + 1, col + 1, e.desc)));
+            }
+        };
+
+        let mut theme = Theme::default();
+        if let Some(t) = table.get("default") {
+            try!(read_style(&mut theme.default, try!(as_table(t, "default"))));
+        }
+        for (name, t) in table.iter() {
+            if name == "default" { continue }
+            let mut style = theme.default.clone();
+            try!(read_style(&mut style, try!(as_table(t, name))));
+            theme.widgets.insert(name.clone(), style);
+        }
+        Ok(theme)
+    }
+
+    pub fn set<S: Into<String>>(&mut self, widget: S, style: Style) {
+        self.widgets.insert(widget.into(), style);
+    }
+
+    // The style for a widget type (ex. "button"), or the default if the theme doesn't mention it
+    pub fn style(&self, widget: &str) -> &Style {
+        self.widgets.get(widget).unwrap_or(&self.default)
+    }
+}
+
+fn as_table<'a>(v: &'a Value, key: &str) -> Result<&'a Table, ThemeError> {
+    v.as_table().ok_or(ThemeError::Value(format!("{} should be a table", key)))
+}
+
+// TOML is picky about 16 and 16.0 being different things, but we aren't
+fn as_f32(v: &Value, key: &str) -> Result<f32, ThemeError> {
+    match *v {
+        Value::Float(f) => Ok(f as f32),
+        Value::Integer(i) => Ok(i as f32),
+        _ => Err(ThemeError::Value(format!("{} should be a number", key)))
+    }
+}
+
+fn as_floats(v: &Value, key: &str, n: usize) -> Result<Vec<f32>, ThemeError> {
+    let err = || ThemeError::Value(format!("{} should be an array of {} numbers", key, n));
+    let arr = try!(v.as_slice().ok_or(err()));
+    if arr.len() != n { return Err(err()) }
+    let mut out = vec![];
+    for x in arr {
+        out.push(try!(as_f32(x, key)));
+    }
+    Ok(out)
+}
+
+fn as_color(v: &Value, key: &str) -> Result<[f32; 4], ThemeError> {
+    let c = try!(as_floats(v, key, 4));
+    Ok([c[0], c[1], c[2], c[3]])
+}
+
+fn as_frame(v: &Value, key: &str) -> Result<Frame, ThemeError> {
+    let t = try!(as_table(v, key));
+    let texture = match t.get("texture").and_then(|v| v.as_str()) {
+        Some(s) => s.to_string(),
+        None => return Err(ThemeError::Value(format!("{} needs a texture", key)))
+    };
+    let b = match t.get("border") {
+        Some(v) => try!(as_floats(v, key, 4)),
+        None => vec![0.0; 4]
+    };
+    let mut frame = Frame::nine(texture, b[0], b[1], b[2], b[3]);
+    if let Some(v) = t.get("tint") {
+        frame.tint = try!(as_color(v, key));
+    }
+    Ok(frame)
+}
+
+// Overwrites whatever the table mentions, leaving the rest alone
+fn read_style(style: &mut Style, t: &Table) -> Result<(), ThemeError> {
+    for (key, v) in t.iter() {
+        let key = key.as_str();
+        match key {
+            "font" => style.font = try!(v.as_str().ok_or(ThemeError::Value("font should be a string".into()))).to_string(),
+            "text_size" => style.text_size = try!(as_f32(v, key)),
+            "text_color" => style.text_color = try!(as_color(v, key)),
+            "padding" => style.padding = try!(as_f32(v, key)),
+            "spacing" => style.spacing = try!(as_f32(v, key)),
+            "panel_color" => style.panel_color = try!(as_color(v, key)),
+            "focus_color" => style.focus_color = try!(as_color(v, key)),
+            "accent_color" => style.accent_color = try!(as_color(v, key)),
+            "frame" => style.frame = Some(try!(as_frame(v, key))),
+            "focus_frame" => style.focus_frame = Some(try!(as_frame(v, key))),
+            _ => return Err(ThemeError::Value(format!("Unknown theme key {}", key)))
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Theme, ThemeError, Frame};
+
+    #[test]
+    fn widgets_override_the_default() {
+        let theme = Theme::parse(r#"
+            [default]
+            font = "big"
+            text_size = 20
+            padding = 2.5
+
+            [button]
+            text_size = 12.0
+            frame = { texture = "ui/button.png", border = [4, 4, 4, 4] }
+            focus_frame = { texture = "ui/button_focus.png", tint = [1.0, 0.5, 0.5, 1.0] }
+        "#).unwrap();
+        assert_eq!(theme.default.font, "big");
+        assert_eq!(theme.default.text_size, 20.0);
+        assert_eq!(theme.default.padding, 2.5);
+
+        let button = theme.style("button");
+        assert_eq!(button.font, "big");
+        assert_eq!(button.text_size, 12.0);
+        assert_eq!(button.padding, 2.5);
+        assert_eq!(button.frame, Some(Frame::nine("ui/button.png", 4.0, 4.0, 4.0, 4.0)));
+        let focus = button.focus_frame.as_ref().unwrap();
+        assert_eq!(focus.border, [0.0; 4]);
+        assert_eq!(focus.tint, [1.0, 0.5, 0.5, 1.0]);
+
+        // Anything not mentioned gets the default
+        assert_eq!(theme.style("slider").text_size, 20.0);
+        assert!(theme.style("slider").frame.is_none());
+    }
+
+    #[test]
+    fn bad_themes() {
+        match Theme::parse("[default]\ntext_size = ") {
+            Err(ThemeError::Parse(e)) => assert!(e.starts_with("2:")),
+            r => panic!("Expected a parse error, got {:?}", r)
+        }
+        match Theme::parse("[default]\ntext_color = [1.0, 1.0, 1.0]") {
+            Err(ThemeError::Value(_)) => (),
+            r => panic!("Expected a value error, got {:?}", r)
+        }
+        match Theme::parse("[button]\nfont = 3") {
+            Err(ThemeError::Value(_)) => (),
+            r => panic!("Expected a value error, got {:?}", r)
+        }
+        match Theme::parse("[button]\nframe = { border = [1, 1, 1, 1] }") {
+            Err(ThemeError::Value(_)) => (),
+            r => panic!("Expected a value error, got {:?}", r)
+        }
+        match Theme::parse("[button]\nshininess = 11") {
+            Err(ThemeError::Value(_)) => (),
+            r => panic!("Expected a value error, got {:?}", r)
+        }
+    }
+}
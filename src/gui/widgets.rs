@@ -0,0 +1,771 @@
+///! A retained widget toolkit, driven entirely by the 12 virtual keys.
+///!
+///! The D-pad moves focus around (to whichever focusable widget is closest in that direction),
+///! Select/A activate, and B backs out. Focused widgets get first dibs on keys, which is how
+///! sliders and lists use the D-pad themselves.
+use input::Key;
+use components::Align;
+use super::{GuiRect, GuiInstruction, GuiPipeIn};
+use super::theme::{Theme, Style};
+
+// What the player did to the GUI. Widgets are told apart by the ids they're made with.
+#[derive(Clone, Debug, PartialEq)]
+pub enum GuiEvent {
+    Activated(String), // A button
+    Toggled(String, bool),
+    Changed(String, f32), // A slider
+    Selected(String, usize), // A list item was activated
+    Back, // B was pressed, and nobody wanted it
+}
+
+pub trait Widget {
+    // How much room this would like. Containers decide how much it actually gets.
+    fn measure(&self, theme: &Theme) -> (f32, f32);
+    fn layout(&mut self, rect: GuiRect, theme: &Theme);
+    fn rect(&self) -> GuiRect;
+    fn draw(&self, out: &GuiPipeIn, theme: &Theme, focused: Option<&Widget>);
+
+    fn focusable(&self) -> bool { false }
+    // Only called on the focused widget. Returns true if it used the key.
+    fn handle(&mut self, _: &Key, _: &mut Vec<GuiEvent>) -> bool { false }
+    // Makes sure rect (something inside this widget) can be seen. Scroll panels care.
+    fn reveal(&mut self, rect: GuiRect, theme: &Theme) {
+        for c in self.children_mut().iter_mut() {
+            c.reveal(rect, theme);
+        }
+    }
+
+    fn children(&self) -> &[Box<Widget>] { &[] }
+    fn children_mut(&mut self) -> &mut [Box<Widget>] { &mut [] }
+}
+
+// Compares by address, since widgets don't have any other identity
+fn same(a: &Widget, b: Option<&Widget>) -> bool {
+    match b {
+        Some(b) => a as *const Widget as *const u8 == b as *const Widget as *const u8,
+        None => false
+    }
+}
+
+fn draw_text(out: &GuiPipeIn, style: &Style, text: &str, rect: GuiRect, align: Align) {
+    let x = match align {
+        Align::Left => rect.x + style.padding,
+        Align::Center => rect.x + rect.w / 2.0,
+        Align::Right => rect.x + rect.w - style.padding,
+    };
+    let y = rect.y + (rect.h - style.text_size) / 2.0;
+    out.send(GuiInstruction::Text(text.into(), style.font.clone(), style.text_size, style.text_color, align, x, y)).unwrap();
+}
+
+fn draw_background(out: &GuiPipeIn, style: &Style, rect: GuiRect, focused: bool) {
+    let inst = match (focused, &style.frame, &style.focus_frame) {
+        (true, _, &Some(ref frame)) => frame.instruction(rect, style.focus_color),
+        (true, &Some(ref frame), &None) => {
+            let mut frame = frame.clone();
+            frame.tint = style.focus_color;
+            frame.instruction(rect, style.focus_color)
+        },
+        (false, &Some(ref frame), _) => frame.instruction(rect, style.panel_color),
+        (true, &None, &None) => GuiInstruction::Panel(rect, style.focus_color),
+        (false, &None, _) => GuiInstruction::Panel(rect, style.panel_color),
+    };
+    out.send(inst).unwrap();
+}
+
+fn line_height(style: &Style) -> f32 {
+    style.text_size + style.padding * 2.0
+}
+
+pub struct Label {
+    pub text: String,
+    pub align: Align,
+    rect: GuiRect,
+}
+
+impl Label {
+    pub fn new<S: Into<String>>(text: S) -> Box<Label> {
+        Box::new(Label {
+            text: text.into(),
+            align: Align::Left,
+            rect: GuiRect::new(0.0, 0.0, 0.0, 0.0),
+        })
+    }
+
+    pub fn centered<S: Into<String>>(text: S) -> Box<Label> {
+        let mut l = Label::new(text);
+        l.align = Align::Center;
+        l
+    }
+}
+
+impl Widget for Label {
+    fn measure(&self, theme: &Theme) -> (f32, f32) { (0.0, line_height(theme.style("label"))) }
+    fn layout(&mut self, rect: GuiRect, _: &Theme) { self.rect = rect }
+    fn rect(&self) -> GuiRect { self.rect }
+    fn draw(&self, out: &GuiPipeIn, theme: &Theme, _: Option<&Widget>) {
+        let style = theme.style("label");
+        draw_text(out, style, &self.text, self.rect, self.align);
+    }
+}
+
+pub struct Button {
+    pub id: String,
+    pub text: String,
+    rect: GuiRect,
+}
+
+impl Button {
+    pub fn new<I: Into<String>, S: Into<String>>(id: I, text: S) -> Box<Button> {
+        Box::new(Button {
+            id: id.into(),
+            text: text.into(),
+            rect: GuiRect::new(0.0, 0.0, 0.0, 0.0),
+        })
+    }
+}
+
+impl Widget for Button {
+    fn measure(&self, theme: &Theme) -> (f32, f32) { (0.0, line_height(theme.style("button"))) }
+    fn layout(&mut self, rect: GuiRect, _: &Theme) { self.rect = rect }
+    fn rect(&self) -> GuiRect { self.rect }
+    fn draw(&self, out: &GuiPipeIn, theme: &Theme, focused: Option<&Widget>) {
+        let style = theme.style("button");
+        draw_background(out, style, self.rect, same(self, focused));
+        draw_text(out, style, &self.text, self.rect, Align::Center);
+    }
+    fn focusable(&self) -> bool { true }
+    fn handle(&mut self, key: &Key, events: &mut Vec<GuiEvent>) -> bool {
+        match *key {
+            Key::A | Key::Select => {
+                events.push(GuiEvent::Activated(self.id.clone()));
+                true
+            },
+            _ => false
+        }
+    }
+}
+
+pub struct Toggle {
+    pub id: String,
+    pub text: String,
+    pub on: bool,
+    rect: GuiRect,
+}
+
+impl Toggle {
+    pub fn new<I: Into<String>, S: Into<String>>(id: I, text: S, on: bool) -> Box<Toggle> {
+        Box::new(Toggle {
+            id: id.into(),
+            text: text.into(),
+            on: on,
+            rect: GuiRect::new(0.0, 0.0, 0.0, 0.0),
+        })
+    }
+}
+
+impl Widget for Toggle {
+    fn measure(&self, theme: &Theme) -> (f32, f32) { (0.0, line_height(theme.style("toggle"))) }
+    fn layout(&mut self, rect: GuiRect, _: &Theme) { self.rect = rect }
+    fn rect(&self) -> GuiRect { self.rect }
+    fn draw(&self, out: &GuiPipeIn, theme: &Theme, focused: Option<&Widget>) {
+        let style = theme.style("toggle");
+        draw_background(out, style, self.rect, same(self, focused));
+        draw_text(out, style, &self.text, self.rect, Align::Left);
+        // A little box on the right, filled in when on
+        let size = style.text_size;
+        let knob = GuiRect::new(self.rect.x + self.rect.w - style.padding - size, self.rect.y + style.padding, size, size);
+        out.send(GuiInstruction::Panel(knob, style.panel_color)).unwrap();
+        if self.on {
+            let inner = GuiRect::new(knob.x + 3.0, knob.y + 3.0, knob.w - 6.0, knob.h - 6.0);
+            out.send(GuiInstruction::Panel(inner, style.accent_color)).unwrap();
+        }
+    }
+    fn focusable(&self) -> bool { true }
+    fn handle(&mut self, key: &Key, events: &mut Vec<GuiEvent>) -> bool {
+        match *key {
+            Key::A | Key::Select => {
+                self.on = !self.on;
+                events.push(GuiEvent::Toggled(self.id.clone(), self.on));
+                true
+            },
+            _ => false
+        }
+    }
+}
+
+// Left and Right nudge the value by step
+pub struct Slider {
+    pub id: String,
+    pub text: String,
+    pub value: f32,
+    pub min: f32,
+    pub max: f32,
+    pub step: f32,
+    rect: GuiRect,
+}
+
+impl Slider {
+    pub fn new<I: Into<String>, S: Into<String>>(id: I, text: S, value: f32, min: f32, max: f32, step: f32) -> Box<Slider> {
+        Box::new(Slider {
+            id: id.into(),
+            text: text.into(),
+            value: value,
+            min: min,
+            max: max,
+            step: step,
+            rect: GuiRect::new(0.0, 0.0, 0.0, 0.0),
+        })
+    }
+}
+
+impl Widget for Slider {
+    fn measure(&self, theme: &Theme) -> (f32, f32) { (0.0, line_height(theme.style("slider"))) }
+    fn layout(&mut self, rect: GuiRect, _: &Theme) { self.rect = rect }
+    fn rect(&self) -> GuiRect { self.rect }
+    fn draw(&self, out: &GuiPipeIn, theme: &Theme, focused: Option<&Widget>) {
+        let style = theme.style("slider");
+        draw_background(out, style, self.rect, same(self, focused));
+        // Label on the left half, track on the right
+        let half = self.rect.w / 2.0;
+        draw_text(out, style, &self.text, GuiRect::new(self.rect.x, self.rect.y, half, self.rect.h), Align::Left);
+        let track = GuiRect::new(self.rect.x + half, self.rect.y + self.rect.h / 2.0 - 2.0, half - style.padding, 4.0);
+        out.send(GuiInstruction::Panel(track, style.panel_color)).unwrap();
+        let t = if self.max > self.min { (self.value - self.min) / (self.max - self.min) } else { 0.0 };
+        out.send(GuiInstruction::Panel(GuiRect::new(track.x, track.y, track.w * t, track.h), style.accent_color)).unwrap();
+    }
+    fn focusable(&self) -> bool { true }
+    fn handle(&mut self, key: &Key, events: &mut Vec<GuiEvent>) -> bool {
+        let value = match *key {
+            Key::Left => (self.value - self.step).max(self.min),
+            Key::Right => (self.value + self.step).min(self.max),
+            _ => return false
+        };
+        if value != self.value {
+            self.value = value;
+            events.push(GuiEvent::Changed(self.id.clone(), value));
+        }
+        true
+    }
+}
+
+// A column of choices with one selected. Up and Down move the selection until it hits
+// either end, and then focus moves on like usual.
+pub struct List {
+    pub id: String,
+    pub items: Vec<String>,
+    pub selected: usize,
+    rect: GuiRect,
+}
+
+impl List {
+    pub fn new<I: Into<String>>(id: I, items: Vec<String>) -> Box<List> {
+        Box::new(List {
+            id: id.into(),
+            items: items,
+            selected: 0,
+            rect: GuiRect::new(0.0, 0.0, 0.0, 0.0),
+        })
+    }
+
+    fn item_rect(&self, i: usize, style: &Style) -> GuiRect {
+        let h = line_height(style);
+        GuiRect::new(self.rect.x, self.rect.y + h * i as f32, self.rect.w, h)
+    }
+}
+
+impl Widget for List {
+    fn measure(&self, theme: &Theme) -> (f32, f32) { (0.0, line_height(theme.style("list")) * self.items.len() as f32) }
+    fn layout(&mut self, rect: GuiRect, _: &Theme) { self.rect = rect }
+    fn rect(&self) -> GuiRect { self.rect }
+    fn draw(&self, out: &GuiPipeIn, theme: &Theme, focused: Option<&Widget>) {
+        let style = theme.style("list");
+        draw_background(out, style, self.rect, false);
+        for (i, item) in self.items.iter().enumerate() {
+            let r = self.item_rect(i, style);
+            if i == self.selected {
+                let color = if same(self, focused) { style.focus_color } else { style.accent_color };
+                out.send(GuiInstruction::Panel(r, color)).unwrap();
+            }
+            draw_text(out, style, item, r, Align::Left);
+        }
+    }
+    fn focusable(&self) -> bool { true }
+    fn handle(&mut self, key: &Key, events: &mut Vec<GuiEvent>) -> bool {
+        match *key {
+            Key::Up if self.selected > 0 => {
+                self.selected -= 1;
+                true
+            },
+            Key::Down if self.selected + 1 < self.items.len() => {
+                self.selected += 1;
+                true
+            },
+            Key::A | Key::Select if !self.items.is_empty() => {
+                events.push(GuiEvent::Selected(self.id.clone(), self.selected));
+                true
+            },
+            _ => false
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Vertical, // A column
+    Horizontal, // A row, with everyone getting the same width
+}
+
+// Lines its children up
+pub struct Container {
+    pub direction: Direction,
+    children: Vec<Box<Widget>>,
+    rect: GuiRect,
+}
+
+impl Container {
+    pub fn column(children: Vec<Box<Widget>>) -> Box<Container> {
+        Box::new(Container {
+            direction: Direction::Vertical,
+            children: children,
+            rect: GuiRect::new(0.0, 0.0, 0.0, 0.0),
+        })
+    }
+
+    pub fn row(children: Vec<Box<Widget>>) -> Box<Container> {
+        Box::new(Container {
+            direction: Direction::Horizontal,
+            children: children,
+            rect: GuiRect::new(0.0, 0.0, 0.0, 0.0),
+        })
+    }
+}
+
+impl Widget for Container {
+    fn measure(&self, theme: &Theme) -> (f32, f32) {
+        let style = theme.style("container");
+        let sizes = self.children.iter().map(|c| c.measure(theme));
+        let gaps = style.spacing * (self.children.len().max(1) - 1) as f32;
+        match self.direction {
+            Direction::Vertical => {
+                let (w, h) = sizes.fold((0.0f32, 0.0f32), |(w, h), (cw, ch)| (w.max(cw), h + ch));
+                (w, h + gaps)
+            },
+            Direction::Horizontal => {
+                let (w, h) = sizes.fold((0.0f32, 0.0f32), |(w, h), (cw, ch)| (w + cw, h.max(ch)));
+                (w + gaps, h)
+            },
+        }
+    }
+
+    fn layout(&mut self, rect: GuiRect, theme: &Theme) {
+        let style = theme.style("container");
+        self.rect = rect;
+        match self.direction {
+            Direction::Vertical => {
+                let mut y = rect.y;
+                for c in self.children.iter_mut() {
+                    let (_, h) = c.measure(theme);
+                    c.layout(GuiRect::new(rect.x, y, rect.w, h), theme);
+                    y += h + style.spacing;
+                }
+            },
+            Direction::Horizontal => {
+                let n = self.children.len().max(1) as f32;
+                let w = (rect.w - style.spacing * (n - 1.0)) / n;
+                for (i, c) in self.children.iter_mut().enumerate() {
+                    c.layout(GuiRect::new(rect.x + (w + style.spacing) * i as f32, rect.y, w, rect.h), theme);
+                }
+            },
+        }
+    }
+
+    fn rect(&self) -> GuiRect { self.rect }
+
+    fn draw(&self, out: &GuiPipeIn, theme: &Theme, focused: Option<&Widget>) {
+        for c in self.children.iter() {
+            c.draw(out, theme, focused);
+        }
+    }
+
+    fn children(&self) -> &[Box<Widget>] { &self.children }
+    fn children_mut(&mut self) -> &mut [Box<Widget>] { &mut self.children }
+}
+
+// Fills rows of columns cells, left to right, top to bottom. Every cell in a row is as tall as the tallest.
+pub struct Grid {
+    pub columns: usize,
+    children: Vec<Box<Widget>>,
+    rect: GuiRect,
+}
+
+impl Grid {
+    pub fn new(columns: usize, children: Vec<Box<Widget>>) -> Box<Grid> {
+        Box::new(Grid {
+            columns: columns.max(1),
+            children: children,
+            rect: GuiRect::new(0.0, 0.0, 0.0, 0.0),
+        })
+    }
+
+    fn row_heights(&self, theme: &Theme) -> Vec<f32> {
+        self.children.chunks(self.columns)
+            .map(|row| row.iter().fold(0.0f32, |h, c| h.max(c.measure(theme).1)))
+            .collect()
+    }
+}
+
+impl Widget for Grid {
+    fn measure(&self, theme: &Theme) -> (f32, f32) {
+        let style = theme.style("grid");
+        let rows = self.row_heights(theme);
+        let w = self.children.iter().fold(0.0f32, |w, c| w.max(c.measure(theme).0)) * self.columns as f32;
+        (w + style.spacing * (self.columns - 1) as f32,
+         rows.iter().sum::<f32>() + style.spacing * (rows.len().max(1) - 1) as f32)
+    }
+
+    fn layout(&mut self, rect: GuiRect, theme: &Theme) {
+        let style = theme.style("grid");
+        self.rect = rect;
+        let rows = self.row_heights(theme);
+        let cols = self.columns as f32;
+        let w = (rect.w - style.spacing * (cols - 1.0)) / cols;
+        let mut y = rect.y;
+        for (r, row) in self.children.chunks_mut(self.columns).enumerate() {
+            for (i, c) in row.iter_mut().enumerate() {
+                c.layout(GuiRect::new(rect.x + (w + style.spacing) * i as f32, y, w, rows[r]), theme);
+            }
+            y += rows[r] + style.spacing;
+        }
+    }
+
+    fn rect(&self) -> GuiRect { self.rect }
+
+    fn draw(&self, out: &GuiPipeIn, theme: &Theme, focused: Option<&Widget>) {
+        for c in self.children.iter() {
+            c.draw(out, theme, focused);
+        }
+    }
+
+    fn children(&self) -> &[Box<Widget>] { &self.children }
+    fn children_mut(&mut self) -> &mut [Box<Widget>] { &mut self.children }
+}
+
+// Shows height pixels of its child at a time, scrolling to whatever has focus
+pub struct ScrollPanel {
+    pub height: f32,
+    offset: f32,
+    child: Vec<Box<Widget>>, // Just the one
+    rect: GuiRect,
+}
+
+impl ScrollPanel {
+    pub fn new(height: f32, child: Box<Widget>) -> Box<ScrollPanel> {
+        Box::new(ScrollPanel {
+            height: height,
+            offset: 0.0,
+            child: vec![child],
+            rect: GuiRect::new(0.0, 0.0, 0.0, 0.0),
+        })
+    }
+
+    fn layout_child(&mut self, theme: &Theme) {
+        let (_, h) = self.child[0].measure(theme);
+        let r = self.rect;
+        self.child[0].layout(GuiRect::new(r.x, r.y - self.offset, r.w, h), theme);
+    }
+}
+
+impl Widget for ScrollPanel {
+    fn measure(&self, theme: &Theme) -> (f32, f32) {
+        let (w, h) = self.child[0].measure(theme);
+        (w, h.min(self.height))
+    }
+
+    fn layout(&mut self, rect: GuiRect, theme: &Theme) {
+        self.rect = rect;
+        let (_, h) = self.child[0].measure(theme);
+        self.offset = self.offset.min((h - rect.h).max(0.0));
+        self.layout_child(theme);
+    }
+
+    fn rect(&self) -> GuiRect { self.rect }
+
+    // NOTE Clips don't nest; a scroll panel inside another one will draw outside it
+    fn draw(&self, out: &GuiPipeIn, theme: &Theme, focused: Option<&Widget>) {
+        out.send(GuiInstruction::Clip(Some(self.rect))).unwrap();
+        self.child[0].draw(out, theme, focused);
+        out.send(GuiInstruction::Clip(None)).unwrap();
+    }
+
+    fn reveal(&mut self, rect: GuiRect, theme: &Theme) {
+        self.child[0].reveal(rect, theme);
+        let content = self.child[0].rect();
+        if !content.contains(rect.x, rect.y) { return } // Not one of ours
+        let before = self.offset;
+        if rect.y < self.rect.y {
+            self.offset -= self.rect.y - rect.y;
+        } else if rect.y + rect.h > self.rect.y + self.rect.h {
+            self.offset += rect.y + rect.h - (self.rect.y + self.rect.h);
+        }
+        if self.offset != before {
+            self.layout_child(theme);
+        }
+    }
+
+    fn children(&self) -> &[Box<Widget>] { &self.child }
+    fn children_mut(&mut self) -> &mut [Box<Widget>] { &mut self.child }
+}
+
+fn collect_focusables(w: &Widget, out: &mut Vec<GuiRect>) {
+    if w.focusable() {
+        out.push(w.rect());
+    }
+    for c in w.children().iter() {
+        collect_focusables(&**c, out);
+    }
+}
+
+// Focusables are numbered in the same order collect_focusables finds them
+fn nth_focusable<'a>(w: &'a Widget, n: &mut usize) -> Option<&'a Widget> {
+    if w.focusable() {
+        if *n == 0 { return Some(w) }
+        *n -= 1;
+    }
+    for c in w.children().iter() {
+        if let Some(f) = nth_focusable(&**c, n) {
+            return Some(f);
+        }
+    }
+    None
+}
+
+fn nth_focusable_mut<'a>(w: &'a mut Widget, n: &mut usize) -> Option<&'a mut Widget> {
+    if w.focusable() {
+        if *n == 0 { return Some(w) }
+        *n -= 1;
+    }
+    for c in w.children_mut().iter_mut() {
+        if let Some(f) = nth_focusable_mut(&mut **c, n) {
+            return Some(f);
+        }
+    }
+    None
+}
+
+// Picks the focusable closest to from in the direction of key. Straying sideways costs double,
+// so moving down a column doesn't jump to a neighbouring one.
+fn neighbour(rects: &[GuiRect], from: usize, key: &Key) -> Option<usize> {
+    let center = |r: &GuiRect| (r.x + r.w / 2.0, r.y + r.h / 2.0);
+    let (fx, fy) = center(&rects[from]);
+    let mut best = None;
+    for (i, r) in rects.iter().enumerate() {
+        if i == from { continue }
+        let (x, y) = center(r);
+        let (along, across) = match *key {
+            Key::Up => (fy - y, (x - fx).abs()),
+            Key::Down => (y - fy, (x - fx).abs()),
+            Key::Left => (fx - x, (y - fy).abs()),
+            Key::Right => (x - fx, (y - fy).abs()),
+            _ => return None
+        };
+        if along <= 0.5 { continue }
+        let cost = along + across * 2.0;
+        match best {
+            Some((_, c)) if c <= cost => (),
+            _ => best = Some((i, cost))
+        }
+    }
+    best.map(|(i, _)| i)
+}
+
+// A widget tree, placed somewhere on screen, with one widget focused
+pub struct Gui {
+    root: Box<Widget>,
+    rect: GuiRect,
+    pub theme: Theme,
+    focus: usize,
+}
+
+impl Gui {
+    pub fn new(root: Box<Widget>, rect: GuiRect, theme: Theme) -> Gui {
+        let mut gui = Gui {
+            root: root,
+            rect: rect,
+            theme: theme,
+            focus: 0,
+        };
+        gui.relayout();
+        gui
+    }
+
+    // Call after changing the widgets, the theme, or where the GUI goes
+    pub fn relayout(&mut self) {
+        self.root.layout(self.rect, &self.theme);
+        self.reveal_focus();
+    }
+
+    pub fn move_to(&mut self, rect: GuiRect) {
+        self.rect = rect;
+        self.relayout();
+    }
+
+    pub fn root_mut(&mut self) -> &mut Widget {
+        &mut *self.root
+    }
+
+    pub fn focused(&self) -> Option<&Widget> {
+        nth_focusable(&*self.root, &mut self.focus.clone())
+    }
+
+    pub fn focused_mut(&mut self) -> Option<&mut Widget> {
+        nth_focusable_mut(&mut *self.root, &mut self.focus.clone())
+    }
+
+    fn reveal_focus(&mut self) {
+        let mut rects = vec![];
+        collect_focusables(&*self.root, &mut rects);
+        if let Some(&r) = rects.get(self.focus) {
+            self.root.reveal(r, &self.theme);
+        }
+    }
+
+    // Feed this every virtual key press. Returns whatever the press caused.
+    pub fn handle_key(&mut self, key: &Key) -> Vec<GuiEvent> {
+        let mut events = vec![];
+        let used = match self.focused_mut() {
+            Some(w) => w.handle(key, &mut events),
+            None => false
+        };
+        if used { return events }
+
+        match *key {
+            Key::Up | Key::Down | Key::Left | Key::Right => {
+                let mut rects = vec![];
+                collect_focusables(&*self.root, &mut rects);
+                if rects.is_empty() { return events }
+                let from = ::std::cmp::min(self.focus, rects.len() - 1);
+                if let Some(next) = neighbour(&rects, from, key) {
+                    self.focus = next;
+                    self.reveal_focus();
+                }
+            },
+            Key::B => events.push(GuiEvent::Back),
+            _ => ()
+        }
+        events
+    }
+
+    pub fn draw(&self, out: &GuiPipeIn) {
+        self.root.draw(out, &self.theme, self.focused());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use input::Key;
+    use gui::{GuiRect, Theme};
+    use super::{Gui, GuiEvent, Widget, Label, Button, Slider, List, Container, Grid, neighbour, nth_focusable};
+
+    fn gui(root: Box<Widget>) -> Gui {
+        Gui::new(root, GuiRect::new(0.0, 0.0, 200.0, 400.0), Theme::default())
+    }
+
+    // Which button has focus, going by what A does
+    fn activate(gui: &mut Gui) -> Vec<GuiEvent> {
+        gui.handle_key(&Key::A)
+    }
+
+    #[test]
+    fn column_focus() {
+        let mut g = gui(Container::column(vec![Label::new("Title"), Button::new("a", "A"), Button::new("b", "B"), Button::new("c", "C")]));
+        // Labels can't have focus
+        assert_eq!(activate(&mut g), vec![GuiEvent::Activated("a".into())]);
+        g.handle_key(&Key::Up);
+        assert_eq!(activate(&mut g), vec![GuiEvent::Activated("a".into())]);
+        g.handle_key(&Key::Down);
+        g.handle_key(&Key::Down);
+        assert_eq!(activate(&mut g), vec![GuiEvent::Activated("c".into())]);
+        g.handle_key(&Key::Down);
+        assert_eq!(activate(&mut g), vec![GuiEvent::Activated("c".into())]);
+        // Nothing to the side in a column
+        g.handle_key(&Key::Left);
+        assert_eq!(activate(&mut g), vec![GuiEvent::Activated("c".into())]);
+        assert_eq!(g.handle_key(&Key::B), vec![GuiEvent::Back]);
+    }
+
+    #[test]
+    fn grid_focus() {
+        let mut g = gui(Grid::new(2, vec![Button::new("a", "A"), Button::new("b", "B"), Button::new("c", "C"), Button::new("d", "D")]));
+        g.handle_key(&Key::Right);
+        assert_eq!(activate(&mut g), vec![GuiEvent::Activated("b".into())]);
+        g.handle_key(&Key::Down);
+        assert_eq!(activate(&mut g), vec![GuiEvent::Activated("d".into())]);
+        g.handle_key(&Key::Left);
+        assert_eq!(activate(&mut g), vec![GuiEvent::Activated("c".into())]);
+        g.handle_key(&Key::Up);
+        assert_eq!(activate(&mut g), vec![GuiEvent::Activated("a".into())]);
+    }
+
+    #[test]
+    fn neighbours() {
+        // Two columns, with the right one sitting a little lower
+        let rects = [GuiRect::new(0.0, 0.0, 10.0, 10.0), GuiRect::new(0.0, 40.0, 10.0, 10.0),
+                     GuiRect::new(20.0, 15.0, 10.0, 10.0), GuiRect::new(20.0, 60.0, 10.0, 10.0)];
+        // Straight down beats the closer one off to the side
+        assert_eq!(neighbour(&rects, 0, &Key::Down), Some(1));
+        assert_eq!(neighbour(&rects, 1, &Key::Down), Some(3));
+        assert_eq!(neighbour(&rects, 0, &Key::Right), Some(2));
+        assert_eq!(neighbour(&rects, 3, &Key::Up), Some(2));
+        assert_eq!(neighbour(&rects, 0, &Key::Up), None);
+        assert_eq!(neighbour(&rects, 0, &Key::A), None);
+    }
+
+    #[test]
+    fn focusables_in_order() {
+        let root: Box<Widget> = Container::column(vec![
+            Button::new("a", "A"),
+            Label::new("Skipped"),
+            Container::row(vec![Button::new("b", "B"), Button::new("c", "C")]),
+        ]);
+        let rects: Vec<_> = (0..3).map(|i| nth_focusable(&*root, &mut i.clone()).unwrap().rect()).collect();
+        // b and c share a row, under a
+        assert!(rects[0].y < rects[1].y);
+        assert_eq!(rects[1].y, rects[2].y);
+        assert!(rects[1].x < rects[2].x);
+        assert!(nth_focusable(&*root, &mut 3).is_none());
+    }
+
+    #[test]
+    fn slider_keys() {
+        let mut g = gui(Container::column(vec![Slider::new("volume", "Volume", 0.5, 0.0, 1.0, 0.25), Button::new("ok", "OK")]));
+        assert_eq!(g.handle_key(&Key::Right), vec![GuiEvent::Changed("volume".into(), 0.75)]);
+        assert_eq!(g.handle_key(&Key::Right), vec![GuiEvent::Changed("volume".into(), 1.0)]);
+        // Already at the top, so nothing changes, but focus stays put
+        assert_eq!(g.handle_key(&Key::Right), vec![]);
+        assert_eq!(g.handle_key(&Key::Left), vec![GuiEvent::Changed("volume".into(), 0.75)]);
+        // Up and Down are still for moving focus
+        g.handle_key(&Key::Down);
+        assert_eq!(activate(&mut g), vec![GuiEvent::Activated("ok".into())]);
+    }
+
+    #[test]
+    fn list_keys() {
+        let items = vec!["One".to_string(), "Two".to_string(), "Three".to_string()];
+        let mut g = gui(Container::column(vec![Button::new("top", "Top"), List::new("list", items), Button::new("bottom", "Bottom")]));
+        g.handle_key(&Key::Down);
+        assert_eq!(activate(&mut g), vec![GuiEvent::Selected("list".into(), 0)]);
+        // Up at the first item leaves the list
+        g.handle_key(&Key::Up);
+        assert_eq!(activate(&mut g), vec![GuiEvent::Activated("top".into())]);
+        g.handle_key(&Key::Down);
+        g.handle_key(&Key::Down);
+        g.handle_key(&Key::Down);
+        assert_eq!(activate(&mut g), vec![GuiEvent::Selected("list".into(), 2)]);
+        // And so does Down at the last
+        g.handle_key(&Key::Down);
+        assert_eq!(activate(&mut g), vec![GuiEvent::Activated("bottom".into())]);
+        // Coming back keeps the selection
+        g.handle_key(&Key::Up);
+        assert_eq!(activate(&mut g), vec![GuiEvent::Selected("list".into(), 2)]);
+    }
+}
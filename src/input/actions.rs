@@ -0,0 +1,249 @@
+///! Named actions on top of the virtual keys, so gameplay asks "fire_light?" instead of "A?".
+///!
+///! What a key means depends on the context: A fires a light bullet in Klay mode, but throws a
+///! light punch in Wor mode. Contexts are stacked; an action is looked up from the top down, and
+///! a blocking context (menus, usually) hides everything under it.
+///!
+///! Contexts are loaded from "data/input/<name>.toml", one table per context:
+///!
+///! [klay]
+///! fire_light = ["A"]
+///!
+///! [menu]
+///! blocking = true
+///! menu_confirm = ["A", "Select"]
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use toml;
+use super::{Key, KeyEvent};
+
+#[derive(Clone, Debug, Default)]
+pub struct ActionContext {
+    pub blocking: bool, // Hide the contexts under this one
+    actions: HashMap<String, Vec<Key>>,
+}
+
+impl ActionContext {
+    pub fn new() -> ActionContext {
+        ActionContext::default()
+    }
+
+    pub fn bind<S: Into<String>>(&mut self, action: S, key: Key) {
+        self.actions.entry(action.into()).or_insert(vec![]).push(key);
+    }
+
+    pub fn keys(&self, action: &str) -> Option<&[Key]> {
+        self.actions.get(action).map(|k| &k[..])
+    }
+}
+
+#[derive(Debug)]
+pub enum ActionError {
+    Io(io::Error),
+    Parse(String),
+    Value(String),
+}
+
+impl From<io::Error> for ActionError {
+    fn from(e: io::Error) -> ActionError {
+        ActionError::Io(e)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ActionMap {
+    contexts: HashMap<String, ActionContext>,
+    stack: Vec<String>, // Active contexts, last on top
+    held: HashSet<Key>,
+    // These only last a frame
+    pressed: HashSet<Key>,
+    released: HashSet<Key>,
+}
+
+impl ActionMap {
+    pub fn new() -> ActionMap {
+        ActionMap::default()
+    }
+
+    pub fn load(name: &str) -> Result<ActionMap, ActionError> {
+        let path = Path::new("data").join("input").join(name).with_extension("toml");
+        let mut s = String::new();
+        try!(try!(File::open(&path)).read_to_string(&mut s));
+        ActionMap::parse(&s)
+    }
+
+    pub fn parse(s: &str) -> Result<ActionMap, ActionError> {
+        let mut parser = toml::Parser::new(s);
+        let table = match parser.parse() {
+            Some(t) => t,
+            None => {
+                let e = &parser.errors[0];
+                let (line, col) = parser.to_linecol(e.lo);
+                return Err(ActionError::Parse(format!("{}:{}: {}", line + 1, col + 1, e.desc)));
+            }
+        };
+
+        let mut map = ActionMap::new();
+        for (name, t) in table.iter() {
+            let t = try!(t.as_table().ok_or(ActionError::Value(format!("{} should be a table", name))));
+            let mut context = ActionContext::new();
+            for (action, v) in t.iter() {
+                if action == "blocking" {
+                    context.blocking = try!(v.as_bool().ok_or(ActionError::Value(format!("{}.blocking should be true or false", name))));
+                    continue;
+                }
+                let keys = try!(v.as_slice().ok_or(ActionError::Value(format!("{}.{} should be a list of keys", name, action))));
+                for k in keys {
+                    let key = match k.as_str().and_then(Key::from_name) {
+                        Some(key) => key,
+                        None => return Err(ActionError::Value(format!("{}.{} has an unknown key {}", name, action, k)))
+                    };
+                    context.bind(action.clone(), key);
+                }
+            }
+            map.add_context(name.clone(), context);
+        }
+        Ok(map)
+    }
+
+    pub fn add_context<S: Into<String>>(&mut self, name: S, context: ActionContext) {
+        self.contexts.insert(name.into(), context);
+    }
+
+    // Leaves the stack alone if there's no such context
+    pub fn push(&mut self, name: &str) -> Result<(), ActionError> {
+        if !self.contexts.contains_key(name) {
+            return Err(ActionError::Value(format!("No input context named {}", name)));
+        }
+        self.stack.push(name.into());
+        Ok(())
+    }
+
+    pub fn pop(&mut self) -> Option<String> {
+        self.stack.pop()
+    }
+
+    pub fn current(&self) -> Option<&str> {
+        self.stack.last().map(|s| &s[..])
+    }
+
+    // Every key the action is bound to in the active contexts
+    pub fn keys(&self, action: &str) -> Vec<Key> {
+        let mut keys = vec![];
+        for name in self.stack.iter().rev() {
+            let context = &self.contexts[name];
+            if let Some(k) = context.keys(action) {
+                keys.extend_from_slice(k);
+            }
+            if context.blocking { break }
+        }
+        keys
+    }
+
+    pub fn process(&mut self, e: &KeyEvent) {
+        match *e {
+            KeyEvent::Pressed(k) => {
+                // Held keyboard keys repeat, but that's not a new press
+                if self.held.insert(k) {
+                    self.pressed.insert(k);
+                }
+            },
+            KeyEvent::Released(k) => {
+                self.held.remove(&k);
+                self.released.insert(k);
+            },
+        }
+    }
+
+    // Call once a frame, after everyone has had their look
+    pub fn next_frame(&mut self) {
+        self.pressed.clear();
+        self.released.clear();
+    }
+
+    pub fn is_held(&self, action: &str) -> bool {
+        self.keys(action).iter().any(|k| self.held.contains(k))
+    }
+
+    // Started this frame
+    pub fn was_pressed(&self, action: &str) -> bool {
+        self.keys(action).iter().any(|k| self.pressed.contains(k))
+    }
+
+    // Stopped this frame
+    pub fn was_released(&self, action: &str) -> bool {
+        self.keys(action).iter().any(|k| self.released.contains(k))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use input::{Key, KeyEvent};
+    use super::ActionMap;
+
+    fn map() -> ActionMap {
+        ActionMap::parse(r#"
+            [global]
+            pause = ["Start"]
+
+            [klay]
+            fire_light = ["A"]
+            fire_heavy = ["B", "C"]
+
+            [menu]
+            blocking = true
+            menu_confirm = ["A", "Select"]
+        "#).unwrap()
+    }
+
+    #[test]
+    fn stacking() {
+        let mut m = map();
+        assert!(m.push("nope").is_err());
+        assert_eq!(m.current(), None);
+        assert!(m.keys("fire_light").is_empty());
+
+        m.push("global").unwrap();
+        m.push("klay").unwrap();
+        assert_eq!(m.current(), Some("klay"));
+        assert_eq!(m.keys("fire_light"), vec![Key::A]);
+        assert_eq!(m.keys("fire_heavy"), vec![Key::B, Key::C]);
+        assert_eq!(m.keys("pause"), vec![Key::Start]);
+
+        // Menus hide everything under them
+        m.push("menu").unwrap();
+        assert_eq!(m.keys("menu_confirm"), vec![Key::A, Key::Select]);
+        assert!(m.keys("fire_light").is_empty());
+        assert!(m.keys("pause").is_empty());
+
+        assert_eq!(m.pop(), Some("menu".into()));
+        assert_eq!(m.keys("fire_light"), vec![Key::A]);
+    }
+
+    #[test]
+    fn edges() {
+        let mut m = map();
+        m.push("klay").unwrap();
+        m.process(&KeyEvent::Pressed(Key::A));
+        assert!(m.was_pressed("fire_light") && m.is_held("fire_light"));
+        assert!(!m.was_released("fire_light"));
+        m.next_frame();
+        assert!(!m.was_pressed("fire_light") && m.is_held("fire_light"));
+
+        // Keyboard repeats while it's held aren't new presses
+        m.process(&KeyEvent::Pressed(Key::A));
+        assert!(!m.was_pressed("fire_light"));
+        m.next_frame();
+
+        m.process(&KeyEvent::Released(Key::A));
+        assert!(m.was_released("fire_light") && !m.is_held("fire_light"));
+        m.next_frame();
+        assert!(!m.was_released("fire_light"));
+
+        // Either of an action's keys does it
+        m.process(&KeyEvent::Pressed(Key::C));
+        assert!(m.was_pressed("fire_heavy"));
+    }
+}
@@ -0,0 +1,436 @@
+///! Fighting game style inputs for Wor mode: motions, double taps, charges and chords.
+///!
+///! Each player's key events go into an InputBuffer, stamped with the frame they happened on.
+///! Whenever something new comes in, every move is checked against the end of the buffer, and
+///! the ones that match get sent down a channel for combat to pick up.
+///!
+///! Moves are loaded from "data/input/<name>.toml":
+///!
+///! [hadoken]
+///! input = ["Down", "Right", "A"] # Pressed in that order...
+///! window = 15 # ...within 15 frames of each other, start to end
+///!
+///! An input step is one of:
+///!   "A"       pressed
+///!   "X+Y"     all pressed within `simultaneous` frames (3 unless the move says otherwise)
+///!   "Left*40" held for at least 40 frames, then let go. Only the letting go has to be in the window.
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::mpsc::{Sender, Receiver, channel};
+use toml;
+use super::{Key, KeyEvent};
+
+pub type ComboPipeIn = Sender<ComboEvent>;
+pub type ComboPipeOut = Receiver<ComboEvent>;
+
+pub fn create_combo_channel() -> (ComboPipeIn, ComboPipeOut) {
+    channel()
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ComboEvent {
+    pub player: usize,
+    pub name: String,
+    pub frame: u64, // When the last input of the move came in
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Step {
+    Press(Key),
+    Together(Vec<Key>),
+    // Key Frames
+    Charge(Key, u64),
+}
+
+impl Step {
+    pub fn parse(s: &str) -> Option<Step> {
+        if s.contains('+') {
+            let mut keys = vec![];
+            for k in s.split('+') {
+                keys.push(match Key::from_name(k.trim()) {
+                    Some(k) => k,
+                    None => return None
+                });
+            }
+            Some(Step::Together(keys))
+        } else if let Some(i) = s.find('*') {
+            let key = Key::from_name(s[..i].trim());
+            let frames = s[i + 1..].trim().parse().ok();
+            match (key, frames) {
+                (Some(k), Some(f)) => Some(Step::Charge(k, f)),
+                _ => None
+            }
+        } else {
+            Key::from_name(s.trim()).map(Step::Press)
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Move {
+    pub name: String,
+    pub steps: Vec<Step>,
+    pub window: u64, // Frames from the first step to the last
+    pub simultaneous: u64, // Frames the keys of a Together step can be apart
+}
+
+#[derive(Debug)]
+pub enum ComboError {
+    Io(io::Error),
+    Parse(String),
+    Value(String),
+}
+
+impl From<io::Error> for ComboError {
+    fn from(e: io::Error) -> ComboError {
+        ComboError::Io(e)
+    }
+}
+
+const DEFAULT_WINDOW: u64 = 20;
+const DEFAULT_SIMULTANEOUS: u64 = 3;
+
+// Every move there is, longest first, so a hadoken beats the plain A at its end
+#[derive(Clone, Debug, Default)]
+pub struct MoveList {
+    moves: Vec<Move>,
+}
+
+impl MoveList {
+    pub fn new() -> MoveList {
+        MoveList::default()
+    }
+
+    pub fn load(name: &str) -> Result<MoveList, ComboError> {
+        let path = Path::new("data").join("input").join(name).with_extension("toml");
+        let mut s = String::new();
+        try!(try!(File::open(&path)).read_to_string(&mut s));
+        MoveList::parse(&s)
+    }
+
+    pub fn parse(s: &str) -> Result<MoveList, ComboError> {
+        let mut parser = toml::Parser::new(s);
+        let table = match parser.parse() {
+            Some(t) => t,
+            None => {
+                let e = &parser.errors[0];
+                let (line, col) = parser.to_linecol(e.lo);
+                return Err(ComboError::Parse(format!("{}:{}: {}", line + 1, col + 1, e.desc)));
+            }
+        };
+
+        let mut list = MoveList::new();
+        for (name, t) in table.iter() {
+            let bad = |what: &str| ComboError::Value(format!("{}.{} is missing or wrong", name, what));
+            let t = try!(t.as_table().ok_or(bad("(table)")));
+            let mut steps = vec![];
+            for s in try!(t.get("input").and_then(|v| v.as_slice()).ok_or(bad("input"))) {
+                let s = try!(s.as_str().ok_or(bad("input")));
+                steps.push(try!(Step::parse(s).ok_or(ComboError::Value(format!("{} has a bad input {}", name, s)))));
+            }
+            if steps.is_empty() { return Err(bad("input")) }
+            let frames = |key: &str, default: u64| match t.get(key) {
+                Some(v) => v.as_integer().map(|i| i as u64).ok_or(bad(key)),
+                None => Ok(default)
+            };
+            list.add(Move {
+                name: name.clone(),
+                steps: steps,
+                window: try!(frames("window", DEFAULT_WINDOW)),
+                simultaneous: try!(frames("simultaneous", DEFAULT_SIMULTANEOUS)),
+            });
+        }
+        Ok(list)
+    }
+
+    pub fn add(&mut self, m: Move) {
+        self.moves.push(m);
+        // Stable, so equally long moves keep their order
+        self.moves.sort_by(|a, b| b.steps.len().cmp(&a.steps.len()));
+    }
+
+    pub fn moves(&self) -> &[Move] {
+        &self.moves
+    }
+
+    // How far back a buffer has to remember
+    fn longest_window(&self) -> u64 {
+        self.moves.iter().map(|m| m.window).max().unwrap_or(0)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Entry {
+    id: u64,
+    frame: u64,
+    event: KeyEvent,
+    held_for: u64, // For releases, how long the key was down
+}
+
+// One player's recent key events
+#[derive(Clone, Debug)]
+pub struct InputBuffer {
+    entries: VecDeque<Entry>, // Oldest first
+    frame: u64,
+    next_id: u64,
+    // Entries before this one were used up by a move, so one motion can't fire twice
+    consumed: u64,
+    held: HashMap<Key, u64>, // When each held key went down
+    keep: u64, // Frames to remember
+}
+
+impl InputBuffer {
+    pub fn new(keep: u64) -> InputBuffer {
+        InputBuffer {
+            entries: VecDeque::new(),
+            frame: 0,
+            next_id: 0,
+            consumed: 0,
+            held: HashMap::new(),
+            keep: keep,
+        }
+    }
+
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    // Returns false for key repeats, which don't count as anything new
+    pub fn push(&mut self, e: KeyEvent) -> bool {
+        let held_for = match e {
+            KeyEvent::Pressed(k) => {
+                if self.held.contains_key(&k) { return false }
+                self.held.insert(k, self.frame);
+                0
+            },
+            KeyEvent::Released(k) => match self.held.remove(&k) {
+                Some(since) => self.frame - since,
+                None => 0
+            },
+        };
+        self.entries.push_back(Entry {
+            id: self.next_id,
+            frame: self.frame,
+            event: e,
+            held_for: held_for,
+        });
+        self.next_id += 1;
+        true
+    }
+
+    pub fn next_frame(&mut self) {
+        self.frame += 1;
+        while self.entries.front().map(|e| e.frame + self.keep < self.frame).unwrap_or(false) {
+            self.entries.pop_front();
+        }
+    }
+
+    // Forgets everything up to now, for after a move comes out
+    pub fn consume(&mut self) {
+        self.consumed = self.next_id;
+    }
+
+    // Does the move end with the newest entry?
+    pub fn matches(&self, m: &Move) -> bool {
+        let last = match self.entries.len() {
+            0 => return false,
+            n => n - 1
+        };
+        let earliest = self.entries[last].frame.saturating_sub(m.window);
+        let mut upto = Some(last);
+        for (n, step) in m.steps.iter().enumerate().rev() {
+            let found = match upto {
+                Some(i) => self.find(step, i, n + 1 == m.steps.len(), earliest, m.simultaneous),
+                None => None
+            };
+            match found {
+                Some(i) => upto = i.checked_sub(1),
+                None => return false
+            }
+        }
+        true
+    }
+
+    fn usable(&self, i: usize, earliest: u64) -> bool {
+        let e = &self.entries[i];
+        e.id >= self.consumed && e.frame >= earliest
+    }
+
+    // Looks backwards from upto for the step, giving the index of the earliest entry it used.
+    // The last step of a move has to be the entry at upto itself.
+    fn find(&self, step: &Step, upto: usize, last: bool, earliest: u64, simultaneous: u64) -> Option<usize> {
+        let lowest = if last { upto } else { 0 };
+        let mut i = upto + 1;
+        while i > lowest {
+            i -= 1;
+            if !self.usable(i, earliest) { return None }
+            let e = self.entries[i];
+            match *step {
+                Step::Press(k) => if e.event == KeyEvent::Pressed(k) { return Some(i) },
+                Step::Charge(k, frames) => if e.event == KeyEvent::Released(k) && e.held_for >= frames { return Some(i) },
+                Step::Together(ref keys) => if let Some(j) = self.find_chord(keys, i, earliest, simultaneous) { return Some(j) },
+            }
+        }
+        None
+    }
+
+    // The chord's newest press is at i. Finds the rest of it, giving the earliest.
+    fn find_chord(&self, keys: &[Key], i: usize, earliest: u64, simultaneous: u64) -> Option<usize> {
+        let newest = self.entries[i];
+        if !keys.iter().any(|&k| newest.event == KeyEvent::Pressed(k)) { return None }
+        let mut first = i;
+        for &k in keys {
+            let mut found = None;
+            let mut j = i + 1;
+            while j > 0 {
+                j -= 1;
+                let e = self.entries[j];
+                if !self.usable(j, earliest) || newest.frame - e.frame > simultaneous { break }
+                if e.event == KeyEvent::Pressed(k) {
+                    found = Some(j);
+                    break;
+                }
+            }
+            match found {
+                Some(j) => first = first.min(j),
+                None => return None
+            }
+        }
+        Some(first)
+    }
+}
+
+// Watches every player's buffer for moves
+pub struct ComboDetector {
+    moves: MoveList,
+    buffers: HashMap<usize, InputBuffer>,
+    sender: ComboPipeIn,
+}
+
+impl ComboDetector {
+    pub fn new(moves: MoveList, sender: ComboPipeIn) -> ComboDetector {
+        ComboDetector {
+            moves: moves,
+            buffers: HashMap::new(),
+            sender: sender,
+        }
+    }
+
+    pub fn buffer(&self, player: usize) -> Option<&InputBuffer> {
+        self.buffers.get(&player)
+    }
+
+    // Feeds in one of player's key events. Only the longest move it finishes gets sent.
+    pub fn process(&mut self, player: usize, e: KeyEvent) {
+        let keep = self.moves.longest_window();
+        let buffer = self.buffers.entry(player).or_insert_with(|| InputBuffer::new(keep));
+        if !buffer.push(e) { return }
+        for m in self.moves.moves() {
+            if buffer.matches(m) {
+                buffer.consume();
+                // Nobody listening is fine, there might just be no combat going on
+                let _ = self.sender.send(ComboEvent {
+                    player: player,
+                    name: m.name.clone(),
+                    frame: buffer.frame(),
+                });
+                return;
+            }
+        }
+    }
+
+    // Call once a frame
+    pub fn next_frame(&mut self) {
+        for b in self.buffers.values_mut() {
+            b.next_frame();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use input::{Key, KeyEvent};
+    use super::{ComboDetector, MoveList, Step, create_combo_channel};
+
+    const MOVES: &'static str = r#"
+        [hadoken]
+        input = ["Down", "Right", "A"]
+        window = 10
+
+        [jab]
+        input = ["A"]
+
+        [dash]
+        input = ["Right", "Right"]
+        window = 8
+
+        [sonic_boom]
+        input = ["Left*30", "Right", "A"]
+        window = 10
+
+        [throw]
+        input = ["X+Y"]
+        simultaneous = 2
+    "#;
+
+    // Feeds in (frames to wait first, event)s and gives back what moves came out
+    fn run(inputs: &[(u64, KeyEvent)]) -> Vec<String> {
+        let (tx, rx) = create_combo_channel();
+        let mut detector = ComboDetector::new(MoveList::parse(MOVES).unwrap(), tx);
+        for &(wait, e) in inputs {
+            for _ in 0..wait {
+                detector.next_frame();
+            }
+            detector.process(0, e);
+        }
+        rx.try_iter().map(|e| e.name).collect()
+    }
+
+    use super::super::KeyEvent::{Pressed, Released};
+
+    #[test]
+    fn parse_steps() {
+        assert_eq!(Step::parse("A"), Some(Step::Press(Key::A)));
+        assert_eq!(Step::parse("X+Y"), Some(Step::Together(vec![Key::X, Key::Y])));
+        assert_eq!(Step::parse("Left*30"), Some(Step::Charge(Key::Left, 30)));
+        assert_eq!(Step::parse("Q"), None);
+    }
+
+    #[test]
+    fn motion_within_window() {
+        assert_eq!(run(&[(0, Pressed(Key::Down)), (3, Pressed(Key::Right)), (3, Pressed(Key::A))]), vec!["hadoken"]);
+        // Too slow is just a jab
+        assert_eq!(run(&[(0, Pressed(Key::Down)), (6, Pressed(Key::Right)), (6, Pressed(Key::A))]), vec!["jab"]);
+    }
+
+    #[test]
+    fn inputs_are_used_up() {
+        let inputs = [(0, Pressed(Key::Down)), (1, Pressed(Key::Right)), (1, Pressed(Key::A)),
+                      (1, Released(Key::A)), (1, Pressed(Key::A))];
+        assert_eq!(run(&inputs), vec!["hadoken", "jab"]);
+    }
+
+    #[test]
+    fn double_tap() {
+        let tap = [(0, Pressed(Key::Right)), (2, Released(Key::Right)), (2, Pressed(Key::Right))];
+        assert_eq!(run(&tap), vec!["dash"]);
+        // Holding it down repeats, but that's not a second tap
+        assert_eq!(run(&[(0, Pressed(Key::Right)), (2, Pressed(Key::Right))]), Vec::<String>::new());
+    }
+
+    #[test]
+    fn charge_and_release() {
+        let charged = [(0, Pressed(Key::Left)), (40, Released(Key::Left)), (1, Pressed(Key::Right)), (1, Pressed(Key::A))];
+        assert_eq!(run(&charged), vec!["sonic_boom"]);
+        let short = [(0, Pressed(Key::Left)), (10, Released(Key::Left)), (1, Pressed(Key::Right)), (1, Pressed(Key::A))];
+        assert_eq!(run(&short), vec!["jab"]);
+    }
+
+    #[test]
+    fn simultaneous_press() {
+        assert_eq!(run(&[(0, Pressed(Key::Y)), (1, Pressed(Key::X))]), vec!["throw"]);
+        assert_eq!(run(&[(0, Pressed(Key::X)), (5, Pressed(Key::Y))]), Vec::<String>::new());
+    }
+}
@@ -0,0 +1,131 @@
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Key {
+    A, B, C,
+    X, Y, Z,
+    Start, Select,
+    Up, Down, Left, Right,
+}
+
+impl Key {
+    // The name as written in data files (ex. "Select")
+    pub fn from_name(name: &str) -> Option<Key> {
+        Some(match name {
+            "A" => Key::A, "B" => Key::B, "C" => Key::C,
+            "X" => Key::X, "Y" => Key::Y, "Z" => Key::Z,
+            "Start" => Key::Start, "Select" => Key::Select,
+            "Up" => Key::Up, "Down" => Key::Down, "Left" => Key::Left, "Right" => Key::Right,
+            _ => return None
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum KeyEvent {
+    Pressed(Key), // Repeats while a keyboard key is held down
+    Released(Key),
+}
+
+mod mouse;
+mod text;
+mod touch;
+mod actions;
+mod combo;
+mod players;
+
+pub use self::mouse::MouseState;
+pub use self::text::TextBuffer;
+pub use self::touch::{TouchPad, TouchRegion, TouchPoint};
+pub use self::actions::{ActionMap, ActionContext, ActionError};
+pub use self::combo::{ComboDetector, ComboEvent, ComboError, ComboPipeIn, ComboPipeOut, InputBuffer, Move, MoveList, Step, create_combo_channel};
+pub use self::players::{Players, Player, PlayerEvent, Device, Bindings, ButtonId};
+
+use glium::glutin::{VirtualKeyCode, Event, ElementState};
+// Converts the mouse, the touch screen and typing into our virtual key codes.
+// Keyboard keys aren't its business: every player has their own bindings, so they go through Players.
+pub struct KeyReader {
+    mouse: MouseState,
+    // While there's one of these, the keyboard types into it instead of pressing keys
+    text: Option<TextBuffer>,
+    touch: TouchPad,
+}
+
+impl KeyReader {
+    pub fn new() -> KeyReader {
+        KeyReader {
+            mouse: MouseState::new(),
+            text: None,
+            touch: TouchPad::new(),
+        }
+    }
+
+    pub fn mouse(&self) -> &MouseState {
+        &self.mouse
+    }
+
+    pub fn touch(&self) -> &TouchPad {
+        &self.touch
+    }
+
+    // To set up the on-screen buttons
+    pub fn touch_mut(&mut self) -> &mut TouchPad {
+        &mut self.touch
+    }
+
+    // On the touch screen. Ask Players about the keyboard.
+    pub fn is_held(&self, k: Key) -> bool {
+        self.touch.held().contains(&k)
+    }
+
+    // Switches to typing into buffer. Only Return still comes through, as Start, so whoever
+    // asked for the text knows when it's done.
+    pub fn start_text(&mut self, buffer: TextBuffer) {
+        self.text = Some(buffer);
+    }
+
+    // Back to keys. Gives back what was typed.
+    pub fn stop_text(&mut self) -> Option<TextBuffer> {
+        self.text.take()
+    }
+
+    pub fn text(&self) -> Option<&TextBuffer> {
+        self.text.as_ref()
+    }
+
+    pub fn text_mut(&mut self) -> Option<&mut TextBuffer> {
+        self.text.as_mut()
+    }
+
+    // Call once a frame, after the frame's events have been read
+    pub fn next_frame(&mut self) {
+        self.mouse.next_frame();
+    }
+
+    // Whether the keyboard is typing, and so not for Players
+    pub fn is_typing(&self) -> bool {
+        self.text.is_some()
+    }
+
+    // Mouse events are soaked up into mouse(), and never turn into keys
+    pub fn process(&mut self, e: &Event) -> Vec<KeyEvent> {
+        let mut out = vec![];
+        if self.mouse.process(e) || self.touch.process(e, &mut out) {
+            return out;
+        }
+        if let Some(ref mut buffer) = self.text {
+            if !buffer.process(e) {
+                if let Event::KeyboardInput(ElementState::Pressed, _, Some(VirtualKeyCode::Return)) = *e {
+                    out.push(KeyEvent::Pressed(Key::Start));
+                }
+            }
+        }
+        out
+    }
+
+    // Just the first key this event pressed, for anyone who doesn't care about releases
+    pub fn interpret_event(&mut self, e: &Event) -> Option<Key> {
+        self.process(e).into_iter().filter_map(|ke| match ke {
+            KeyEvent::Pressed(k) => Some(k),
+            KeyEvent::Released(_) => None,
+        }).next()
+    }
+}
@@ -0,0 +1,90 @@
+///! Where the mouse is and what it's doing, kept per frame
+use std::collections::HashSet;
+use glium::glutin::{Event, ElementState, MouseButton, MouseScrollDelta};
+
+// Touchpads scroll in pixels, wheels in lines. This is how many pixels make a line.
+const PIXELS_PER_LINE: f32 = 20.0;
+
+#[derive(Clone, Debug)]
+pub struct MouseState {
+    // In window pixels, from the top left, y going down
+    pub position: (i32, i32),
+    held: HashSet<MouseButton>,
+    // These only last a frame
+    pressed: HashSet<MouseButton>,
+    released: HashSet<MouseButton>,
+    wheel: (f32, f32), // In lines, x then y
+    moved: bool,
+}
+
+impl MouseState {
+    pub fn new() -> MouseState {
+        MouseState {
+            position: (0, 0),
+            held: HashSet::new(),
+            pressed: HashSet::new(),
+            released: HashSet::new(),
+            wheel: (0.0, 0.0),
+            moved: false,
+        }
+    }
+
+    // Returns true if the event was the mouse's
+    pub fn process(&mut self, e: &Event) -> bool {
+        match *e {
+            Event::MouseMoved(x, y) => {
+                self.position = (x, y);
+                self.moved = true;
+            },
+            Event::MouseInput(ElementState::Pressed, button) => {
+                self.held.insert(button);
+                self.pressed.insert(button);
+            },
+            Event::MouseInput(ElementState::Released, button) => {
+                self.held.remove(&button);
+                self.released.insert(button);
+            },
+            Event::MouseWheel(delta, _) => {
+                let (x, y) = match delta {
+                    MouseScrollDelta::LineDelta(x, y) => (x, y),
+                    MouseScrollDelta::PixelDelta(x, y) => (x / PIXELS_PER_LINE, y / PIXELS_PER_LINE),
+                };
+                self.wheel.0 += x;
+                self.wheel.1 += y;
+            },
+            _ => return false
+        }
+        true
+    }
+
+    // Forgets everything that only lasts a frame
+    pub fn next_frame(&mut self) {
+        self.pressed.clear();
+        self.released.clear();
+        self.wheel = (0.0, 0.0);
+        self.moved = false;
+    }
+
+    pub fn is_held(&self, b: MouseButton) -> bool {
+        self.held.contains(&b)
+    }
+
+    // Went down this frame
+    pub fn was_pressed(&self, b: MouseButton) -> bool {
+        self.pressed.contains(&b)
+    }
+
+    // Came up this frame
+    pub fn was_released(&self, b: MouseButton) -> bool {
+        self.released.contains(&b)
+    }
+
+    // How far the wheel turned this frame, in lines
+    pub fn wheel(&self) -> (f32, f32) {
+        self.wheel
+    }
+
+    pub fn moved(&self) -> bool {
+        self.moved
+    }
+}
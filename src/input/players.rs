@@ -0,0 +1,374 @@
+///! Local multiplayer: several people on one machine, each on their own device.
+///!
+///! Players join by pressing Start on a device nobody has claimed yet, and get the first free slot.
+///! Every player has their own bindings, their own held keys and their own ActionMap, so one player's A
+///! doesn't fire for everybody.
+///!
+///! glutin doesn't do gamepads, so whatever reads them feeds their buttons in with gamepad_button.
+use std::collections::{HashMap, HashSet};
+use glium::glutin::{Event, ElementState, VirtualKeyCode};
+use super::{Key, KeyEvent, ActionMap, ActionError};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Device {
+    KeyboardLeft, // WASD and thereabouts
+    KeyboardRight, // The arrows and thereabouts
+    Gamepad(usize),
+}
+
+// Gamepad buttons, as numbered by whatever reads the gamepad
+pub type ButtonId = u32;
+
+#[derive(Clone, Debug, Default)]
+pub struct Bindings {
+    keys: HashMap<VirtualKeyCode, Key>,
+    buttons: HashMap<ButtonId, Key>,
+}
+
+impl Bindings {
+    pub fn new() -> Bindings {
+        Bindings::default()
+    }
+
+    // What a device starts out with
+    pub fn default_for(device: Device) -> Bindings {
+        use glium::glutin::VirtualKeyCode as K;
+
+        let mut b = Bindings::new();
+        match device {
+            Device::KeyboardLeft => {
+                for &(code, key) in [(K::W, Key::Up), (K::S, Key::Down), (K::A, Key::Left), (K::D, Key::Right),
+                                     (K::F, Key::A), (K::G, Key::B), (K::H, Key::C),
+                                     (K::R, Key::X), (K::T, Key::Y), (K::Y, Key::Z),
+                                     (K::Tab, Key::Start), (K::Q, Key::Select)].iter() {
+                    b.bind_key(code, key);
+                }
+            },
+            Device::KeyboardRight => {
+                for &(code, key) in [(K::Up, Key::Up), (K::Down, Key::Down), (K::Left, Key::Left), (K::Right, Key::Right),
+                                     (K::J, Key::A), (K::K, Key::B), (K::L, Key::C),
+                                     (K::U, Key::X), (K::I, Key::Y), (K::O, Key::Z),
+                                     (K::Return, Key::Start), (K::RShift, Key::Select)].iter() {
+                    b.bind_key(code, key);
+                }
+            },
+            // Face buttons bottom, right, left, top, then shoulders, then Start Select, then the D-pad
+            Device::Gamepad(_) => {
+                for &(button, key) in [(0, Key::A), (1, Key::B), (2, Key::X), (3, Key::Y), (4, Key::C), (5, Key::Z),
+                                       (6, Key::Start), (7, Key::Select),
+                                       (8, Key::Up), (9, Key::Down), (10, Key::Left), (11, Key::Right)].iter() {
+                    b.bind_button(button, key);
+                }
+            },
+        }
+        b
+    }
+
+    pub fn bind_key(&mut self, code: VirtualKeyCode, key: Key) {
+        self.keys.insert(code, key);
+    }
+
+    pub fn bind_button(&mut self, button: ButtonId, key: Key) {
+        self.buttons.insert(button, key);
+    }
+
+    pub fn key(&self, code: VirtualKeyCode) -> Option<Key> {
+        self.keys.get(&code).cloned()
+    }
+
+    pub fn button(&self, button: ButtonId) -> Option<Key> {
+        self.buttons.get(&button).cloned()
+    }
+}
+
+pub struct Player {
+    pub device: Device,
+    pub bindings: Bindings,
+    pub actions: ActionMap, // Fed this player's keys only
+    held: HashSet<Key>,
+    // These only last a frame
+    pressed: HashSet<Key>,
+    released: HashSet<Key>,
+}
+
+impl Player {
+    fn new(device: Device, actions: ActionMap) -> Player {
+        Player {
+            device: device,
+            bindings: Bindings::default_for(device),
+            actions: actions,
+            held: HashSet::new(),
+            pressed: HashSet::new(),
+            released: HashSet::new(),
+        }
+    }
+
+    // For keys that don't come from the player's device (ex. the touch screen)
+    pub fn process(&mut self, e: KeyEvent) {
+        self.actions.process(&e);
+        match e {
+            KeyEvent::Pressed(k) => {
+                if self.held.insert(k) {
+                    self.pressed.insert(k);
+                }
+            },
+            KeyEvent::Released(k) => {
+                self.held.remove(&k);
+                self.released.insert(k);
+            },
+        }
+    }
+
+    pub fn is_held(&self, k: Key) -> bool {
+        self.held.contains(&k)
+    }
+
+    pub fn was_pressed(&self, k: Key) -> bool {
+        self.pressed.contains(&k)
+    }
+
+    pub fn was_released(&self, k: Key) -> bool {
+        self.released.contains(&k)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PlayerEvent {
+    Joined(usize, Device), // Slot Device
+    Left(usize),
+    // Slot Event
+    Key(usize, KeyEvent),
+}
+
+pub struct Players {
+    slots: Vec<Option<Player>>,
+    // Devices that can join by pressing Start
+    devices: Vec<Device>,
+    actions: ActionMap, // What players start out with when they join, contexts and all
+}
+
+impl Players {
+    pub fn new(slots: usize) -> Players {
+        Players {
+            slots: (0..slots).map(|_| None).collect(),
+            devices: vec![Device::KeyboardLeft, Device::KeyboardRight],
+            actions: ActionMap::new(),
+        }
+    }
+
+    // Gives everyone, and everyone who joins later, a copy of actions
+    pub fn set_actions(&mut self, actions: ActionMap) {
+        for p in self.slots.iter_mut().filter_map(|s| s.as_mut()) {
+            p.actions = actions.clone();
+        }
+        self.actions = actions;
+    }
+
+    // Pushes a context for everyone. Leaves everyone alone if there's no such context.
+    pub fn push_context(&mut self, name: &str) -> Result<(), ActionError> {
+        try!(self.actions.push(name));
+        for p in self.slots.iter_mut().filter_map(|s| s.as_mut()) {
+            try!(p.actions.push(name));
+        }
+        Ok(())
+    }
+
+    pub fn pop_context(&mut self) -> Option<String> {
+        for p in self.slots.iter_mut().filter_map(|s| s.as_mut()) {
+            p.actions.pop();
+        }
+        self.actions.pop()
+    }
+
+    pub fn current_context(&self) -> Option<&str> {
+        self.actions.current()
+    }
+
+    // Whether anyone started doing action this frame
+    pub fn any_pressed(&self, action: &str) -> bool {
+        self.slots.iter().filter_map(|s| s.as_ref()).any(|p| p.actions.was_pressed(action))
+    }
+
+    // A gamepad got plugged in
+    pub fn connect(&mut self, device: Device) {
+        if !self.devices.contains(&device) {
+            self.devices.push(device);
+        }
+    }
+
+    // A gamepad got pulled out. Whoever was using it leaves.
+    pub fn disconnect(&mut self, device: Device) -> Option<PlayerEvent> {
+        self.devices.retain(|&d| d != device);
+        self.slot_of(device).map(|slot| self.leave(slot).unwrap())
+    }
+
+    pub fn join(&mut self, device: Device) -> Option<PlayerEvent> {
+        if self.slot_of(device).is_some() { return None }
+        let free = match self.slots.iter().position(|s| s.is_none()) {
+            Some(i) => i,
+            None => return None
+        };
+        self.slots[free] = Some(Player::new(device, self.actions.clone()));
+        Some(PlayerEvent::Joined(free, device))
+    }
+
+    pub fn leave(&mut self, slot: usize) -> Option<PlayerEvent> {
+        match self.slots.get_mut(slot).and_then(|s| s.take()) {
+            Some(_) => Some(PlayerEvent::Left(slot)),
+            None => None
+        }
+    }
+
+    pub fn player(&self, slot: usize) -> Option<&Player> {
+        self.slots.get(slot).and_then(|s| s.as_ref())
+    }
+
+    pub fn player_mut(&mut self, slot: usize) -> Option<&mut Player> {
+        self.slots.get_mut(slot).and_then(|s| s.as_mut())
+    }
+
+    // Slots with someone in them
+    pub fn joined(&self) -> Vec<usize> {
+        (0..self.slots.len()).filter(|&i| self.slots[i].is_some()).collect()
+    }
+
+    pub fn slot_of(&self, device: Device) -> Option<usize> {
+        self.slots.iter().position(|s| s.as_ref().map(|p| p.device == device).unwrap_or(false))
+    }
+
+    // Call once a frame, after the frame's events have been read
+    pub fn next_frame(&mut self) {
+        for p in self.slots.iter_mut().filter_map(|s| s.as_mut()) {
+            p.pressed.clear();
+            p.released.clear();
+            p.actions.next_frame();
+        }
+    }
+
+    pub fn process(&mut self, e: &Event) -> Vec<PlayerEvent> {
+        match *e {
+            Event::KeyboardInput(state, _, Some(code)) => {
+                let mut out = vec![];
+                for &device in [Device::KeyboardLeft, Device::KeyboardRight].iter() {
+                    out.extend(self.input(device, state, |b| b.key(code)));
+                }
+                out
+            },
+            _ => vec![]
+        }
+    }
+
+    pub fn gamepad_button(&mut self, pad: usize, button: ButtonId, state: ElementState) -> Vec<PlayerEvent> {
+        self.input(Device::Gamepad(pad), state, |b| b.button(button))
+    }
+
+    // Sends a device's input to its player, or joins it up if it pressed Start
+    fn input<F: Fn(&Bindings) -> Option<Key>>(&mut self, device: Device, state: ElementState, lookup: F) -> Vec<PlayerEvent> {
+        if !self.devices.contains(&device) { return vec![] }
+        match self.slot_of(device) {
+            Some(slot) => {
+                let player = self.slots[slot].as_mut().unwrap();
+                match lookup(&player.bindings) {
+                    Some(k) => {
+                        let e = match state {
+                            ElementState::Pressed => KeyEvent::Pressed(k),
+                            ElementState::Released => KeyEvent::Released(k),
+                        };
+                        player.process(e);
+                        vec![PlayerEvent::Key(slot, e)]
+                    },
+                    None => vec![]
+                }
+            },
+            None => {
+                let start = lookup(&Bindings::default_for(device)) == Some(Key::Start);
+                if start && state == ElementState::Pressed {
+                    self.join(device).into_iter().collect()
+                } else {
+                    vec![]
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glium::glutin::{Event, ElementState, VirtualKeyCode};
+    use input::{Key, KeyEvent, ActionMap};
+    use super::{Players, PlayerEvent, Device};
+
+    fn key(state: ElementState, code: VirtualKeyCode) -> Event {
+        Event::KeyboardInput(state, 0, Some(code))
+    }
+
+    #[test]
+    fn join_with_start() {
+        let mut players = Players::new(2);
+        // Nobody's there to move
+        assert!(players.process(&key(ElementState::Pressed, VirtualKeyCode::W)).is_empty());
+        assert_eq!(players.process(&key(ElementState::Pressed, VirtualKeyCode::Return)),
+                   vec![PlayerEvent::Joined(0, Device::KeyboardRight)]);
+        assert_eq!(players.process(&key(ElementState::Pressed, VirtualKeyCode::Tab)),
+                   vec![PlayerEvent::Joined(1, Device::KeyboardLeft)]);
+        assert_eq!(players.gamepad_button(0, 6, ElementState::Pressed), vec![]); // Not connected
+        players.connect(Device::Gamepad(0));
+        assert_eq!(players.gamepad_button(0, 6, ElementState::Pressed), vec![]); // No room
+        assert_eq!(players.joined(), vec![0, 1]);
+    }
+
+    #[test]
+    fn keys_go_to_their_player() {
+        let mut players = Players::new(2);
+        players.join(Device::KeyboardLeft);
+        players.join(Device::KeyboardRight);
+        assert_eq!(players.process(&key(ElementState::Pressed, VirtualKeyCode::F)),
+                   vec![PlayerEvent::Key(0, KeyEvent::Pressed(Key::A))]);
+        assert_eq!(players.process(&key(ElementState::Pressed, VirtualKeyCode::J)),
+                   vec![PlayerEvent::Key(1, KeyEvent::Pressed(Key::A))]);
+        players.process(&key(ElementState::Released, VirtualKeyCode::F));
+        assert!(!players.player(0).unwrap().is_held(Key::A));
+        assert!(players.player(1).unwrap().is_held(Key::A));
+        assert!(players.player(1).unwrap().was_pressed(Key::A));
+        players.next_frame();
+        assert!(!players.player(1).unwrap().was_pressed(Key::A));
+    }
+
+    #[test]
+    fn rebinding_and_leaving() {
+        let mut players = Players::new(2);
+        players.connect(Device::Gamepad(3));
+        assert_eq!(players.gamepad_button(3, 6, ElementState::Pressed), vec![PlayerEvent::Joined(0, Device::Gamepad(3))]);
+        players.player_mut(0).unwrap().bindings.bind_button(20, Key::Z);
+        assert_eq!(players.gamepad_button(3, 20, ElementState::Pressed), vec![PlayerEvent::Key(0, KeyEvent::Pressed(Key::Z))]);
+        assert_eq!(players.disconnect(Device::Gamepad(3)), Some(PlayerEvent::Left(0)));
+        assert!(players.joined().is_empty());
+        assert_eq!(players.leave(0), None);
+    }
+
+    #[test]
+    fn actions_per_player() {
+        let mut players = Players::new(3);
+        players.set_actions(ActionMap::parse("[klay]\nfire_light = [\"A\"]\n[menu]\nblocking = true\nmenu_confirm = [\"A\"]").unwrap());
+        players.join(Device::KeyboardLeft);
+        assert!(players.push_context("nope").is_err());
+        players.push_context("klay").unwrap();
+        // Joining late still gets the contexts everyone else has
+        players.join(Device::KeyboardRight);
+
+        players.process(&key(ElementState::Pressed, VirtualKeyCode::J));
+        assert!(players.player(1).unwrap().actions.was_pressed("fire_light"));
+        assert!(!players.player(0).unwrap().actions.was_pressed("fire_light"));
+        assert!(players.any_pressed("fire_light"));
+        players.next_frame();
+        assert!(!players.any_pressed("fire_light"));
+
+        players.push_context("menu").unwrap();
+        assert_eq!(players.current_context(), Some("menu"));
+        players.process(&key(ElementState::Pressed, VirtualKeyCode::F));
+        assert!(players.player(0).unwrap().actions.was_pressed("menu_confirm"));
+        assert!(!players.player(0).unwrap().actions.was_pressed("fire_light"));
+        assert_eq!(players.pop_context(), Some("menu".into()));
+        assert_eq!(players.current_context(), Some("klay"));
+    }
+}
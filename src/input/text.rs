@@ -0,0 +1,315 @@
+///! Typing: names for save files, commands for the debug console...
+use glium::glutin::{Event, ElementState, VirtualKeyCode};
+
+// An editable line of text with a caret and maybe a selection.
+// Positions count chars, not bytes, and run from 0 (before the first char) to len().
+#[derive(Clone, Debug)]
+pub struct TextBuffer {
+    text: String,
+    caret: usize,
+    anchor: Option<usize>, // The other end of the selection, if there is one
+    max_len: Option<usize>, // In chars
+    // The keyboard doesn't tell us about modifiers, so we keep track ourselves
+    shift: bool,
+    ctrl: bool,
+}
+
+impl TextBuffer {
+    pub fn new() -> TextBuffer {
+        TextBuffer {
+            text: String::new(),
+            caret: 0,
+            anchor: None,
+            max_len: None,
+            shift: false,
+            ctrl: false,
+        }
+    }
+
+    pub fn with_max_len(max: usize) -> TextBuffer {
+        let mut b = TextBuffer::new();
+        b.max_len = Some(max);
+        b
+    }
+
+    // Starts out holding s (cut down to the max length), with the caret at the end
+    pub fn with_text(mut self, s: &str) -> TextBuffer {
+        self.text.clear();
+        self.caret = 0;
+        self.anchor = None;
+        self.insert_str(s);
+        self
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn len(&self) -> usize {
+        self.text.chars().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+
+    pub fn caret(&self) -> usize {
+        self.caret
+    }
+
+    // Start and end of the selection, start first
+    pub fn selection(&self) -> Option<(usize, usize)> {
+        match self.anchor {
+            Some(a) if a != self.caret => Some((a.min(self.caret), a.max(self.caret))),
+            _ => None
+        }
+    }
+
+    pub fn selected_text(&self) -> Option<&str> {
+        self.selection().map(|(a, b)| &self.text[self.byte(a)..self.byte(b)])
+    }
+
+    pub fn clear(&mut self) {
+        self.text.clear();
+        self.caret = 0;
+        self.anchor = None;
+    }
+
+    pub fn select_all(&mut self) {
+        self.anchor = Some(0);
+        self.caret = self.len();
+    }
+
+    // Takes the place of the selection, if there is one. Whatever doesn't fit is dropped.
+    pub fn insert_str(&mut self, s: &str) {
+        self.delete_selection();
+        for c in s.chars() {
+            if !self.insert(c) { break }
+        }
+    }
+
+    // Returns false if the buffer was full
+    pub fn insert_char(&mut self, c: char) -> bool {
+        self.delete_selection();
+        self.insert(c)
+    }
+
+    fn insert(&mut self, c: char) -> bool {
+        if let Some(max) = self.max_len {
+            if self.len() >= max { return false }
+        }
+        let at = self.byte(self.caret);
+        self.text.insert(at, c);
+        self.caret += 1;
+        true
+    }
+
+    // Deletes the selection, or the char before the caret
+    pub fn backspace(&mut self) {
+        if self.delete_selection() { return }
+        if self.caret > 0 {
+            self.caret -= 1;
+            let at = self.byte(self.caret);
+            self.text.remove(at);
+        }
+    }
+
+    // Deletes the selection, or the char after the caret
+    pub fn delete(&mut self) {
+        if self.delete_selection() { return }
+        if self.caret < self.len() {
+            let at = self.byte(self.caret);
+            self.text.remove(at);
+        }
+    }
+
+    // Moving with select held grows the selection, and moving without it drops it
+    pub fn move_to(&mut self, pos: usize, select: bool) {
+        if select {
+            if self.anchor.is_none() {
+                self.anchor = Some(self.caret);
+            }
+        } else {
+            self.anchor = None;
+        }
+        self.caret = pos.min(self.len());
+    }
+
+    pub fn move_left(&mut self, select: bool) {
+        // Without select, a selection collapses to its start
+        let pos = match self.selection() {
+            Some((a, _)) if !select => a,
+            _ => self.caret.saturating_sub(1)
+        };
+        self.move_to(pos, select);
+    }
+
+    pub fn move_right(&mut self, select: bool) {
+        let pos = match self.selection() {
+            Some((_, b)) if !select => b,
+            _ => self.caret + 1
+        };
+        self.move_to(pos, select);
+    }
+
+    pub fn home(&mut self, select: bool) {
+        self.move_to(0, select);
+    }
+
+    pub fn end(&mut self, select: bool) {
+        let len = self.len();
+        self.move_to(len, select);
+    }
+
+    // Returns true if there was anything to delete
+    fn delete_selection(&mut self) -> bool {
+        match self.selection() {
+            Some((a, b)) => {
+                let (start, end) = (self.byte(a), self.byte(b));
+                self.text.drain(start..end);
+                self.caret = a;
+                self.anchor = None;
+                true
+            },
+            None => {
+                self.anchor = None;
+                false
+            }
+        }
+    }
+
+    // Byte offset of a char position
+    fn byte(&self, pos: usize) -> usize {
+        self.text.char_indices().nth(pos).map(|(i, _)| i).unwrap_or(self.text.len())
+    }
+
+    // Feeds an event into the buffer. Returns true if the buffer used it.
+    pub fn process(&mut self, e: &Event) -> bool {
+        match *e {
+            // Control characters (backspace, return, tab...) come as key presses too, so we use those
+            Event::ReceivedCharacter(c) if !c.is_control() => {
+                // Ctrl+A also sends an 'a' on some platforms
+                if self.ctrl { return true }
+                self.insert_char(c);
+                true
+            },
+            Event::KeyboardInput(state, _, Some(code)) => {
+                let pressed = state == ElementState::Pressed;
+                match code {
+                    VirtualKeyCode::LShift | VirtualKeyCode::RShift => self.shift = pressed,
+                    VirtualKeyCode::LControl | VirtualKeyCode::RControl => self.ctrl = pressed,
+                    _ if !pressed => return false,
+                    VirtualKeyCode::Back => self.backspace(),
+                    VirtualKeyCode::Delete => self.delete(),
+                    VirtualKeyCode::Left => { let s = self.shift; self.move_left(s) },
+                    VirtualKeyCode::Right => { let s = self.shift; self.move_right(s) },
+                    VirtualKeyCode::Home => { let s = self.shift; self.home(s) },
+                    VirtualKeyCode::End => { let s = self.shift; self.end(s) },
+                    VirtualKeyCode::A if self.ctrl => self.select_all(),
+                    _ => return false
+                }
+                true
+            },
+            _ => false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glium::glutin::{Event, ElementState, VirtualKeyCode};
+    use super::TextBuffer;
+
+    fn key(code: VirtualKeyCode, state: ElementState) -> Event {
+        Event::KeyboardInput(state, 0, Some(code))
+    }
+
+    #[test]
+    fn multibyte_chars() {
+        let mut b = TextBuffer::new().with_text("añ€😀z");
+        assert_eq!(b.len(), 5);
+        assert_eq!(b.caret(), 5);
+        b.move_left(false);
+        b.backspace();
+        assert_eq!(b.text(), "añ€z");
+        assert_eq!(b.caret(), 3);
+        b.home(false);
+        b.move_right(false);
+        b.delete();
+        assert_eq!(b.text(), "a€z");
+        b.insert_char('ü');
+        assert_eq!(b.text(), "aü€z");
+        assert_eq!(b.caret(), 2);
+        // Nothing to delete past either end
+        b.end(false);
+        b.delete();
+        b.home(false);
+        b.backspace();
+        assert_eq!(b.text(), "aü€z");
+    }
+
+    #[test]
+    fn selections() {
+        let mut b = TextBuffer::new().with_text("héllo wörld");
+        b.home(false);
+        for _ in 0..5 {
+            b.move_right(true);
+        }
+        assert_eq!(b.selection(), Some((0, 5)));
+        assert_eq!(b.selected_text(), Some("héllo"));
+        b.insert_str("bye");
+        assert_eq!(b.text(), "bye wörld");
+        assert_eq!(b.caret(), 3);
+        assert_eq!(b.selection(), None);
+
+        // Selecting backwards works the same
+        b.end(false);
+        b.move_left(true);
+        b.move_left(true);
+        assert_eq!(b.selected_text(), Some("ld"));
+        b.backspace();
+        assert_eq!(b.text(), "bye wör");
+        // Moving without select collapses to the selection's start
+        b.select_all();
+        b.move_left(false);
+        assert_eq!(b.caret(), 0);
+        assert_eq!(b.selection(), None);
+    }
+
+    #[test]
+    fn max_len() {
+        let mut b = TextBuffer::with_max_len(4).with_text("ñññññ");
+        assert_eq!(b.text(), "ññññ");
+        assert!(!b.insert_char('x'));
+        assert_eq!(b.text(), "ññññ");
+        // Replacing a selection makes room first
+        b.move_left(true);
+        b.move_left(true);
+        b.insert_str("xyz");
+        assert_eq!(b.text(), "ññxy");
+        assert_eq!(b.caret(), 4);
+    }
+
+    #[test]
+    fn events() {
+        let mut b = TextBuffer::new();
+        assert!(b.process(&Event::ReceivedCharacter('h')));
+        assert!(b.process(&Event::ReceivedCharacter('é')));
+        // Backspace comes as a key press too, and shouldn't get typed
+        assert!(!b.process(&Event::ReceivedCharacter('\u{8}')));
+        assert!(b.process(&key(VirtualKeyCode::LShift, ElementState::Pressed)));
+        assert!(b.process(&key(VirtualKeyCode::Left, ElementState::Pressed)));
+        assert!(b.process(&key(VirtualKeyCode::LShift, ElementState::Released)));
+        assert_eq!(b.selected_text(), Some("é"));
+        assert!(b.process(&key(VirtualKeyCode::Back, ElementState::Pressed)));
+        assert_eq!(b.text(), "h");
+
+        // Ctrl+A selects everything without typing an 'a'
+        b.process(&key(VirtualKeyCode::LControl, ElementState::Pressed));
+        b.process(&key(VirtualKeyCode::A, ElementState::Pressed));
+        b.process(&Event::ReceivedCharacter('a'));
+        b.process(&key(VirtualKeyCode::LControl, ElementState::Released));
+        assert_eq!(b.selected_text(), Some("h"));
+        assert!(!b.process(&key(VirtualKeyCode::Back, ElementState::Released)));
+    }
+}
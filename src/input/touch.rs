@@ -0,0 +1,226 @@
+///! Touch screens: fingers on on-screen regions press the virtual keys
+use std::collections::{HashMap, HashSet};
+use glium::glutin::{Event, Touch, TouchPhase};
+use gui::{GuiRect, GuiInstruction, GuiPipeIn};
+use components::Align;
+use super::{Key, KeyEvent};
+
+// A patch of screen that holds key down while a finger is on it
+#[derive(Clone, Debug, PartialEq)]
+pub struct TouchRegion {
+    pub key: Key,
+    pub rect: GuiRect, // In window pixels, like the GUI
+}
+
+// A finger on the screen
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TouchPoint {
+    pub id: u64,
+    pub position: (f32, f32), // In window pixels, from the top left
+    pub start: (f32, f32), // Where it first came down
+}
+
+pub struct TouchPad {
+    regions: Vec<TouchRegion>,
+    points: HashMap<u64, TouchPoint>,
+    // Only drawn once the screen's been touched, so mouse and keyboard players don't see it
+    visible: bool,
+}
+
+impl TouchPad {
+    pub fn new() -> TouchPad {
+        TouchPad {
+            regions: vec![],
+            points: HashMap::new(),
+            visible: false,
+        }
+    }
+
+    // A D-pad in the bottom left, the face buttons in the bottom right, and Start and Select
+    // in the middle, sized for a w by h screen
+    pub fn default_layout(w: f32, h: f32) -> TouchPad {
+        let mut pad = TouchPad::new();
+        let s = (w.min(h) / 8.0).floor(); // A button's size
+        let m = s / 2.0; // Margin
+        let bottom = h - m - s * 3.0;
+        // D-pad, as a plus
+        pad.add_region(Key::Up, GuiRect::new(m + s, bottom, s, s));
+        pad.add_region(Key::Left, GuiRect::new(m, bottom + s, s, s));
+        pad.add_region(Key::Right, GuiRect::new(m + s * 2.0, bottom + s, s, s));
+        pad.add_region(Key::Down, GuiRect::new(m + s, bottom + s * 2.0, s, s));
+        // X Y Z over A B C
+        let right = w - m - s * 3.0;
+        for (i, &(top, low)) in [(Key::X, Key::A), (Key::Y, Key::B), (Key::Z, Key::C)].iter().enumerate() {
+            let x = right + s * i as f32;
+            pad.add_region(top, GuiRect::new(x, bottom + s, s * 0.9, s * 0.9));
+            pad.add_region(low, GuiRect::new(x, bottom + s * 2.0, s * 0.9, s * 0.9));
+        }
+        pad.add_region(Key::Select, GuiRect::new(w / 2.0 - s * 1.5, h - m - s / 2.0, s, s / 2.0));
+        pad.add_region(Key::Start, GuiRect::new(w / 2.0 + s * 0.5, h - m - s / 2.0, s, s / 2.0));
+        pad
+    }
+
+    pub fn add_region(&mut self, key: Key, rect: GuiRect) {
+        self.regions.push(TouchRegion {
+            key: key,
+            rect: rect,
+        });
+    }
+
+    pub fn clear_regions(&mut self) {
+        self.regions.clear();
+    }
+
+    pub fn regions(&self) -> &[TouchRegion] {
+        &self.regions
+    }
+
+    // Every finger on the screen, for UI that wants them raw
+    pub fn points(&self) -> Vec<TouchPoint> {
+        self.points.values().cloned().collect()
+    }
+
+    // The keys held by fingers right now. Where regions overlap, a finger holds all of them.
+    pub fn held(&self) -> HashSet<Key> {
+        let mut keys = HashSet::new();
+        for p in self.points.values() {
+            for r in self.regions.iter() {
+                if r.rect.contains(p.position.0, p.position.1) {
+                    keys.insert(r.key);
+                }
+            }
+        }
+        keys
+    }
+
+    // Feeds a touch event in, adding any key changes it caused to out. Returns true if it was a touch.
+    pub fn process(&mut self, e: &Event, out: &mut Vec<KeyEvent>) -> bool {
+        let touch = match *e {
+            Event::Touch(ref t) => t.clone(),
+            _ => return false
+        };
+        self.visible = true;
+
+        let before = self.held();
+        self.apply(touch);
+        let after = self.held();
+
+        // Sliding from one region to the next lets go of the first
+        for k in before.difference(&after) {
+            out.push(KeyEvent::Released(*k));
+        }
+        for k in after.difference(&before) {
+            out.push(KeyEvent::Pressed(*k));
+        }
+        true
+    }
+
+    fn apply(&mut self, t: Touch) {
+        let pos = (t.location.0 as f32, t.location.1 as f32);
+        match t.phase {
+            TouchPhase::Started => {
+                self.points.insert(t.id, TouchPoint {
+                    id: t.id,
+                    position: pos,
+                    start: pos,
+                });
+            },
+            TouchPhase::Moved => {
+                if let Some(p) = self.points.get_mut(&t.id) {
+                    p.position = pos;
+                }
+            },
+            TouchPhase::Ended | TouchPhase::Cancelled => {
+                self.points.remove(&t.id);
+            },
+        }
+    }
+
+    pub fn draw(&self, out: &GuiPipeIn) {
+        if !self.visible { return }
+        let held = self.held();
+        for r in self.regions.iter() {
+            let alpha = if held.contains(&r.key) { 0.6 } else { 0.25 };
+            out.send(GuiInstruction::Panel(r.rect, [1.0, 1.0, 1.0, alpha])).unwrap();
+            let size = (r.rect.h / 2.0).floor();
+            out.send(GuiInstruction::Text(format!("{:?}", r.key), "basic".into(), size, [0.0, 0.0, 0.0, 0.8], Align::Center,
+                r.rect.x + r.rect.w / 2.0, r.rect.y + (r.rect.h - size) / 2.0)).unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glium::glutin::{Event, Touch, TouchPhase};
+    use gui::GuiRect;
+    use input::{Key, KeyEvent};
+    use super::TouchPad;
+
+    fn touch(phase: TouchPhase, x: f64, y: f64, id: u64) -> Event {
+        Event::Touch(Touch {
+            phase: phase,
+            location: (x, y),
+            id: id,
+        })
+    }
+
+    fn pad() -> TouchPad {
+        let mut pad = TouchPad::new();
+        pad.add_region(Key::Left, GuiRect::new(0.0, 0.0, 10.0, 10.0));
+        pad.add_region(Key::Right, GuiRect::new(10.0, 0.0, 10.0, 10.0));
+        pad.add_region(Key::A, GuiRect::new(50.0, 0.0, 10.0, 10.0));
+        pad
+    }
+
+    #[test]
+    fn press_and_release() {
+        let mut pad = pad();
+        let mut out = vec![];
+        assert!(pad.process(&touch(TouchPhase::Started, 5.0, 5.0, 1), &mut out));
+        assert_eq!(out, vec![KeyEvent::Pressed(Key::Left)]);
+        out.clear();
+        pad.process(&touch(TouchPhase::Ended, 5.0, 5.0, 1), &mut out);
+        assert_eq!(out, vec![KeyEvent::Released(Key::Left)]);
+        assert!(pad.points().is_empty());
+    }
+
+    #[test]
+    fn sliding_between_regions() {
+        let mut pad = pad();
+        let mut out = vec![];
+        pad.process(&touch(TouchPhase::Started, 5.0, 5.0, 1), &mut out);
+        out.clear();
+        pad.process(&touch(TouchPhase::Moved, 15.0, 5.0, 1), &mut out);
+        assert_eq!(out, vec![KeyEvent::Released(Key::Left), KeyEvent::Pressed(Key::Right)]);
+        out.clear();
+        // Off every region
+        pad.process(&touch(TouchPhase::Moved, 35.0, 5.0, 1), &mut out);
+        assert_eq!(out, vec![KeyEvent::Released(Key::Right)]);
+        assert_eq!(pad.points()[0].start, (5.0, 5.0));
+    }
+
+    #[test]
+    fn fingers_share_keys() {
+        let mut pad = pad();
+        let mut out = vec![];
+        pad.process(&touch(TouchPhase::Started, 55.0, 5.0, 1), &mut out);
+        pad.process(&touch(TouchPhase::Started, 2.0, 2.0, 2), &mut out);
+        pad.process(&touch(TouchPhase::Started, 56.0, 6.0, 3), &mut out);
+        assert_eq!(out, vec![KeyEvent::Pressed(Key::A), KeyEvent::Pressed(Key::Left)]);
+        out.clear();
+        // A is still held by the third finger
+        pad.process(&touch(TouchPhase::Cancelled, 55.0, 5.0, 1), &mut out);
+        assert!(out.is_empty());
+        pad.process(&touch(TouchPhase::Ended, 56.0, 6.0, 3), &mut out);
+        assert_eq!(out, vec![KeyEvent::Released(Key::A)]);
+        assert_eq!(pad.points().len(), 1);
+    }
+
+    #[test]
+    fn ignores_other_events() {
+        let mut pad = pad();
+        let mut out = vec![];
+        assert!(!pad.process(&Event::MouseMoved(5, 5), &mut out));
+        assert!(out.is_empty());
+    }
+}
@@ -0,0 +1,6 @@
+///! Loading levels and the things in them
+mod prefab;
+mod tiled;
+
+pub use self::prefab::{Prefab, PrefabError};
+pub use self::tiled::{Level, MapObject, MapError, parse_tmx, parse_json};
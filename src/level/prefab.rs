@@ -0,0 +1,195 @@
+///! Entities described in "data/entities/<name>.ent.toml", one table per component:
+///!
+///! [spatial]
+///! origin = [16, 16]
+///! rotation = 90.0
+///!
+///! [visualtype]
+///! type = "still"
+///! size = [32, 32]
+///! color = [1.0, 0.0, 0.0, 1.0]
+///! texture = "crate.png" # Optional, relative to "data/textures"
+///!
+///! [depth]
+///! layer = "world"
+///! depth = 1.0
+///!
+///! Anything left out is left off the entity. Where it goes is up to whoever spawns it.
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use cgmath::{Point2, Vector2};
+use specs::{World, Entity};
+use toml::{self, Value};
+use components::{Spatial, VisualType, Depth, RenderLayer, Tint};
+use graphics::Vertex;
+
+#[derive(Debug)]
+pub enum PrefabError {
+    Io(io::Error),
+    Parse(String),
+    Value(String),
+}
+
+impl From<io::Error> for PrefabError {
+    fn from(e: io::Error) -> PrefabError {
+        PrefabError::Io(e)
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct Prefab {
+    pub spatial: Option<Spatial>,
+    pub visual: Option<VisualType>,
+    pub depth: Option<Depth>,
+    pub tint: Option<Tint>,
+}
+
+impl Prefab {
+    pub fn load(name: &str) -> Result<Prefab, PrefabError> {
+        let path = Path::new("data").join("entities").join(format!("{}.ent.toml", name));
+        let mut s = String::new();
+        try!(try!(File::open(&path)).read_to_string(&mut s));
+        Prefab::parse(&s)
+    }
+
+    pub fn parse(s: &str) -> Result<Prefab, PrefabError> {
+        let mut parser = toml::Parser::new(s);
+        let table = match parser.parse() {
+            Some(t) => t,
+            None => {
+                let e = &parser.errors[0];
+                let (line, col) = parser.to_linecol(e.lo);
+                return Err(PrefabError::Parse(format!("{}:{}: {}", line + 1, col + 1, e.desc)));
+            }
+        };
+
+        let mut prefab = Prefab::default();
+        for (name, v) in table.iter() {
+            match &name[..] {
+                "spatial" => prefab.spatial = Some(try!(parse_spatial(v))),
+                "visualtype" => prefab.visual = Some(try!(parse_visual(v))),
+                "depth" => prefab.depth = Some(try!(parse_depth(v))),
+                "tint" => prefab.tint = Some(Tint(try!(color(v, "tint")))),
+                _ => return Err(PrefabError::Value(format!("Unknown component {}", name)))
+            }
+        }
+        Ok(prefab)
+    }
+
+    // Makes one, with its bottom left corner at pos
+    pub fn spawn(&self, world: &mut World, pos: Point2<f32>) -> Entity {
+        let spatial = match self.spatial {
+            Some(ref s) => Spatial { pos: pos, ..s.clone() },
+            None => Spatial::new(pos, Point2::new(0.0, 0.0)),
+        };
+        let mut builder = world.create_now().with(spatial);
+        if let Some(ref v) = self.visual {
+            builder = builder.with(v.clone());
+        }
+        if let Some(d) = self.depth {
+            builder = builder.with(d);
+        }
+        if let Some(t) = self.tint {
+            builder = builder.with(t);
+        }
+        builder.build()
+    }
+}
+
+fn number(v: &Value) -> Option<f32> {
+    v.as_float().map(|f| f as f32).or(v.as_integer().map(|i| i as f32))
+}
+
+// [x, y]
+fn pair(v: &Value, what: &str) -> Result<(f32, f32), PrefabError> {
+    match v.as_slice() {
+        Some(s) if s.len() == 2 => match (number(&s[0]), number(&s[1])) {
+            (Some(x), Some(y)) => return Ok((x, y)),
+            _ => ()
+        },
+        _ => ()
+    }
+    Err(PrefabError::Value(format!("{} should be two numbers", what)))
+}
+
+fn color(v: &Value, what: &str) -> Result<[f32; 4], PrefabError> {
+    let c: Vec<f32> = v.as_slice().map(|s| s.iter().filter_map(number).collect()).unwrap_or(vec![]);
+    if c.len() != 4 {
+        return Err(PrefabError::Value(format!("{} should be four numbers (RGBA)", what)));
+    }
+    Ok([c[0], c[1], c[2], c[3]])
+}
+
+fn parse_spatial(v: &Value) -> Result<Spatial, PrefabError> {
+    let mut s = Spatial::new(Point2::new(0.0, 0.0), Point2::new(0.0, 0.0));
+    let t = try!(v.as_table().ok_or(PrefabError::Value("spatial should be a table".into())));
+    for (k, v) in t.iter() {
+        match &k[..] {
+            "origin" => {
+                let (x, y) = try!(pair(v, "spatial.origin"));
+                s.origin = Point2::new(x, y);
+            },
+            "scale" => {
+                let (x, y) = try!(pair(v, "spatial.scale"));
+                s.scale = Vector2::new(x, y);
+            },
+            "rotation" => s.rotation = try!(number(v).ok_or(PrefabError::Value("spatial.rotation should be a number".into()))),
+            // What entities looked like before rotation had its own field
+            "transform" => if let Some(rot) = v.lookup("rot").and_then(number) {
+                s.rotation = rot;
+            },
+            _ => return Err(PrefabError::Value(format!("Unknown field spatial.{}", k)))
+        }
+    }
+    Ok(s)
+}
+
+fn parse_visual(v: &Value) -> Result<VisualType, PrefabError> {
+    let t = try!(v.as_table().ok_or(PrefabError::Value("visualtype should be a table".into())));
+    for k in t.keys() {
+        match &k[..] {
+            "type" | "size" | "color" | "texture" => (),
+            _ => return Err(PrefabError::Value(format!("Unknown field visualtype.{}", k)))
+        }
+    }
+    match v.lookup("type").and_then(|t| t.as_str()) {
+        Some("still") => (),
+        _ => return Err(PrefabError::Value("visualtype.type should be \"still\"".into()))
+    }
+    let (w, h) = match v.lookup("size") {
+        Some(s) => try!(pair(s, "visualtype.size")),
+        None => (32.0, 32.0),
+    };
+    let c = match v.lookup("color") {
+        Some(c) => try!(color(c, "visualtype.color")),
+        None => [1.0; 4],
+    };
+    let texture = match v.lookup("texture") {
+        Some(t) => Some(try!(t.as_str().ok_or(PrefabError::Value("visualtype.texture should be a string".into()))).to_string()),
+        None => None,
+    };
+    let quad = vec![
+        Vertex { position: [0.0, 0.0], color: c, tex_coords: [0.0, 0.0] },
+        Vertex { position: [w, 0.0], color: c, tex_coords: [1.0, 0.0] },
+        Vertex { position: [w, h], color: c, tex_coords: [1.0, 1.0] },
+        Vertex { position: [0.0, h], color: c, tex_coords: [0.0, 1.0] },
+    ];
+    Ok(VisualType::Still(quad, Some(vec![0, 1, 2, 0, 2, 3]), texture))
+}
+
+fn parse_depth(v: &Value) -> Result<Depth, PrefabError> {
+    let layer = match v.lookup("layer").and_then(|l| l.as_str()) {
+        Some("background") => RenderLayer::Background,
+        Some("world") | None => RenderLayer::World,
+        Some("bullets") => RenderLayer::Bullets,
+        Some("effects") => RenderLayer::Effects,
+        Some("foreground") => RenderLayer::Foreground,
+        Some(other) => return Err(PrefabError::Value(format!("Unknown layer {}", other)))
+    };
+    let depth = match v.lookup("depth") {
+        Some(d) => try!(number(d).ok_or(PrefabError::Value("depth.depth should be a number".into()))),
+        None => 0.0,
+    };
+    Ok(Depth::new(layer, depth))
+}
@@ -0,0 +1,493 @@
+///! Imports maps made in Tiled (mapeditor.org), saved as .tmx or .json.
+///!
+///! Tile layers become a Tilemap, and tiles with solid = true in the tileset become TileCollision.
+///! Objects become entities: an object's type names the prefab it's made from
+///! (ex. type "bat" spawns "data/entities/bat.ent.toml").
+///!
+///! NOTE Only csv (and plain xml, in .tmx) layer data for now. Set Tiled to save layers as CSV.
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read, BufReader};
+use std::path::{Path, PathBuf, Component};
+use cgmath::Point2;
+use specs::{World, Entity};
+use xml::reader::{EventReader, XmlEvent};
+use serde_json::{self, Value};
+use components::{Spatial, Tilemap, Tileset};
+use super::prefab::{Prefab, PrefabError};
+
+#[derive(Debug)]
+pub enum MapError {
+    Io(io::Error),
+    Xml(String),
+    Json(String),
+    Value(String), // Parsed fine, but doesn't make sense
+    Prefab(String, PrefabError), // PrefabName Error
+}
+
+impl From<io::Error> for MapError {
+    fn from(e: io::Error) -> MapError {
+        MapError::Io(e)
+    }
+}
+
+#[derive(Clone, Debug)]
+// Something placed on an object layer. In world units, with y going up from the bottom of the map.
+pub struct MapObject {
+    pub name: String,
+    pub kind: String, // Tiled's "type". Names the prefab.
+    pub pos: Point2<f32>, // Bottom left corner
+    pub size: (f32, f32),
+    pub properties: HashMap<String, String>,
+}
+
+pub struct Level {
+    pub tilemap: Tilemap,
+    pub objects: Vec<MapObject>,
+}
+
+impl Level {
+    // .tmx or .json, going by the extension
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Level, MapError> {
+        let path = path.as_ref();
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let mut s = String::new();
+        try!(try!(File::open(path)).read_to_string(&mut s));
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("tmx") => parse_tmx(&s, dir),
+            Some("json") => parse_json(&s, dir),
+            _ => Err(MapError::Value(format!("{} isn't a .tmx or .json map", path.display())))
+        }
+    }
+
+    // Makes the map's entity (with its bottom left corner at the world's origin) and everything on it.
+    // The map's entity comes first.
+    pub fn spawn(&self, world: &mut World) -> Result<Vec<Entity>, MapError> {
+        let mut prefabs: HashMap<&str, Prefab> = HashMap::new();
+        for o in self.objects.iter().filter(|o| !o.kind.is_empty()) {
+            if !prefabs.contains_key(&o.kind[..]) {
+                let prefab = try!(Prefab::load(&o.kind).map_err(|e| MapError::Prefab(o.kind.clone(), e)));
+                prefabs.insert(&o.kind, prefab);
+            }
+        }
+
+        let mut entities = vec![];
+        entities.push(world.create_now()
+            .with(Spatial::new(Point2::new(0.0, 0.0), Point2::new(0.0, 0.0)))
+            .with(self.tilemap.clone())
+            .with(self.tilemap.collision("solid"))
+            .build());
+        // Objects without a type are just markers (spawn points, triggers...) for whoever loaded the level
+        for o in self.objects.iter().filter(|o| !o.kind.is_empty()) {
+            entities.push(prefabs[&o.kind[..]].spawn(world, o.pos));
+        }
+        Ok(entities)
+    }
+
+    pub fn object_named(&self, name: &str) -> Option<&MapObject> {
+        self.objects.iter().find(|o| o.name == name)
+    }
+}
+
+// Tilesets keep their image next to the map (or near it), but textures get loaded from "data/textures"
+fn texture_name(dir: &Path, source: &str) -> String {
+    let mut path = PathBuf::new();
+    for c in dir.join(source).components() {
+        match c {
+            Component::ParentDir => { path.pop(); },
+            Component::CurDir => (),
+            c => path.push(c.as_os_str()),
+        }
+    }
+    let name = match path.strip_prefix(Path::new("data").join("textures")) {
+        Ok(p) => p.to_path_buf(),
+        Err(_) => PathBuf::from(path.file_name().unwrap_or(source.as_ref())),
+    };
+    name.components().map(|c| c.as_os_str().to_string_lossy().into_owned()).collect::<Vec<_>>().join("/")
+}
+
+fn parse_csv(data: &str) -> Result<Vec<u32>, MapError> {
+    data.split(',').map(|t| t.trim()).filter(|t| !t.is_empty())
+        .map(|t| t.parse().map_err(|_| MapError::Value(format!("{} isn't a tile", t))))
+        .collect()
+}
+
+// Turns an object from Tiled's y-down coordinates. Tile objects hang off their bottom left, and everything else
+// off its top left.
+fn flip_object(map_height: f32, x: f32, y: f32, w: f32, h: f32, is_tile: bool) -> Point2<f32> {
+    if is_tile {
+        Point2::new(x, map_height - y)
+    } else {
+        Point2::new(x, map_height - y - h)
+    }
+}
+
+// Just enough of an xml tree to walk a .tmx
+struct Node {
+    name: String,
+    attributes: HashMap<String, String>,
+    children: Vec<Node>,
+    text: String,
+}
+
+impl Node {
+    fn attr(&self, name: &str) -> Option<&str> {
+        self.attributes.get(name).map(|a| &a[..])
+    }
+
+    fn num<T: ::std::str::FromStr>(&self, name: &str) -> Result<T, MapError> {
+        self.attr(name).and_then(|a| a.parse().ok())
+            .ok_or(MapError::Value(format!("<{}> needs a number for {}", self.name, name)))
+    }
+
+    fn num_or<T: ::std::str::FromStr>(&self, name: &str, default: T) -> T {
+        self.attr(name).and_then(|a| a.parse().ok()).unwrap_or(default)
+    }
+
+    fn children_named<'a>(&'a self, name: &'a str) -> Box<Iterator<Item=&'a Node> + 'a> {
+        Box::new(self.children.iter().filter(move |c| c.name == name))
+    }
+
+    fn properties(&self) -> HashMap<String, String> {
+        let mut props = HashMap::new();
+        for p in self.children_named("properties").flat_map(|ps| ps.children_named("property")) {
+            if let Some(name) = p.attr("name") {
+                props.insert(name.to_string(), p.attr("value").unwrap_or(&p.text).to_string());
+            }
+        }
+        props
+    }
+}
+
+fn parse_xml<R: Read>(r: R) -> Result<Node, MapError> {
+    let mut stack: Vec<Node> = vec![];
+    for e in EventReader::new(r) {
+        match e {
+            Ok(XmlEvent::StartElement { name, attributes, .. }) => stack.push(Node {
+                name: name.local_name,
+                attributes: attributes.into_iter().map(|a| (a.name.local_name, a.value)).collect(),
+                children: vec![],
+                text: String::new(),
+            }),
+            Ok(XmlEvent::Characters(s)) => if let Some(n) = stack.last_mut() {
+                n.text.push_str(&s);
+            },
+            Ok(XmlEvent::EndElement { .. }) => {
+                let node = stack.pop().unwrap();
+                match stack.last_mut() {
+                    Some(parent) => parent.children.push(node),
+                    None => return Ok(node),
+                }
+            },
+            Ok(_) => (),
+            Err(e) => return Err(MapError::Xml(format!("{}", e))),
+        }
+    }
+    Err(MapError::Xml("No root element".into()))
+}
+
+fn tmx_tileset(node: &Node, first_id: u32, dir: &Path) -> Result<Tileset, MapError> {
+    let image = try!(node.children_named("image").next().ok_or(MapError::Value("Tilesets need an image".into())));
+    let source = try!(image.attr("source").ok_or(MapError::Value("<image> needs a source".into())));
+    let image_size: (u32, u32) = (try!(image.num("width")), try!(image.num("height")));
+    let tile_size: (u32, u32) = (try!(node.num("tilewidth")), try!(node.num("tileheight")));
+    let (margin, spacing) = (node.num_or("margin", 0), node.num_or("spacing", 0));
+    let columns = node.num_or("columns", (image_size.0 - margin * 2 + spacing) / (tile_size.0 + spacing));
+    let rows = (image_size.1 - margin * 2 + spacing) / (tile_size.1 + spacing);
+
+    let mut properties = HashMap::new();
+    for tile in node.children_named("tile") {
+        properties.insert(try!(tile.num("id")), tile.properties());
+    }
+    Ok(Tileset {
+        first_id: first_id,
+        texture: texture_name(dir, source),
+        image_size: image_size,
+        tile_size: tile_size,
+        margin: margin,
+        spacing: spacing,
+        columns: columns,
+        tile_count: node.num_or("tilecount", columns * rows),
+        properties: properties,
+    })
+}
+
+pub fn parse_tmx(s: &str, dir: &Path) -> Result<Level, MapError> {
+    let map = try!(parse_xml(s.as_bytes()));
+    if map.name != "map" {
+        return Err(MapError::Value("A .tmx should start with <map>".into()));
+    }
+    let (width, height): (u32, u32) = (try!(map.num("width")), try!(map.num("height")));
+    let tile_size: (f32, f32) = (try!(map.num("tilewidth")), try!(map.num("tileheight")));
+
+    let mut tilesets = vec![];
+    for t in map.children_named("tileset") {
+        let first_id = try!(t.num("firstgid"));
+        let tileset = match t.attr("source") {
+            // Saved in its own .tsx, with its image relative to that
+            Some(source) => {
+                let path = dir.join(source);
+                let tsx = try!(parse_xml(BufReader::new(try!(File::open(&path)))));
+                try!(tmx_tileset(&tsx, first_id, path.parent().unwrap_or(dir)))
+            },
+            None => try!(tmx_tileset(t, first_id, dir)),
+        };
+        tilesets.push(tileset);
+    }
+
+    let mut tilemap = Tilemap::new(width, height, tile_size, tilesets);
+    let map_height = height as f32 * tile_size.1;
+    let mut objects = vec![];
+    for layer in map.children.iter() {
+        match &layer.name[..] {
+            "layer" => {
+                let data = try!(layer.children_named("data").next().ok_or(MapError::Value("<layer> needs <data>".into())));
+                let tiles = match data.attr("encoding") {
+                    Some("csv") => try!(parse_csv(&data.text)),
+                    None => data.children_named("tile").map(|t| t.num_or("gid", 0)).collect(),
+                    Some(other) => return Err(MapError::Value(format!("Can't read {} layer data. Save it as CSV.", other)))
+                };
+                if tiles.len() != (width * height) as usize {
+                    return Err(MapError::Value(format!("Layer {} is the wrong size", layer.attr("name").unwrap_or(""))));
+                }
+                tilemap.add_layer(layer.attr("name").unwrap_or(""), tiles);
+                if layer.attr("visible") == Some("0") {
+                    let i = tilemap.layers().len() - 1;
+                    tilemap.set_visible(i, false);
+                }
+            },
+            "objectgroup" => for o in layer.children_named("object") {
+                let (x, y): (f32, f32) = (try!(o.num("x")), try!(o.num("y")));
+                let size: (f32, f32) = (o.num_or("width", 0.0), o.num_or("height", 0.0));
+                objects.push(MapObject {
+                    name: o.attr("name").unwrap_or("").into(),
+                    kind: o.attr("type").unwrap_or("").into(),
+                    pos: flip_object(map_height, x, y, size.0, size.1, o.attr("gid").is_some()),
+                    size: size,
+                    properties: o.properties(),
+                });
+            },
+            _ => ()
+        }
+    }
+    Ok(Level {
+        tilemap: tilemap,
+        objects: objects,
+    })
+}
+
+fn field<'a>(v: &'a Value, name: &str) -> Option<&'a Value> {
+    v.as_object().and_then(|o| o.get(name))
+}
+
+fn json_num(v: &Value, name: &str) -> Result<f64, MapError> {
+    field(v, name).and_then(|n| n.as_f64()).ok_or(MapError::Value(format!("{} should be a number", name)))
+}
+
+fn json_str<'a>(v: &'a Value, name: &str) -> &'a str {
+    field(v, name).and_then(|s| s.as_str()).unwrap_or("")
+}
+
+fn json_string(v: &Value) -> String {
+    match *v {
+        Value::String(ref s) => s.clone(),
+        ref other => format!("{}", other),
+    }
+}
+
+// Tiled has saved these as an object ({"solid": true}) and as a list ([{"name": "solid", "value": true}])
+fn json_properties(v: Option<&Value>) -> HashMap<String, String> {
+    let mut props = HashMap::new();
+    match v {
+        Some(&Value::Object(ref o)) => for (k, v) in o.iter() {
+            props.insert(k.clone(), json_string(v));
+        },
+        Some(&Value::Array(ref a)) => for p in a.iter() {
+            if let Some(value) = field(p, "value") {
+                props.insert(json_str(p, "name").to_string(), json_string(value));
+            }
+        },
+        _ => ()
+    }
+    props
+}
+
+fn json_tileset(t: &Value, first_id: u32, dir: &Path) -> Result<Tileset, MapError> {
+    let image_size = (try!(json_num(t, "imagewidth")) as u32, try!(json_num(t, "imageheight")) as u32);
+    let tile_size = (try!(json_num(t, "tilewidth")) as u32, try!(json_num(t, "tileheight")) as u32);
+    let margin = json_num(t, "margin").unwrap_or(0.0) as u32;
+    let spacing = json_num(t, "spacing").unwrap_or(0.0) as u32;
+    let columns = try!(json_num(t, "columns")) as u32;
+
+    let mut properties = HashMap::new();
+    if let Some(&Value::Object(ref o)) = field(t, "tileproperties") {
+        for (id, props) in o.iter() {
+            let id = try!(id.parse().map_err(|_| MapError::Value(format!("{} isn't a tile", id))));
+            properties.insert(id, json_properties(Some(props)));
+        }
+    }
+    if let Some(&Value::Array(ref tiles)) = field(t, "tiles") {
+        for tile in tiles.iter() {
+            properties.insert(try!(json_num(tile, "id")) as u32, json_properties(field(tile, "properties")));
+        }
+    }
+    Ok(Tileset {
+        first_id: first_id,
+        texture: texture_name(dir, json_str(t, "image")),
+        image_size: image_size,
+        tile_size: tile_size,
+        margin: margin,
+        spacing: spacing,
+        columns: columns,
+        tile_count: try!(json_num(t, "tilecount")) as u32,
+        properties: properties,
+    })
+}
+
+pub fn parse_json(s: &str, dir: &Path) -> Result<Level, MapError> {
+    let map: Value = try!(serde_json::from_str(s).map_err(|e| MapError::Json(format!("{}", e))));
+    let (width, height) = (try!(json_num(&map, "width")) as u32, try!(json_num(&map, "height")) as u32);
+    let tile_size = (try!(json_num(&map, "tilewidth")) as f32, try!(json_num(&map, "tileheight")) as f32);
+
+    let mut tilesets = vec![];
+    for t in field(&map, "tilesets").and_then(|t| t.as_array()).map(|t| &t[..]).unwrap_or(&[]) {
+        let first_id = try!(json_num(t, "firstgid")) as u32;
+        let tileset = match field(t, "source").and_then(|s| s.as_str()) {
+            Some(source) => {
+                let path = dir.join(source);
+                let mut s = String::new();
+                try!(try!(File::open(&path)).read_to_string(&mut s));
+                let external: Value = try!(serde_json::from_str(&s).map_err(|e| MapError::Json(format!("{}: {}", source, e))));
+                try!(json_tileset(&external, first_id, path.parent().unwrap_or(dir)))
+            },
+            None => try!(json_tileset(t, first_id, dir)),
+        };
+        tilesets.push(tileset);
+    }
+
+    let mut tilemap = Tilemap::new(width, height, tile_size, tilesets);
+    let map_height = height as f32 * tile_size.1;
+    let mut objects = vec![];
+    for layer in field(&map, "layers").and_then(|l| l.as_array()).map(|l| &l[..]).unwrap_or(&[]) {
+        match json_str(layer, "type") {
+            "tilelayer" => {
+                if field(layer, "encoding").is_some() && json_str(layer, "encoding") != "csv" {
+                    return Err(MapError::Value(format!("Can't read {} layer data. Save it as CSV.", json_str(layer, "encoding"))));
+                }
+                let data = try!(field(layer, "data").and_then(|d| d.as_array()).ok_or(MapError::Value("Tile layers need data".into())));
+                let tiles: Vec<u32> = data.iter().map(|t| t.as_u64().unwrap_or(0) as u32).collect();
+                if tiles.len() != (width * height) as usize {
+                    return Err(MapError::Value(format!("Layer {} is the wrong size", json_str(layer, "name"))));
+                }
+                tilemap.add_layer(json_str(layer, "name"), tiles);
+                if field(layer, "visible").and_then(|v| v.as_bool()) == Some(false) {
+                    let i = tilemap.layers().len() - 1;
+                    tilemap.set_visible(i, false);
+                }
+            },
+            "objectgroup" => for o in field(layer, "objects").and_then(|o| o.as_array()).map(|o| &o[..]).unwrap_or(&[]) {
+                let (x, y) = (try!(json_num(o, "x")) as f32, try!(json_num(o, "y")) as f32);
+                let size = (json_num(o, "width").unwrap_or(0.0) as f32, json_num(o, "height").unwrap_or(0.0) as f32);
+                objects.push(MapObject {
+                    name: json_str(o, "name").into(),
+                    kind: json_str(o, "type").into(),
+                    pos: flip_object(map_height, x, y, size.0, size.1, field(o, "gid").is_some()),
+                    size: size,
+                    properties: json_properties(field(o, "properties")),
+                });
+            },
+            _ => ()
+        }
+    }
+    Ok(Level {
+        tilemap: tilemap,
+        objects: objects,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use super::{parse_tmx, parse_json};
+
+    const TMX: &'static str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.0" orientation="orthogonal" width="3" height="2" tilewidth="16" tileheight="16">
+ <tileset firstgid="1" name="cave" tilewidth="16" tileheight="16" tilecount="4" columns="2">
+  <image source="../textures/cave.png" width="32" height="32"/>
+  <tile id="1">
+   <properties>
+    <property name="solid" value="true"/>
+   </properties>
+  </tile>
+ </tileset>
+ <layer name="ground" width="3" height="2">
+  <data encoding="csv">
+1,0,0,
+2,2,3
+</data>
+ </layer>
+ <objectgroup name="things">
+  <object id="1" name="start" x="16" y="8" width="8" height="8"/>
+  <object id="2" type="bat" x="32" y="16" width="16" height="16" gid="4"/>
+ </objectgroup>
+</map>"#;
+
+    #[test]
+    fn tmx() {
+        let level = parse_tmx(TMX, Path::new("data/levels")).unwrap();
+        let map = &level.tilemap;
+        assert_eq!(map.size(), (3, 2));
+        assert_eq!(map.tilesets[0].texture, "cave.png");
+        assert_eq!(map.get(0, 0, 0), 1);
+        assert_eq!(map.get(0, 2, 1), 3);
+        // Tile 2 is the solid one, and the bottom row is y 0 to 16 in the world
+        let collision = map.collision("solid");
+        assert!(collision.solid_at(8.0, 8.0));
+        assert!(!collision.solid_at(8.0, 24.0));
+        assert!(!collision.solid_at(40.0, 8.0));
+        assert!(!collision.overlaps(30.0, 20.0, 4.0, 4.0));
+        assert!(collision.overlaps(10.0, 14.0, 4.0, 4.0));
+
+        assert_eq!(level.objects.len(), 2);
+        let start = level.object_named("start").unwrap();
+        assert_eq!((start.pos.x, start.pos.y), (16.0, 16.0));
+        // Tile objects hang off their bottom left
+        assert_eq!(level.objects[1].kind, "bat");
+        assert_eq!((level.objects[1].pos.x, level.objects[1].pos.y), (32.0, 16.0));
+    }
+
+    #[test]
+    fn json() {
+        let json = r#"{
+            "width": 2, "height": 1, "tilewidth": 8, "tileheight": 8,
+            "tilesets": [{
+                "firstgid": 1, "image": "tiles.png", "imagewidth": 16, "imageheight": 8,
+                "tilewidth": 8, "tileheight": 8, "columns": 2, "tilecount": 2,
+                "tileproperties": {"0": {"solid": true}}
+            }],
+            "layers": [
+                {"type": "tilelayer", "name": "ground", "data": [1, 2]},
+                {"type": "objectgroup", "name": "things", "objects": [
+                    {"name": "door", "type": "", "x": 0, "y": 0, "width": 8, "height": 8,
+                     "properties": {"to": "level2"}}
+                ]}
+            ]
+        }"#;
+        let level = parse_json(json, Path::new("data/levels")).unwrap();
+        let map = &level.tilemap;
+        assert_eq!(map.layers()[0].name, "ground");
+        assert_eq!(map.property(1, "solid"), Some("true"));
+        assert_eq!(map.property(2, "solid"), None);
+        // Tile 1 at the left of the bottom row, which is uv 0 to 0.5 across the whole image
+        assert_eq!(map.tilesets[0].uv(1), [0.0, 1.0, 0.5, 0.0]);
+        let door = level.object_named("door").unwrap();
+        assert_eq!((door.pos.x, door.pos.y), (0.0, 0.0));
+        assert_eq!(door.properties["to"], "level2");
+    }
+
+    #[test]
+    fn base64_is_refused() {
+        let tmx = TMX.replace(r#"<data encoding="csv">"#, r#"<data encoding="base64">"#);
+        assert!(parse_tmx(&tmx, Path::new("")).is_err());
+    }
+}
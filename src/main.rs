@@ -13,7 +13,11 @@ extern crate slog_json;
 
 extern crate time;
 extern crate image;
+extern crate toml;
+extern crate rand;
 extern crate fontae;
+extern crate xml;
+extern crate serde_json;
 
 mod graphics;
 mod state;
@@ -21,6 +25,8 @@ mod input;
 mod components;
 mod systems;
 mod font;
+mod gui;
+mod level;
 
 fn main() {
     use glium::{DisplayBuild, Surface};
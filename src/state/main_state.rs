@@ -8,8 +8,9 @@ use glium::{Surface, Display, Texture2d};
 use {components, systems};
 use super::super::graphics::Vertex;
 use systems::{Renderer, RenderSystem, RenderPipeIn};
+use gui::{self, GuiRenderer, GuiPipeIn, Gui, GuiEvent, GuiRect};
 use std::cell::RefCell;
-use super::super::input::KeyReader;
+use super::super::input::{KeyReader, KeyEvent, TouchPad, ActionMap, Players, PlayerEvent, Device};
 use slog::Logger;
 use glium::glutin::Event;
 
@@ -20,6 +21,8 @@ pub struct MainGameState {
     // Using an array of Textures? Why? So that we can freely create textures and store them on the go.
     render_in: RenderPipeIn, // To init stuff on setup
     renderer: Renderer,
+    gui_in: GuiPipeIn, // For whoever wants to draw GUI
+    gui_renderer: GuiRenderer,
 
     // A cache for a buncha stuff
     game_tex: Option<Texture2d>,
@@ -28,16 +31,32 @@ pub struct MainGameState {
     vertexbuffers: Vec<VertexBuffer<Vertex>>,
     indexbuffers: Vec<IndexBuffer<u32>>,
     keyreader: KeyReader,
+    screen_size: (u32, u32),
+    pause_menu: Option<Gui>, // The game stops while this is up
 }
 
 impl MainGameState {
     pub fn new() -> Box<MainGameState> {
         let (render_in, render_out) = systems::create_render_channel();
+        let (gui_in, gui_out) = gui::create_gui_channel();
         let mut planner = {
             let mut w = World::new();
             // Register components
             w.register::<components::Spatial>();
             w.register::<components::VisualType>();
+            w.register::<components::Text>();
+            w.register::<components::Camera>();
+            w.register::<components::ViewMask>();
+            w.register::<components::Depth>();
+            w.register::<components::Tint>();
+            w.register::<components::Tilemap>();
+            w.register::<components::TileCollision>();
+            w.register::<components::Emitter>();
+            w.register::<components::BulletEmitter>();
+            w.register::<components::Bullet>();
+            w.register::<components::Beam>();
+            w.register::<components::Collider>();
+            w.add_resource(components::BeamHits::default());
 
             // Create the Planner to run systems
             Planner::new(w, 4)
@@ -47,15 +66,51 @@ impl MainGameState {
             planner: planner,
             render_in: render_in,
             renderer: Renderer::new(render_out, (0, 0)), // resize at setup
+            gui_in: gui_in,
+            gui_renderer: GuiRenderer::new(gui_out, (0, 0)), // same here
             game_tex: None,
             gui_tex: None,
             programs: vec![],
             indexbuffers: vec![],
             vertexbuffers: vec![],
-            keyreader: KeyReader::new()
+            keyreader: KeyReader::new(),
+            screen_size: (0, 0),
+            pause_menu: None,
         };
         Box::new(state)
     }
+
+    fn close_pause_menu(&mut self) {
+        if self.pause_menu.take().is_some() {
+            // Opening it might not have managed to push the menu context
+            let mut players = self.planner.mut_world().write_resource::<Players>();
+            if players.current_context() == Some("menu") {
+                players.pop_context();
+            }
+        }
+    }
+}
+
+fn pause_menu(screen: (u32, u32), log: &Logger) -> Gui {
+    use gui::{Widget, Theme, Label, Button, Container};
+
+    // Falls back to the built in look if the theme is missing or broken
+    let theme = match Theme::load("default") {
+        Ok(theme) => theme,
+        Err(e) => {
+            error!(log, "Couldn't load the default theme, using the built in one: {:?}", e);
+            Theme::default()
+        }
+    };
+    let root = Container::column(vec![
+        Label::centered("Paused"),
+        Button::new("resume", "Resume"),
+        Button::new("quit", "Quit"),
+    ]);
+    let (_, h) = root.measure(&theme);
+    let w = 240.0;
+    let rect = GuiRect::new((screen.0 as f32 - w) / 2.0, (screen.1 as f32 - h) / 2.0, w, h);
+    Gui::new(root, rect, theme)
 }
 
 impl State for MainGameState {
@@ -67,7 +122,10 @@ impl State for MainGameState {
         info!(log, "Main Game is being initialized! Yay!");
         // Resize renderer to actual dimensions
         let (swidth, sheight) = c.get_framebuffer_dimensions();
+        self.screen_size = (swidth, sheight);
         self.renderer.size_and_center(swidth, sheight);
+        self.gui_renderer.size(swidth, sheight);
+        *self.keyreader.touch_mut() = TouchPad::default_layout(swidth as f32, sheight as f32);
 
         self.game_tex = Some(Texture2d::empty(c, swidth, sheight).unwrap());
         self.gui_tex = Some(Texture2d::empty(c, swidth, sheight).unwrap());
@@ -89,17 +147,11 @@ impl State for MainGameState {
 
         let render_sys = systems::RenderSystem::new(self.render_in.clone());
 
-        use cgmath::{Rotation3, Basis3, Transform};
-
         // Setup entities
-        self.planner.mut_world().create_now().with(
+        let followed = self.planner.mut_world().create_now().with(
             components::Spatial {
-                pos: cgmath::Point2::new(32.0, 32.0),
-                origin: cgmath::Point2::new(16.0, 16.0),
-                transform: cgmath::Decomposed {
-                    rot: Basis3::from_angle_z(cgmath::Deg(90.0)),
-                    ..cgmath::Decomposed::one()
-                }
+                rotation: 90.0,
+                ..components::Spatial::new(cgmath::Point2::new(32.0, 32.0), cgmath::Point2::new(16.0, 16.0))
             }
         ).with(
             // TODO: Hide generating types
@@ -109,17 +161,10 @@ impl State for MainGameState {
                 Vertex { position: [35.0, 35.0], color: [1.0,0.0,0.0,1.0], tex_coords: [1.0, 1.0] },
                 Vertex { position: [0.0, 32.0], color: [1.0,0.0,0.0,1.0], tex_coords: [0.0, 1.0] }
             ], Some(vec![0, 1, 2, 2, 0, 3]), None)
-        );
+        ).build();
 
         self.planner.mut_world().create_now().with(
-            components::Spatial {
-                pos: cgmath::Point2::new(0.0, 0.0),
-                origin: cgmath::Point2::new(16.0, 16.0),
-                transform: cgmath::Decomposed {
-                    rot: Basis3::from_angle_z(cgmath::Deg(0.0)),
-                    ..cgmath::Decomposed::one()
-                }
-            }
+            components::Spatial::new(cgmath::Point2::new(0.0, 0.0), cgmath::Point2::new(16.0, 16.0))
         ).with(
             // TODO: Hide generating types
             components::VisualType::Still(vec![
@@ -130,11 +175,47 @@ impl State for MainGameState {
             ], Some(vec![0, 1, 2, 2, 0, 3]), None)
         );
 
+        // Gameplay asks each player's ActionMap about actions, instead of looking at keys.
+        // Player one starts out on the arrows, and anyone else can join by pressing their Start.
+        let mut players = Players::new(4);
+        players.set_actions(ActionMap::load("default").expect("Failed to load input actions"));
+        for context in ["global", "klay"].iter() {
+            if let Err(e) = players.push_context(context) {
+                error!(log, "{:?}", e);
+            }
+        }
+        players.join(Device::KeyboardRight);
+        self.planner.mut_world().add_resource(players);
+
+        let mut camera = components::Camera::new((swidth as f32, sheight as f32)).following(followed);
+        camera.dead_zone = (32.0, 24.0);
+        self.planner.mut_world().create_now().with(camera).build();
+
+        // Cameras go first, though the render system may still catch them mid-move and draw last frame's view.
+        // It's the one that sends the views, so at worst the whole frame is a frame behind.
+        self.planner.add_system(systems::CameraSystem::new(), "camera", 10);
+        self.planner.add_system(systems::BulletSystem::new(), "bullets", 8);
+        self.planner.add_system(systems::BeamSystem, "beams", 8);
+        self.planner.add_system(systems::ParticleSystem, "particles", 7);
         self.planner.add_system(render_sys, "render", 5);
     }
 
     fn update(&mut self, dura: Duration, log: Logger) -> Update {
-        self.planner.dispatch(dura);
+        let pause = self.planner.mut_world().read_resource::<Players>().any_pressed("pause");
+        if pause && self.pause_menu.is_none() {
+            self.pause_menu = Some(pause_menu(self.screen_size, &log));
+            if let Err(e) = self.planner.mut_world().write_resource::<Players>().push_context("menu") {
+                error!(log, "{:?}", e);
+            }
+        }
+        match self.pause_menu {
+            Some(ref menu) => menu.draw(&self.gui_in),
+            None => self.planner.dispatch(dura),
+        }
+        self.keyreader.touch().draw(&self.gui_in);
+        self.keyreader.next_frame();
+        self.planner.wait(); // Everyone's done looking at this frame's actions
+        self.planner.mut_world().write_resource::<Players>().next_frame();
         Update::Nothing
     }
 
@@ -147,6 +228,14 @@ impl State for MainGameState {
         target.clear_color(0.0, 0.0, 0.0, 1.0);
 
         self.renderer.draw(context, &mut self.game_tex.as_mut().unwrap().as_surface());
+        self.gui_renderer.draw(context, &mut self.gui_tex.as_mut().unwrap().as_surface());
+        let mut errors = self.renderer.take_errors();
+        errors.extend(self.renderer.fonts_mut().take_errors());
+        errors.extend(self.gui_renderer.take_errors());
+        errors.extend(self.gui_renderer.fonts_mut().take_errors());
+        for e in errors {
+            error!(log, "{}", e);
+        }
 
         {
             let ref game_tex = self.game_tex.as_ref().unwrap();
@@ -165,6 +254,46 @@ impl State for MainGameState {
         // debug!(log, "{:?}", ev);
         // debug!(log, "{:?}", self.keyreader.interpret_event(&ev));
 
+        // Touches, and Start once typing's done. The keyboard belongs to the players.
+        let mut key_events = self.keyreader.process(&ev);
+        {
+            let mut players = self.planner.mut_world().write_resource::<Players>();
+            // The touch screen is player one's
+            if let Some(p) = players.player_mut(0) {
+                for &ke in key_events.iter() {
+                    p.process(ke);
+                }
+            }
+            let player_events = if self.keyreader.is_typing() { vec![] } else { players.process(&ev) };
+            for pe in player_events {
+                match pe {
+                    PlayerEvent::Key(_, ke) => key_events.push(ke),
+                    PlayerEvent::Joined(slot, device) => info!(log, "Player {} joined on {:?}", slot + 1, device),
+                    PlayerEvent::Left(slot) => info!(log, "Player {} left", slot + 1),
+                }
+            }
+        }
+        if !key_events.is_empty() {
+            // Widgets move focus around by key, so the menu gets them raw, from anybody
+            let mut gui_events = vec![];
+            if let Some(ref mut menu) = self.pause_menu {
+                for ke in key_events.iter() {
+                    if let KeyEvent::Pressed(key) = *ke {
+                        gui_events.extend(menu.handle_key(&key));
+                    }
+                }
+            }
+            for e in gui_events {
+                match e {
+                    GuiEvent::Activated(ref id) if id == "quit" => return EventUpdate::Update(Update::Pop),
+                    GuiEvent::Activated(ref id) if id == "resume" => self.close_pause_menu(),
+                    GuiEvent::Back => self.close_pause_menu(),
+                    _ => ()
+                }
+            }
+            return EventUpdate::Halt;
+        }
+
         match ev {
             Event::Closed => EventUpdate::Update(Update::Pop),   // the window has been closed by the user
             Event::Focused(u) => EventUpdate::Halt, // TODO change this to push a state
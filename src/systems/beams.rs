@@ -0,0 +1,39 @@
+use specs;
+use time::Duration;
+use cgmath::EuclideanSpace;
+use components::{Beam, BeamHits, Hit, Collider, Spatial, closest_on_segment};
+
+// Grows and shrinks the beams, and works out what they're touching.
+// Everything a beam hits goes in the BeamHits resource, for combat to take the damage off.
+pub struct BeamSystem;
+
+impl specs::System<Duration> for BeamSystem {
+    fn run(&mut self, arg: specs::RunArg, dura: Duration) {
+        use specs::Join;
+
+        let (mut beams, spat, colliders, ents, mut hits) = arg.fetch(|w| {
+            (w.write::<Beam>(), w.read::<Spatial>(), w.read::<Collider>(), w.entities(), w.write_resource::<BeamHits>())
+        });
+        let dt = dura.num_microseconds().unwrap_or(0) as f32 / 1_000_000.0;
+        hits.0.clear();
+
+        for (beam, s, e) in (&mut beams, &spat, &ents).iter() {
+            beam.update(dt);
+            if !beam.is_out() { continue }
+            let (a, b) = beam.segment(s.pos + s.origin.to_vec(), s.rotation);
+            // Goes right through everything, so everything gets hit
+            for (c, cs, target) in (&colliders, &spat, &ents).iter() {
+                if target == e { continue }
+                let origin = cs.pos + cs.origin.to_vec();
+                if c.hit_by_capsule(origin, a, b, beam.width / 2.0) {
+                    hits.0.push(Hit {
+                        beam: e,
+                        target: target,
+                        damage: beam.damage * dt,
+                        at: closest_on_segment(origin + c.offset, a, b),
+                    });
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,159 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use specs;
+use time::Duration;
+use cgmath::{Point2, EuclideanSpace};
+use components::{BulletEmitter, Bullet, Pattern, Shot, Spatial, VisualType, Depth, RenderLayer, Tint};
+use level::Prefab;
+
+// Fires the emitters' patterns and moves the bullets along.
+// Bullets come and go by the thousand, so spent ones are stripped down and kept around to be fired again
+// instead of being deleted.
+pub struct BulletSystem {
+    pool: Vec<specs::Entity>, // Spent bullets, waiting to be reused
+    looks: HashMap<String, Option<Prefab>>, // By bullet name. None if it couldn't be loaded.
+    pub bounds: Option<(Point2<f32>, Point2<f32>)>, // Bottom left and top right. Anything that leaves is gone.
+    pub max: usize, // Bullets out at once. Any past this just don't get fired.
+    live: usize,
+}
+
+impl BulletSystem {
+    pub fn new() -> BulletSystem {
+        BulletSystem {
+            pool: vec![],
+            looks: HashMap::new(),
+            bounds: None,
+            max: 4000,
+            live: 0,
+        }
+    }
+
+    pub fn with_bounds(mut self, bottom_left: Point2<f32>, top_right: Point2<f32>) -> BulletSystem {
+        self.bounds = Some((bottom_left, top_right));
+        self
+    }
+
+    // Bullets out right now
+    pub fn live(&self) -> usize {
+        self.live
+    }
+
+    // Bullets waiting in the pool
+    pub fn pooled(&self) -> usize {
+        self.pool.len()
+    }
+
+    // Only tries loading once, so a broken bullet doesn't hit the disk every shot
+    fn look(&mut self, name: &str) -> Option<&Prefab> {
+        self.looks.entry(name.into()).or_insert_with(|| Prefab::load(name).ok()).as_ref()
+    }
+
+    fn out_of_bounds(&self, p: Point2<f32>) -> bool {
+        match self.bounds {
+            Some((min, max)) => p.x < min.x || p.y < min.y || p.x > max.x || p.y > max.y,
+            None => false
+        }
+    }
+}
+
+// Fired, but not out yet
+struct Pending {
+    pattern: Arc<Pattern>,
+    from: Point2<f32>,
+    shot: Shot,
+    target: Option<specs::Entity>,
+}
+
+impl specs::System<Duration> for BulletSystem {
+    fn run(&mut self, arg: specs::RunArg, dura: Duration) {
+        use specs::Join;
+
+        let (mut emitters, mut bullets, mut spat, mut visuals, mut depths, mut tints, ents) = arg.fetch(|w| {
+            (w.write::<BulletEmitter>(), w.write::<Bullet>(), w.write::<Spatial>(), w.write::<VisualType>(),
+             w.write::<Depth>(), w.write::<Tint>(), w.entities())
+        });
+        let dt = dura.num_microseconds().unwrap_or(0) as f32 / 1_000_000.0;
+        let mut pending = vec![];
+
+        // Where everything is before anything moves, for aiming
+        let centers: HashMap<u32, Point2<f32>> = (&spat, &ents).iter().map(|(s, e)| (e.get_id(), s.pos + s.origin.to_vec())).collect();
+        // Which way the target is from pos, in degrees
+        let aim = |pos: Point2<f32>, target: Option<specs::Entity>| {
+            target.and_then(|t| centers.get(&t.get_id())).map(|t| {
+                let d = *t - pos;
+                d.y.atan2(d.x).to_degrees()
+            })
+        };
+
+        let mut finished = vec![];
+        for (em, e) in (&mut emitters, &ents).iter() {
+            if !em.active { continue }
+            let (from, facing) = spat.get(e).map(|s| (s.pos + s.origin.to_vec(), s.rotation)).unwrap_or((Point2::new(0.0, 0.0), 0.0));
+            for shot in em.firing.update(dt, facing, aim(from, em.target)) {
+                pending.push(Pending { pattern: em.firing.pattern.clone(), from: from, shot: shot, target: em.target });
+            }
+            if em.remove_when_done && em.firing.is_done() {
+                finished.push(e);
+            }
+        }
+        for e in finished {
+            emitters.remove(e);
+        }
+
+        let mut spent = vec![];
+        for (b, s, e) in (&mut bullets, &mut spat, &ents).iter() {
+            let target_aim = aim(b.pos, b.target);
+            for shot in b.update(dt, target_aim) {
+                let child = b.child.as_ref().map(|c| c.pattern.clone()).unwrap();
+                pending.push(Pending { pattern: child, from: b.pos, shot: shot, target: b.target });
+            }
+            s.pos = b.pos - s.origin.to_vec();
+            s.rotation = b.angle;
+            if b.is_done() || self.out_of_bounds(b.pos) {
+                spent.push(e);
+            }
+        }
+        // Stripped down to nothing the other systems care about, and back in the pool
+        for e in spent {
+            bullets.remove(e);
+            spat.remove(e);
+            visuals.remove(e);
+            tints.remove(e);
+            self.pool.push(e);
+        }
+        // Counted rather than kept track of, since bullets can also go by someone else deleting them
+        self.live = (&bullets).iter().count();
+
+        for p in pending {
+            if self.live >= self.max { break }
+            // Bullets that can't be loaded just don't get fired
+            let look = match self.look(&p.pattern.bullet) {
+                Some(look) => look.clone(),
+                None => continue
+            };
+            let e = match self.pool.pop() {
+                Some(e) => e,
+                None => arg.create(),
+            };
+            let pos = p.from + p.shot.offset;
+            let mut bullet = Bullet::new(p.pattern, pos, p.shot.angle);
+            bullet.target = p.target;
+
+            let spatial = look.spatial.unwrap_or(Spatial::new(Point2::new(0.0, 0.0), Point2::new(0.0, 0.0)));
+            spat.insert(e, Spatial {
+                pos: pos - spatial.origin.to_vec(),
+                rotation: p.shot.angle,
+                ..spatial
+            });
+            bullets.insert(e, bullet);
+            if let Some(v) = look.visual {
+                visuals.insert(e, v);
+            }
+            depths.insert(e, look.depth.unwrap_or(Depth::new(RenderLayer::Bullets, 0.0)));
+            if let Some(t) = look.tint {
+                tints.insert(e, t);
+            }
+            self.live += 1;
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use specs;
+use time::Duration;
+use cgmath::Point2;
+use components::{Camera, Spatial};
+
+// Moves the cameras along after their targets, and works out where they're looking (shake and all).
+// Each camera drives its own view, so split screen is just one camera per player.
+// The RenderSystem is the one that tells the renderer, so the view can't change halfway through a frame's draws.
+pub struct CameraSystem {
+    time: f32, // Seconds so far, to drive the shake
+}
+
+impl CameraSystem {
+    pub fn new() -> CameraSystem {
+        CameraSystem {
+            time: 0.0,
+        }
+    }
+}
+
+impl specs::System<Duration> for CameraSystem {
+    fn run(&mut self, arg: specs::RunArg, dura: Duration) {
+        use specs::Join;
+
+        let (mut cams, spat) = arg.fetch(|w| {
+            (w.write::<Camera>(), w.read::<Spatial>())
+        });
+        let dt = dura.num_microseconds().unwrap_or(0) as f32 / 1_000_000.0;
+        self.time += dt;
+
+        for cam in (&mut cams).iter() {
+            let target = cam.target.and_then(|t| spat.get(t)).map(|s| s.pos);
+            if let Some(target) = target {
+                follow(cam, target, dt);
+            }
+            clamp(cam);
+            cam.trauma = (cam.trauma - cam.shake_decay * dt).max(0.0);
+
+            // Squaring makes small shakes subtle and big ones violent
+            let shake = cam.trauma * cam.trauma;
+            let x = cam.position.x + cam.shake_offset * shake * noise(self.time, 0.0) / cam.zoom.x;
+            let y = cam.position.y + cam.shake_offset * shake * noise(self.time, 10.0) / cam.zoom.y;
+            cam.shown_at = Point2::new(x, y);
+            cam.shown_angle = cam.rotation + cam.shake_angle * shake * noise(self.time, 20.0);
+        }
+    }
+}
+
+fn follow(cam: &mut Camera, target: Point2<f32>, dt: f32) {
+    // Only move far enough to get the target back in the dead zone
+    let mut goal = cam.position;
+    let (dx, dy) = cam.dead_zone;
+    if target.x > cam.position.x + dx {
+        goal.x = target.x - dx;
+    } else if target.x < cam.position.x - dx {
+        goal.x = target.x + dx;
+    }
+    if target.y > cam.position.y + dy {
+        goal.y = target.y - dy;
+    } else if target.y < cam.position.y - dy {
+        goal.y = target.y + dy;
+    }
+
+    // Covers the same fraction of the distance every second, whatever the frame rate
+    let t = if cam.follow_speed > 0.0 { 1.0 - (-cam.follow_speed * dt).exp() } else { 1.0 };
+    cam.position.x += (goal.x - cam.position.x) * t;
+    cam.position.y += (goal.y - cam.position.y) * t;
+}
+
+// Keeps the edges of what's shown inside the bounds. A level smaller than the view just gets centered.
+fn clamp(cam: &mut Camera) {
+    let (min, max) = match cam.bounds {
+        Some(b) => b,
+        None => return
+    };
+    let half_w = cam.viewport.0 / cam.zoom.x / 2.0;
+    let half_h = cam.viewport.1 / cam.zoom.y / 2.0;
+    cam.position.x = if max.x - min.x < half_w * 2.0 {
+        (min.x + max.x) / 2.0
+    } else {
+        cam.position.x.max(min.x + half_w).min(max.x - half_w)
+    };
+    cam.position.y = if max.y - min.y < half_h * 2.0 {
+        (min.y + max.y) / 2.0
+    } else {
+        cam.position.y.max(min.y + half_h).min(max.y - half_h)
+    };
+}
+
+// Smooth-ish wobble between -1 and 1. Different seeds wobble differently.
+fn noise(t: f32, seed: f32) -> f32 {
+    ((t * 29.0 + seed).sin() + (t * 47.0 + seed * 1.7).sin() * 0.5) / 1.5
+}
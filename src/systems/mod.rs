@@ -1,3 +1,11 @@
 mod rendering;
+mod camera;
+mod particles;
+mod bullets;
+mod beams;
 
-pub use self::rendering::{RenderSystem, Renderer, RenderInstruction, RenderPipeIn, RenderPipeOut, create_render_channel};
+pub use self::rendering::{RenderSystem, Renderer, RenderInstruction, RenderPipeIn, RenderPipeOut, create_render_channel, load_shaders, View, MAIN_VIEW, BlendMode};
+pub use self::camera::CameraSystem;
+pub use self::particles::ParticleSystem;
+pub use self::bullets::BulletSystem;
+pub use self::beams::BeamSystem;
@@ -0,0 +1,27 @@
+use specs;
+use time::Duration;
+use cgmath::{Point2, EuclideanSpace};
+use components::{Emitter, Spatial};
+
+// Moves every emitter's particles along and spawns new ones. One-shot emitters take their entity with them when they're done.
+pub struct ParticleSystem;
+
+impl specs::System<Duration> for ParticleSystem {
+    fn run(&mut self, arg: specs::RunArg, dura: Duration) {
+        use specs::Join;
+
+        let (mut emitters, spat, ents) = arg.fetch(|w| {
+            (w.write::<Emitter>(), w.read::<Spatial>(), w.entities())
+        });
+        let dt = dura.num_microseconds().unwrap_or(0) as f32 / 1_000_000.0;
+
+        for (em, e) in (&mut emitters, &ents).iter() {
+            // An emitter without a Spatial just sits at the origin
+            let (at, angle) = spat.get(e).map(|s| (s.pos + s.origin.to_vec(), s.rotation)).unwrap_or((Point2::new(0.0, 0.0), 0.0));
+            em.update(dt, at, angle);
+            if em.remove_when_done && em.is_done() {
+                arg.delete(e);
+            }
+        }
+    }
+}
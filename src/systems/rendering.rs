@@ -1,10 +1,12 @@
 use specs;
-use graphics::{Vertex, Index};
+use graphics::{Vertex, Index, Instance, pivot_matrix};
 use time::Duration;
 use std::sync::mpsc::{Sender, Receiver, channel};
-use image::DynamicImage;
-use cgmath::{Matrix4, Decomposed, Ortho, Vector3, Basis3, Point2};
-use components::{Spatial, VisualType};
+use cgmath::{Matrix4, Ortho, Vector2, Point2};
+use components::{Spatial, VisualType, Text, Align, ViewMask, RenderLayer, Depth, Tint, Tilemap, Emitter, Beam, Camera, CHUNK_SIZE};
+use font::{self, FontLibrary, TextCaches, TextKey};
+use fontae::SimpleText;
+use std::collections::HashMap;
 
 pub type RenderPipeIn = Sender<RenderInstruction>;
 pub type RenderPipeOut = Receiver<RenderInstruction>;
@@ -13,78 +15,305 @@ pub fn create_render_channel() -> (RenderPipeIn, RenderPipeOut) {
     channel()
 }
 
-// FIXME Maybe: Genericize?
-fn create_origin_translation(origin: &Point2<f32>, decomp: &Decomposed<Vector3<f32>, Basis3<f32>>) -> Decomposed<Vector3<f32>, Basis3<f32>> {
-    use cgmath::{Rotation, Transform, InnerSpace, Angle};
-    let trans_angl = decomp.rot
-        .rotate_vector(Vector3::new(1.0, 0.0, 0.0))
-        .angle(Vector3::new(1.0, 0.0, 0.0));
-    // println!("Angle {:?}", trans_angl);
-
-    // Derived from SFML
-    let origin_rot_x = origin.x * (1.0 - trans_angl.cos()) + origin.y * trans_angl.sin();
-    let origin_rot_y = origin.y * (1.0 - trans_angl.cos()) - origin.x * trans_angl.sin();
-    // NOTE There is not scaleX or scaleY, only scale
-    let origin_scale_x = origin.x * (1.0 - decomp.scale); // Would use scaleX
-    let origin_scale_y = origin.y * (1.0 - decomp.scale); // Would use scaleY
-    // End derivation
-
-    Decomposed {
-        disp: Vector3::new(
-            origin_rot_x + origin_scale_x,
-            origin_rot_y + origin_scale_y,
-            0.0
-        ),
-        ..Decomposed::one()
-    }
-}
-
 #[allow(dead_code)]
 #[derive(Clone)]
 pub enum RenderInstruction {
     ClearScreen(f32, f32, f32, f32),
-    // Vertices Indices Texture ShaderID Modelmatrix
-    Draw(Vec<Vertex>, Option<Vec<Index>>, Option<DynamicImage>, String, Matrix4<f32>),
-    Zoom(f32), // We don't support separate x and y zooms...yet.
-    Translate(f32, f32),
+    // Vertices Indices Texture(under "data/textures") ShaderID Modelmatrix
+    Draw(Vec<Vertex>, Option<Vec<Index>>, Option<String>, String, Matrix4<f32>),
+    // Entity(ID) Text FontName Size Color Alignment Modelmatrix
+    DrawText(u32, String, String, f32, [f32; 4], Align, Matrix4<f32>),
+    Zoom(f32, f32), // X Y. 2.0 shows things twice as big that way.
+    Translate(f32, f32), // Moves what's looked at by this much
+    LookAt(f32, f32), // Looks right at this world point
+    Rotate(f32), // Degrees, counterclockwise
     SetOrigin(f32, f32),
+    // The view instructions above change this view from now on. They're ignored while there's no view by that name.
+    SelectView(String),
+    // Left Bottom Width Height, in pixels on the target. For the selected view.
+    SetViewport(f32, f32, f32, f32),
+    // Draws from now on go to these views. An empty list means every view.
+    DrawTo(Vec<String>),
+    // Draws from now on are in this layer, and move with its parallax
+    Layer(RenderLayer),
+    // Map(entity ID) Layer Vertices Indices Texture Bounds(left bottom right top, in the map's space)
+    // Kept by the renderer until it's told to forget the map's chunks
+    Chunk(u32, usize, Vec<Vertex>, Vec<Index>, String, [f32; 4]),
+    ForgetChunks(u32),
+    // Map Modelmatrix. Draws whichever of the map's chunks can be seen.
+    DrawChunks(u32, Matrix4<f32>),
+    // Vertices(already in world space) Indices Texture Blending
+    DrawWorld(Vec<Vertex>, Vec<Index>, Option<String>, BlendMode),
+    // The same mesh over and over, once per instance
+    // Vertices Indices ShaderID Instances
+    DrawInstanced(Vec<Vertex>, Option<Vec<Index>>, String, Vec<Instance>),
 }
 
 pub struct RenderSystem {
     pipeline: Sender<RenderInstruction>,
+    // Entity ID to the version of its Tilemap the renderer has the chunks of
+    tilemaps: HashMap<u32, u64>,
 }
 
 impl RenderSystem {
     pub fn new(p: Sender<RenderInstruction>) -> RenderSystem {
         RenderSystem {
             pipeline: p,
+            tilemaps: HashMap::new(),
+        }
+    }
+
+    // Sends the chunks of any map that's new or changed, and forgets the ones that are gone
+    fn upload_tilemaps(&mut self, maps: &[(u32, &Tilemap)]) {
+        let gone: Vec<u32> = self.tilemaps.keys().filter(|id| !maps.iter().any(|m| m.0 == **id)).cloned().collect();
+        for id in gone {
+            self.tilemaps.remove(&id);
+            self.pipeline.send(RenderInstruction::ForgetChunks(id)).unwrap();
+        }
+        for &(id, map) in maps {
+            if self.tilemaps.get(&id) == Some(&map.version()) { continue }
+            // NOTE Rebuilds the whole map for a single tile. Fine for now, since maps barely change.
+            self.pipeline.send(RenderInstruction::ForgetChunks(id)).unwrap();
+            let (cw, ch) = map.chunks();
+            for layer in 0..map.layers().len() {
+                if !map.layers()[layer].visible { continue }
+                for tileset in 0..map.tilesets.len() {
+                    for cy in 0..ch {
+                        for cx in 0..cw {
+                            let (verts, indices, bounds) = chunk_mesh(map, layer, tileset, cx, cy);
+                            if verts.is_empty() { continue }
+                            self.pipeline.send(RenderInstruction::Chunk(id, layer, verts, indices, map.tilesets[tileset].texture.clone(), bounds)).unwrap();
+                        }
+                    }
+                }
+            }
+            self.tilemaps.insert(id, map.version());
+        }
+    }
+}
+
+// The tiles of one layer and tileset in a chunk, as quads in the map's space (bottom left at 0, 0)
+fn chunk_mesh(map: &Tilemap, layer: usize, tileset: usize, cx: u32, cy: u32) -> (Vec<Vertex>, Vec<Index>, [f32; 4]) {
+    let (width, height) = map.size();
+    let (tw, th) = map.tile_size;
+    let set = &map.tilesets[tileset];
+    let (mut verts, mut indices) = (vec![], vec![]);
+    // Chunk rows count from the top, like tiles
+    for y in cy * CHUNK_SIZE..((cy + 1) * CHUNK_SIZE).min(height) {
+        for x in cx * CHUNK_SIZE..((cx + 1) * CHUNK_SIZE).min(width) {
+            let id = map.get(layer, x, y);
+            if id == 0 || !set.contains(id) { continue }
+            let uv = set.uv(id);
+            let (left, bottom) = (x as f32 * tw, (height - 1 - y) as f32 * th);
+            let base = verts.len() as Index;
+            for &(px, py, u, v) in [(left, bottom, uv[0], uv[3]), (left + tw, bottom, uv[2], uv[3]),
+                                    (left + tw, bottom + th, uv[2], uv[1]), (left, bottom + th, uv[0], uv[1])].iter() {
+                verts.push(Vertex { position: [px, py], color: [1.0; 4], tex_coords: [u, v] });
+            }
+            indices.extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
         }
     }
+    let right = ((cx + 1) * CHUNK_SIZE).min(width) as f32 * tw;
+    let bottom = height.saturating_sub((cy + 1) * CHUNK_SIZE) as f32 * th;
+    let top = (height - cy * CHUNK_SIZE) as f32 * th;
+    (verts, indices, [(cx * CHUNK_SIZE) as f32 * tw, bottom, right, top])
 }
 
 impl specs::System<Duration> for RenderSystem {
     fn run(&mut self, arg: specs::RunArg, _: Duration) {
         use specs::Join;
+        use cgmath::EuclideanSpace;
 
-        let (mut spat, vtype, ents) = arg.fetch(|w| {
-            (w.write::<Spatial>(), w.read::<VisualType>(), w.entities())
+        let (spat, vtype, text, masks, depths, tints, tilemaps, emitters, beams, cams, ents) = arg.fetch(|w| {
+            (w.read::<Spatial>(), w.read::<VisualType>(), w.read::<Text>(), w.read::<ViewMask>(), w.read::<Depth>(),
+             w.read::<Tint>(), w.read::<Tilemap>(), w.read::<Emitter>(), w.read::<Beam>(), w.read::<Camera>(), w.entities())
         });
-        self.pipeline.send(RenderInstruction::ClearScreen(0.0, 0.0, 0.0, 1.0)).unwrap();
-        self.pipeline.send(RenderInstruction::Translate(-1.0, -0.5)).unwrap();
-        for (s, v, _) in (&mut spat, &vtype, &ents).iter() {
-            // Here we kind of change it up!
-            use cgmath::{Transform, EuclideanSpace};
-
-            s.transform.disp = s.pos.to_vec().extend(0.0); // Sets out model's displacement to out position. Duh.
-            let origin_trans = create_origin_translation(&s.origin, &s.transform);
-            let model_matrix: Matrix4<f32> = origin_trans.concat(&s.transform).into();
-            match v.clone() {
+        let maps: Vec<(u32, &Tilemap)> = (&tilemaps, &ents).iter().map(|(m, e)| (e.get_id(), m)).collect();
+        self.upload_tilemaps(&maps);
+        let depth_of = |e: specs::Entity| depths.get(e).cloned().unwrap_or(Depth::default());
+        let views_of = |e: specs::Entity| masks.get(e).map(|m| m.0.clone()).unwrap_or(vec![]);
+
+        let mut queue = vec![];
+        // Untextured stills, by mesh and depth. Lots of the same thing (bullets, mostly) get drawn all at once.
+        let mut groups: Vec<MeshGroup> = vec![];
+        for (s, v, e) in (&spat, &vtype, &ents).iter() {
+            match *v {
                 VisualType::Sprite { .. } => (),
+                VisualType::Still(ref verts, ref indx, None) => {
+                    let (depth, views) = (depth_of(e), views_of(e));
+                    let tint = tints.get(e).map(|t| t.0).unwrap_or([1.0; 4]);
+                    let found = groups.iter().position(|g| {
+                        g.depth.layer == depth.layer && g.depth.depth == depth.depth && g.views == views && &g.vertices == verts && &g.indices == indx
+                    });
+                    let i = match found {
+                        Some(i) => i,
+                        None => {
+                            groups.push(MeshGroup {
+                                vertices: verts.clone(),
+                                indices: indx.clone(),
+                                depth: depth,
+                                views: views,
+                                members: vec![],
+                            });
+                            groups.len() - 1
+                        }
+                    };
+                    groups[i].members.push((s.clone(), tint));
+                },
+                // Textured, so batched by texture instead
                 VisualType::Still(ref verts, ref indx, ref tex) => {
-                    self.pipeline.send(RenderInstruction::Draw(verts.clone(), indx.clone(), tex.clone(), "basic".into(), model_matrix)).unwrap();
+                    let material = format!("tiles:{}", tex.as_ref().map(|t| &t[..]).unwrap_or(""));
+                    queue.push(Queued::new(depth_of(e), &material, views_of(e),
+                        RenderInstruction::Draw(verts.clone(), indx.clone(), tex.clone(), "tiles".into(), model_matrix(s))));
                 }
             }
         }
+        for g in groups {
+            if g.members.len() >= INSTANCING_THRESHOLD {
+                let instances = g.members.iter().map(|&(ref s, tint)| instance(s, tint)).collect();
+                queue.push(Queued::new(g.depth, "instanced", g.views,
+                    RenderInstruction::DrawInstanced(g.vertices, g.indices, "instanced".into(), instances)));
+            } else {
+                for (s, tint) in g.members {
+                    let verts = g.vertices.iter().map(|v| Vertex {
+                        color: [v.color[0] * tint[0], v.color[1] * tint[1], v.color[2] * tint[2], v.color[3] * tint[3]],
+                        ..*v
+                    }).collect();
+                    queue.push(Queued::new(g.depth, "basic", g.views.clone(),
+                        RenderInstruction::Draw(verts, g.indices.clone(), None, "basic".into(), model_matrix(&s))));
+                }
+            }
+        }
+        for (s, _, e) in (&spat, &tilemaps, &ents).iter() {
+            queue.push(Queued::new(depth_of(e), "tiles", views_of(e), RenderInstruction::DrawChunks(e.get_id(), model_matrix(s))));
+        }
+        // Particles are already where they are in the world, so they don't need a Spatial
+        for (em, e) in (&emitters, &ents).iter() {
+            if em.particles().is_empty() { continue }
+            let (verts, indices) = em.mesh();
+            let material = format!("particles:{}", em.def.texture.as_ref().map(|t| &t[..]).unwrap_or(""));
+            queue.push(Queued::new(depth_of(e), &material, views_of(e),
+                RenderInstruction::DrawWorld(verts, indices, em.def.texture.clone(), em.def.blend)));
+        }
+        // Beams have their own depth, so they don't end up under whoever's firing them
+        for (b, s, e) in (&beams, &spat, &ents).iter() {
+            if !b.is_out() { continue }
+            let (start, end) = b.segment(s.pos + s.origin.to_vec(), s.rotation);
+            let (verts, indices) = b.mesh(start, end);
+            let material = format!("beam:{}", b.texture.as_ref().map(|t| &t[..]).unwrap_or(""));
+            queue.push(Queued::new(b.depth, &material, views_of(e),
+                RenderInstruction::DrawWorld(verts, indices, b.texture.clone(), BlendMode::Additive)));
+        }
+        for (s, t, e) in (&spat, &text, &ents).iter() {
+            let material = format!("font:{}", t.font);
+            queue.push(Queued::new(depth_of(e), &material, views_of(e),
+                RenderInstruction::DrawText(e.get_id(), t.text.clone(), t.font.clone(), t.size, t.color, t.align, model_matrix(s))));
+        }
+        // Storage order means nothing, so this is what decides what's on top.
+        // Material last, so things that can share a shader end up next to each other.
+        queue.sort_by(|a, b| {
+            use std::cmp::Ordering;
+            match a.depth.layer.cmp(&b.depth.layer) {
+                Ordering::Equal => match a.depth.depth.partial_cmp(&b.depth.depth).unwrap_or(Ordering::Equal) {
+                    Ordering::Equal => a.material.cmp(&b.material),
+                    o => o
+                },
+                o => o
+            }
+        });
+
+        // The view is up to the cameras. Sent from here, ahead of everything else, so the whole frame sees the same views.
+        for cam in (&cams).iter() {
+            self.pipeline.send(RenderInstruction::SelectView(cam.view.clone())).unwrap();
+            self.pipeline.send(RenderInstruction::LookAt(cam.shown_at.x, cam.shown_at.y)).unwrap();
+            self.pipeline.send(RenderInstruction::Zoom(cam.zoom.x, cam.zoom.y)).unwrap();
+            self.pipeline.send(RenderInstruction::Rotate(cam.shown_angle)).unwrap();
+        }
+        self.pipeline.send(RenderInstruction::ClearScreen(0.0, 0.0, 0.0, 1.0)).unwrap();
+        let mut router = ViewRouter::new(&self.pipeline);
+        let mut layer = None;
+        for q in queue {
+            if layer != Some(q.depth.layer) {
+                layer = Some(q.depth.layer);
+                self.pipeline.send(RenderInstruction::Layer(q.depth.layer)).unwrap();
+            }
+            router.route(&q.views);
+            self.pipeline.send(q.inst).unwrap();
+        }
+    }
+}
+
+// A draw waiting for its turn
+struct Queued {
+    depth: Depth,
+    material: String, // Shader, or font for text
+    views: Vec<String>,
+    inst: RenderInstruction,
+}
+
+impl Queued {
+    fn new(depth: Depth, material: &str, views: Vec<String>, inst: RenderInstruction) -> Queued {
+        Queued {
+            depth: depth,
+            material: material.into(),
+            views: views,
+            inst: inst,
+        }
+    }
+}
+
+// Any more of the same mesh than this, and they're drawn instanced instead of batched
+const INSTANCING_THRESHOLD: usize = 32;
+
+// Entities drawn with the same mesh, at the same depth and in the same views.
+// They all go at once, so anything at another depth can't be let in without ending up over or under the wrong things.
+struct MeshGroup {
+    vertices: Vec<Vertex>,
+    indices: Option<Vec<Index>>,
+    depth: Depth,
+    views: Vec<String>,
+    members: Vec<(Spatial, [f32; 4])>, // Spatial Tint
+}
+
+// Only sends DrawTo when the views actually change, which for most things they don't.
+// Always starts a frame at every view, since whatever was last sent sticks around.
+struct ViewRouter<'a> {
+    pipeline: &'a Sender<RenderInstruction>,
+    current: Vec<String>,
+}
+
+impl<'a> ViewRouter<'a> {
+    fn new(p: &'a Sender<RenderInstruction>) -> ViewRouter<'a> {
+        p.send(RenderInstruction::DrawTo(vec![])).unwrap();
+        ViewRouter {
+            pipeline: p,
+            current: vec![],
+        }
+    }
+
+    fn route(&mut self, views: &[String]) {
+        if views != &self.current[..] {
+            self.current = views.to_vec();
+            self.pipeline.send(RenderInstruction::DrawTo(self.current.clone())).unwrap();
+        }
+    }
+}
+
+// Turned and scaled around the origin, with the bottom left corner at pos (before turning)
+fn model_matrix(s: &Spatial) -> Matrix4<f32> {
+    use cgmath::EuclideanSpace;
+
+    pivot_matrix(s.pos + s.origin.to_vec(), s.origin, s.rotation, s.scale)
+}
+
+// The same as model_matrix, for the instanced shader to do itself
+fn instance(s: &Spatial, tint: [f32; 4]) -> Instance {
+    Instance {
+        i_position: [s.pos.x + s.origin.x, s.pos.y + s.origin.y],
+        i_pivot: [s.origin.x, s.origin.y],
+        i_rotation: s.rotation,
+        i_scale: [s.scale.x, s.scale.y],
+        i_tint: tint,
     }
 }
 
@@ -100,7 +329,7 @@ use std::io::Error as IoError;
 // the "data" folder. The name given (ex. "basic") is the name of the pair
 // of files making up the shader program, with fn.vert and fn.frag being loaded.
 // (ex. "basic.vert" and "basic.frag")
-fn load_shaders<P: AsRef<str>>(s: P) -> Result<(String, String), IoError> {
+pub fn load_shaders<P: AsRef<str>>(s: P) -> Result<(String, String), IoError> {
     use std::path::Path;
     let base = Path::new("shaders").join(s.as_ref());
     Ok((match File::open(&base.with_extension("vert")) {
@@ -125,50 +354,175 @@ fn load_shaders<P: AsRef<str>>(s: P) -> Result<(String, String), IoError> {
     }))
 }
 
-// This keeps an origin and a Decomposed together, mainly for Renderer purposes, simulating SFML
+// Where the world is seen from, and how it lands on the screen. Cameras (systems::CameraSystem) drive this.
 #[derive(Clone, Debug)]
 pub struct View {
-    pub origin: Point2<f32>,
+    pub origin: Point2<f32>, // Where center ends up on the viewport. The middle, usually.
+    pub viewport_pos: (f32, f32), // Bottom left corner on the target, in pixels
     pub viewport_size: (f32, f32),
-    pub transform: Decomposed<Vector3<f32>, Basis3<f32>>,
+    pub center: Point2<f32>, // The world point being looked at
+    pub zoom: Vector2<f32>, // X Y. 2.0 shows everything twice as big that way.
+    pub rotation: f32, // Degrees, counterclockwise. The world turns the other way.
+    pub projection: Ortho<f32>,
 }
 
 impl View {
+    // A 1 to 1 mapping of a w by h viewport, with (0, 0) at the bottom left
+    pub fn new(w: f32, h: f32) -> View {
+        View {
+            origin: Point2::new(w / 2.0, h / 2.0),
+            viewport_pos: (0.0, 0.0),
+            viewport_size: (w, h),
+            center: Point2::new(w / 2.0, h / 2.0),
+            zoom: Vector2::new(1.0, 1.0),
+            rotation: 0.0,
+            projection: Ortho {
+                left: 0.0,
+                right: w,
+                bottom: 0.0,
+                top: h,
+                near: 0.0,
+                far: 5.0
+            },
+        }
+    }
+
+    // Puts the view on part of the target, keeping what's looked at in the middle
+    pub fn set_viewport(&mut self, x: f32, y: f32, w: f32, h: f32) {
+        let center = self.center;
+        *self = View {
+            viewport_pos: (x, y),
+            center: center,
+            zoom: self.zoom,
+            rotation: self.rotation,
+            ..View::new(w, h)
+        };
+    }
+
+    pub fn resize(&mut self, w: f32, h: f32) {
+        let (x, y) = self.viewport_pos;
+        self.set_viewport(x, y, w, h);
+    }
+
+    // Where it goes on the target, for DrawParameters
+    pub fn viewport_rect(&self) -> Rect {
+        Rect {
+            left: self.viewport_pos.0 as u32,
+            bottom: self.viewport_pos.1 as u32,
+            width: self.viewport_size.0 as u32,
+            height: self.viewport_size.1 as u32,
+        }
+    }
+
+    // World to viewport pixels. The world turns and zooms around center, which lands on origin.
+    pub fn matrix(&self) -> Matrix4<f32> {
+        pivot_matrix(self.origin, self.center, -self.rotation, self.zoom)
+    }
+
+    // For layers that scroll slower (or faster) than the world. (1, 1) is matrix(), (0, 0) doesn't scroll at all.
+    // Only what's looked at gets scaled, so zoom and rotation still apply as normal.
+    pub fn parallax_matrix(&self, factor: Vector2<f32>) -> Matrix4<f32> {
+        let center = Point2::new(self.center.x * factor.x, self.center.y * factor.y);
+        pivot_matrix(self.origin, center, -self.rotation, self.zoom)
+    }
+
+    pub fn projection_matrix(&self) -> Matrix4<f32> {
+        self.projection.clone().into()
+    }
+
+    // How many world units fit across and up the viewport, ignoring rotation
+    pub fn world_size(&self) -> (f32, f32) {
+        (self.viewport_size.0 / self.zoom.x, self.viewport_size.1 / self.zoom.y)
+    }
+
+    // Turns pixels (from the top left of the viewport, y going down, like mouse events) into world coordinates.
+    // The projection has y going up from the bottom of the viewport, so y gets flipped before undoing the view.
+    // Renderer::screen_to_world does this from window pixels, for whichever view is there.
+    // None if the view can't be undone (zoomed to 0), since every pixel is then the same world point.
+    pub fn screen_to_world(&self, x: f32, y: f32) -> Option<Point2<f32>> {
+        use cgmath::{SquareMatrix, Vector4};
+
+        self.matrix().invert().map(|inverse| {
+            let p = inverse * Vector4::new(x, self.viewport_size.1 - y, 0.0, 1.0);
+            Point2::new(p.x, p.y)
+        })
+    }
 }
 
 // This struct runs on the other side, and trys to organize and realize the commands of the rendering system.
-// NOTE FUTURE: May implement GUI system using this
+// NOTE The GUI has its own renderer (gui::GuiRenderer), drawing into its own texture.
 pub struct Renderer {
     receiver: Receiver<RenderInstruction>,
-    // TODO Move projection to view to complete it.
-    projection: Ortho<f32>,
-    view: View,
+    // Drawn in this order, so later ones (minimaps, picture-in-picture) go on top
+    views: Vec<(String, View)>,
+    selected: Option<usize>, // Which view the view instructions change. None if it's gone.
+    draw_to: Vec<usize>, // Which views draws go to. Empty means all of them.
+    screen: (u32, u32),
     default_view: View, // A 1 to 1 mapping of the screen
+    layer: RenderLayer, // Of the draws coming in
+    parallax: HashMap<RenderLayer, Vector2<f32>>,
+    fonts: FontLibrary,
+    texts: TextCaches,
+    programs: HashMap<String, Program>, // By shader name
+    batch: Batch,
+    vertex_buffer: Option<VertexBuffer<Vertex>>,
+    index_buffer: Option<IndexBuffer<Index>>,
+    instance_buffer: Option<VertexBuffer<Instance>>,
+    draw_calls: usize,
+    // Loaded from "data/textures" as they're asked for
+    textures: HashMap<String, Texture2d>,
+    chunks: HashMap<u32, Vec<Chunk>>, // By map, in layer order
+    errors: Vec<String>, // Waiting for someone with a logger
 }
 
-use glium::Surface;
+// A piece of a tilemap, kept on the GPU
+struct Chunk {
+    layer: usize,
+    texture: String,
+    bounds: [f32; 4], // Left Bottom Right Top
+    vertices: VertexBuffer<Vertex>,
+    indices: IndexBuffer<Index>,
+}
+
+use glium::{Surface, Rect, Program, VertexBuffer, IndexBuffer, Texture2d};
 use glium::backend::Facade;
-use cgmath::Transform;
+// The background drifts by at half speed, everything else keeps up with the world
+fn default_parallax() -> HashMap<RenderLayer, Vector2<f32>> {
+    let mut p = HashMap::new();
+    for &layer in RenderLayer::all().iter() {
+        p.insert(layer, Vector2::new(1.0, 1.0));
+    }
+    p.insert(RenderLayer::Background, Vector2::new(0.5, 0.5));
+    p
+}
+
+// The view everyone gets unless they ask for something else
+pub const MAIN_VIEW: &'static str = "main";
+
 impl Renderer {
     pub fn new(r: Receiver<RenderInstruction>, wsize: (u32, u32)) -> Renderer {
-        let default_view = View {
-            origin: Point2::new(wsize.0 as f32 / 2.0, wsize.1 as f32 / 2.0),
-            viewport_size: (wsize.0 as f32, wsize.1 as f32),
-            transform: Decomposed::one()
-        };
+        let default_view = View::new(wsize.0 as f32, wsize.1 as f32);
 
         Renderer {
             receiver: r,
-            projection: Ortho {
-                left: 0.0,
-                right: wsize.0 as f32,
-                bottom: 0.0,
-                top: wsize.1 as f32,
-                near: 0.0,
-                far: 5.0
-            },
-            view: default_view.clone(),
-            default_view: default_view
+            views: vec![(MAIN_VIEW.into(), default_view.clone())],
+            selected: Some(0),
+            draw_to: vec![],
+            screen: wsize,
+            default_view: default_view,
+            layer: RenderLayer::World,
+            parallax: default_parallax(),
+            fonts: FontLibrary::new(),
+            texts: TextCaches::new(),
+            programs: HashMap::new(),
+            batch: Batch::default(),
+            vertex_buffer: None,
+            index_buffer: None,
+            instance_buffer: None,
+            draw_calls: 0,
+            textures: HashMap::new(),
+            chunks: HashMap::new(),
+            errors: vec![],
         }
     }
 
@@ -177,63 +531,605 @@ impl Renderer {
         self.center(x as f32, y as f32);
     }
 
+    // Resets the main view to cover the whole screen
     pub fn center(&mut self, x: f32, y: f32) {
-        self.view = View {
-            origin: Point2::new(x / 2.0, y / 2.0),
-            viewport_size: (x, y),
-            transform: Decomposed::one()
-        };
+        self.add_view(MAIN_VIEW, View::new(x, y));
+    }
+
+    // The main view
+    pub fn view(&self) -> &View {
+        self.view_named(MAIN_VIEW).unwrap_or(&self.default_view)
+    }
+
+    pub fn view_named(&self, name: &str) -> Option<&View> {
+        self.views.iter().find(|v| v.0 == name).map(|v| &v.1)
     }
 
-    // Sets screen size
+    pub fn view_named_mut(&mut self, name: &str) -> Option<&mut View> {
+        self.views.iter_mut().find(|v| v.0 == name).map(|v| &mut v.1)
+    }
+
+    // Replaces any view with the same name. New views go on top.
+    pub fn add_view<S: Into<String>>(&mut self, name: S, view: View) {
+        let name = name.into();
+        match self.views.iter().position(|v| v.0 == name) {
+            Some(i) => self.views[i].1 = view,
+            None => self.views.push((name, view))
+        }
+    }
+
+    // Whatever was selected stays selected, unless it's what went
+    pub fn remove_view(&mut self, name: &str) {
+        let selected = self.selected.map(|i| self.views[i].0.clone());
+        self.views.retain(|v| v.0 != name);
+        self.selected = selected.and_then(|s| self.find(&s));
+        self.draw_to.clear();
+    }
+
+    // Lays the named views out side by side across the screen, making any that don't exist yet.
+    // Two views split it down the middle, three or four make a grid.
+    pub fn split_screen(&mut self, names: &[&str]) {
+        let (w, h) = (self.screen.0 as f32, self.screen.1 as f32);
+        let cols = if names.len() > 2 { 2 } else { names.len().max(1) };
+        let rows = (names.len() + cols - 1) / cols;
+        let (vw, vh) = (w / cols as f32, h / rows.max(1) as f32);
+        for (i, name) in names.iter().enumerate() {
+            let (col, row) = (i % cols, i / cols);
+            // The first row goes at the top
+            let (x, y) = (vw * col as f32, h - vh * (row + 1) as f32);
+            let mut view = self.view_named(name).cloned().unwrap_or(View::new(vw, vh));
+            view.set_viewport(x, y, vw, vh);
+            self.add_view(*name, view);
+        }
+    }
+
+    // Turns window pixels (from the top left, y going down) into world coordinates, in the
+    // topmost view there. Gives back which view that was too. None if there's no view there, or it's zoomed to 0.
+    pub fn screen_to_world(&self, x: f32, y: f32) -> Option<(&str, Point2<f32>)> {
+        let from_bottom = self.screen.1 as f32 - y;
+        for &(ref name, ref view) in self.views.iter().rev() {
+            let (vx, vy) = view.viewport_pos;
+            let (vw, vh) = view.viewport_size;
+            if x >= vx && x < vx + vw && from_bottom >= vy && from_bottom < vy + vh {
+                return view.screen_to_world(x - vx, vy + vh - from_bottom).map(|p| (&name[..], p));
+            }
+        }
+        None
+    }
+
+    // How fast a layer scrolls compared to the world. See View::parallax_matrix.
+    pub fn set_parallax(&mut self, layer: RenderLayer, x: f32, y: f32) {
+        self.parallax.insert(layer, Vector2::new(x, y));
+    }
+
+    // Of the layer being drawn
+    fn current_parallax(&self) -> Vector2<f32> {
+        self.parallax.get(&self.layer).cloned().unwrap_or(Vector2::new(1.0, 1.0))
+    }
+
+    // To set up fonts that need more than a name (fallbacks, SDF...)
+    pub fn fonts_mut(&mut self) -> &mut FontLibrary {
+        &mut self.fonts
+    }
+
+    // Everything that went wrong loading since the last time this was called. Fonts keep their own.
+    pub fn take_errors(&mut self) -> Vec<String> {
+        ::std::mem::replace(&mut self.errors, vec![])
+    }
+
+    // Sets screen size. Views keep the same share of the screen they had.
     pub fn size(&mut self, w: u32, h: u32) {
-        self.projection =  Ortho {
-            left: 0.0,
-            right: w as f32,
-            bottom: 0.0,
-            top: h as f32,
-            near: 0.0,
-            far: 5.0
-        };
+        let old = self.screen;
+        self.screen = (w, h);
+        self.default_view = View::new(w as f32, h as f32);
+        if old.0 == 0 || old.1 == 0 {
+            // Nothing sensible to scale from
+            for v in self.views.iter_mut() {
+                v.1.set_viewport(0.0, 0.0, w as f32, h as f32);
+            }
+            return;
+        }
+        let (sx, sy) = (w as f32 / old.0 as f32, h as f32 / old.1 as f32);
+        for v in self.views.iter_mut() {
+            let view = &mut v.1;
+            let (x, y) = view.viewport_pos;
+            let (vw, vh) = view.viewport_size;
+            view.set_viewport(x * sx, y * sy, vw * sx, vh * sy);
+        }
+    }
+
+    fn find(&self, name: &str) -> Option<usize> {
+        self.views.iter().position(|v| v.0 == name)
+    }
+
+    fn selected_view(&mut self) -> Option<&mut View> {
+        match self.selected {
+            Some(i) => self.views.get_mut(i).map(|v| &mut v.1),
+            None => None
+        }
+    }
+
+    // The views a draw goes to right now
+    fn targets(&self) -> Vec<usize> {
+        if self.draw_to.is_empty() {
+            (0..self.views.len()).collect()
+        } else {
+            self.draw_to.clone()
+        }
+    }
+
+    // How many times the last frame hit the GPU (text not counted)
+    pub fn draw_calls(&self) -> usize {
+        self.draw_calls
     }
 
     pub fn draw<F: Facade, S: Surface>(&mut self, f: &F, surface: &mut S) {
+        self.draw_calls = 0;
         // Check if there are any instructions
         while let Ok(inst) = self.receiver.try_recv() {
+            // Anything but another draw for the same batch changes what the batch would look like, so it goes out first
+            let key = batch_key(&inst);
+            if key.is_none() || key != self.batch.key {
+                self.flush(f, surface);
+            }
+
             match inst {
                 RenderInstruction::ClearScreen(r, g, b, a) => surface.clear_color(r, g, b, a),
-                RenderInstruction::Zoom(by) => self.view.transform.scale = by,
-                RenderInstruction::Translate(x, y) => self.view.transform.disp -= Vector3::new(x, y, 0.0),
-                RenderInstruction::SetOrigin(x, y) => self.view.origin = Point2::new(x, y),
-                RenderInstruction::Draw(vb, ib, tex, shd, model_m) => {
-                    use glium::{IndexBuffer, index, VertexBuffer, Program};
-                    use cgmath::Transform;
-                    use cgmath::conv::*;
-
-                    let view_origin_adjust = create_origin_translation(&self.view.origin, &self.view.transform);
-                    let view_m: Matrix4<f32> = view_origin_adjust.concat(&self.view.transform).clone().into();
-                    let proj_m: Matrix4<f32> = self.projection.clone().into();
-                    let uniforms = match tex {
-                        Some(_) => unimplemented!(),
-                        None => uniform!{
-                            mvp: array4x4(proj_m * view_m * model_m)
-                        }
-                    };
-                    let index_buffer = match ib {
-                        Some(indx) => Some(IndexBuffer::new(f, index::PrimitiveType::TrianglesList, &indx).unwrap()),
-                        None => None
+                // With no view selected these go nowhere, like draws to views that don't exist
+                RenderInstruction::Zoom(x, y) => if let Some(view) = self.selected_view() {
+                    view.zoom = Vector2::new(x, y);
+                },
+                RenderInstruction::Translate(x, y) => if let Some(view) = self.selected_view() {
+                    view.center = view.center + Vector2::new(x, y);
+                },
+                RenderInstruction::LookAt(x, y) => if let Some(view) = self.selected_view() {
+                    view.center = Point2::new(x, y);
+                },
+                RenderInstruction::Rotate(deg) => if let Some(view) = self.selected_view() {
+                    view.rotation = deg;
+                },
+                RenderInstruction::SetOrigin(x, y) => if let Some(view) = self.selected_view() {
+                    view.origin = Point2::new(x, y);
+                },
+                RenderInstruction::SelectView(name) => self.selected = self.find(&name),
+                RenderInstruction::SetViewport(x, y, w, h) => if let Some(view) = self.selected_view() {
+                    view.set_viewport(x, y, w, h);
+                },
+                // Views that don't exist (a player who's left, say) are just skipped
+                RenderInstruction::DrawTo(names) => self.draw_to = names.iter().filter_map(|n| self.find(n)).collect(),
+                RenderInstruction::Layer(layer) => self.layer = layer,
+                RenderInstruction::Draw(vb, ib, texture, _, model_m) => {
+                    if let Some(ref texture) = texture {
+                        self.cache_texture(f, texture);
+                    }
+                    self.batch.key = key;
+                    self.batch.add(&vb, ib.as_ref().map(|i| &i[..]), model_m);
+                },
+                RenderInstruction::DrawWorld(vb, ib, texture, _) => {
+                    if let Some(ref texture) = texture {
+                        self.cache_texture(f, texture);
+                    }
+                    self.batch.key = key;
+                    self.batch.add(&vb, Some(&ib), Matrix4::from_scale(1.0));
+                },
+                RenderInstruction::Chunk(map, layer, vb, ib, texture, bounds) => {
+                    use glium::index;
+
+                    self.cache_texture(f, &texture);
+                    let chunks = self.chunks.entry(map).or_insert(vec![]);
+                    let at = chunks.iter().position(|c| c.layer > layer).unwrap_or(chunks.len());
+                    chunks.insert(at, Chunk {
+                        layer: layer,
+                        texture: texture,
+                        bounds: bounds,
+                        vertices: VertexBuffer::new(f, &vb).unwrap(),
+                        indices: IndexBuffer::new(f, index::PrimitiveType::TrianglesList, &ib).unwrap(),
+                    });
+                },
+                RenderInstruction::ForgetChunks(map) => {
+                    self.chunks.remove(&map);
+                },
+                RenderInstruction::DrawChunks(map, model_m) => self.draw_chunks(f, surface, map, model_m),
+                RenderInstruction::DrawInstanced(vb, ib, shd, instances) => self.draw_instanced(f, surface, &vb, ib, &shd, &instances),
+                RenderInstruction::DrawText(id, text, font_name, size, color, align, model_m) => {
+                    let parallax = self.current_parallax();
+                    let matrices: Vec<_> = self.targets().into_iter().map(|i| {
+                        let view = &self.views[i].1;
+                        (view.projection_matrix() * view.parallax_matrix(parallax) * model_m, Some(view.viewport_rect()))
+                    }).collect();
+                    font::draw_text(f, surface, &mut self.fonts, &mut self.texts, &font_name, TextKey::Entity(id),
+                                    SimpleText::new(text, color), size, align, &matrices);
+                }
+            }
+        }
+        self.flush(f, surface);
+        self.texts.end_frame();
+    }
+
+    fn draw_chunks<F: Facade, S: Surface>(&mut self, f: &F, surface: &mut S, map: u32, model_m: Matrix4<f32>) {
+        use glium::{DrawParameters, Blend, uniforms};
+        use cgmath::conv::*;
+
+        let targets = self.targets();
+        let parallax = self.current_parallax();
+        if !self.programs.contains_key("tiles") {
+            let (vert_shd_src, frag_shd_src) = load_shaders("tiles").unwrap();
+            self.programs.insert("tiles".into(), Program::from_source(f, &vert_shd_src, &frag_shd_src, None).unwrap());
+        }
+        let program = &self.programs["tiles"];
+        let chunks = match self.chunks.get(&map) {
+            Some(c) => c,
+            None => return
+        };
+        for i in targets {
+            let view = &self.views[i].1;
+            let view_m = view.parallax_matrix(parallax);
+            let seen = visible_area(view, view_m);
+            let mvp = view.projection_matrix() * view_m * model_m;
+            let params = DrawParameters {
+                viewport: Some(view.viewport_rect()),
+                blend: Blend::alpha_blending(),
+                ..Default::default()
+            };
+            for chunk in chunks.iter() {
+                // Skip what's off screen. A view that can't be undone gets everything.
+                if let Some(seen) = seen {
+                    if !overlaps(seen, transform_bounds(model_m, chunk.bounds)) { continue }
+                }
+                let uniforms = uniform!{
+                    mvp: array4x4(mvp),
+                    tex: self.textures[&chunk.texture].sampled()
+                        .magnify_filter(uniforms::MagnifySamplerFilter::Nearest)
+                        .minify_filter(uniforms::MinifySamplerFilter::Nearest)
+                };
+                surface.draw(&chunk.vertices, &chunk.indices, program, &uniforms, &params).unwrap();
+                self.draw_calls += 1;
+            }
+        }
+    }
+
+    // The same mesh once per instance, in one go per view
+    fn draw_instanced<F: Facade, S: Surface>(&mut self, f: &F, surface: &mut S, vertices: &[Vertex], indices: Option<Vec<Index>>,
+                                             shader: &str, instances: &[Instance]) {
+        use glium::{index, DrawParameters};
+        use cgmath::conv::*;
+
+        if instances.is_empty() { return }
+        let targets = self.targets();
+        let parallax = self.current_parallax();
+        let n = instances.len();
+        if self.instance_buffer.as_ref().map(|b| b.len() < n).unwrap_or(true) {
+            self.instance_buffer = Some(VertexBuffer::empty_dynamic(f, n.next_power_of_two()).unwrap());
+        }
+        let instance_buffer = self.instance_buffer.as_ref().unwrap();
+        instance_buffer.slice(0..n).unwrap().write(instances);
+
+        // The mesh is small, so it's fine to make it every time
+        let mesh = VertexBuffer::new(f, vertices).unwrap();
+        let index_buffer = indices.map(|i| IndexBuffer::new(f, index::PrimitiveType::TrianglesList, &i).unwrap());
+        let program = self.programs.entry(shader.into()).or_insert_with(|| {
+            let (vert_shd_src, frag_shd_src) = load_shaders(shader).unwrap();
+            Program::from_source(f, &vert_shd_src, &frag_shd_src, None).unwrap()
+        });
+        for i in targets {
+            let view = &self.views[i].1;
+            let uniforms = uniform!{
+                vp: array4x4(view.projection_matrix() * view.parallax_matrix(parallax))
+            };
+            let params = DrawParameters {
+                viewport: Some(view.viewport_rect()),
+                ..Default::default()
+            };
+            let per_instance = instance_buffer.slice(0..n).unwrap().per_instance().expect("Instancing isn't supported here");
+            match index_buffer {
+                Some(ref ib) => surface.draw((&mesh, per_instance), ib, program, &uniforms, &params).unwrap(),
+                None => surface.draw((&mesh, per_instance), index::NoIndices(index::PrimitiveType::TrianglesList), program, &uniforms, &params).unwrap(),
+            }
+            self.draw_calls += 1;
+        }
+    }
+
+    // Loads a texture the first time it's used. One that can't be loaded is plain white from then on.
+    fn cache_texture<F: Facade>(&mut self, f: &F, name: &str) {
+        if !self.textures.contains_key(name) {
+            let tex = match load_texture(f, name) {
+                Ok(tex) => tex,
+                Err(e) => {
+                    self.errors.push(e);
+                    white_texture(f)
+                }
+            };
+            self.textures.insert(name.to_string(), tex);
+        }
+    }
+
+    // Draws whatever's been batched up, once per view
+    fn flush<F: Facade, S: Surface>(&mut self, f: &F, surface: &mut S) {
+        use glium::{index, uniforms, DrawParameters};
+        use cgmath::conv::*;
+
+        let key = match self.batch.key {
+            Some(ref k) if !self.batch.vertices.is_empty() => k.clone(),
+            _ => return
+        };
+        let shader = key.shader.clone();
+        let targets = self.targets();
+        let parallax = self.current_parallax();
+        let (nv, ni) = (self.batch.vertices.len(), self.batch.indices.len());
+
+        // The buffers stick around and only ever grow, so a steady stream of bullets doesn't allocate
+        if self.vertex_buffer.as_ref().map(|b| b.len() < nv).unwrap_or(true) {
+            self.vertex_buffer = Some(VertexBuffer::empty_dynamic(f, nv.next_power_of_two()).unwrap());
+        }
+        if self.index_buffer.as_ref().map(|b| b.len() < ni).unwrap_or(true) {
+            self.index_buffer = Some(IndexBuffer::empty_dynamic(f, index::PrimitiveType::TrianglesList, ni.next_power_of_two()).unwrap());
+        }
+        let (vertex_buffer, index_buffer) = (self.vertex_buffer.as_ref().unwrap(), self.index_buffer.as_ref().unwrap());
+        vertex_buffer.slice(0..nv).unwrap().write(&self.batch.vertices);
+        index_buffer.slice(0..ni).unwrap().write(&self.batch.indices);
+
+        let program = self.programs.entry(shader.clone()).or_insert_with(|| {
+            let (vert_shd_src, frag_shd_src) = load_shaders(&shader).unwrap();
+            Program::from_source(f, &vert_shd_src, &frag_shd_src, None).unwrap()
+        });
+        for i in targets {
+            let view = &self.views[i].1;
+            // The vertices are already in world space
+            let mvp = array4x4(view.projection_matrix() * view.parallax_matrix(parallax));
+            let params = DrawParameters {
+                viewport: Some(view.viewport_rect()),
+                blend: key.blend.blend(),
+                ..Default::default()
+            };
+            let (vertices, indices) = (vertex_buffer.slice(0..nv).unwrap(), index_buffer.slice(0..ni).unwrap());
+            match key.texture {
+                Some(ref name) => {
+                    let uniforms = uniform!{
+                        mvp: mvp,
+                        tex: self.textures[name].sampled()
+                            .magnify_filter(uniforms::MagnifySamplerFilter::Nearest)
                     };
-                    let indsource: index::IndicesSource = match index_buffer {
-                        Some(ref ib) =>ib.into(),
-                        None => index::NoIndices(index::PrimitiveType::TrianglesList).into()
+                    surface.draw(vertices, indices, program, &uniforms, &params).unwrap();
+                },
+                None => {
+                    let uniforms = uniform!{
+                        mvp: mvp
                     };
-                    let vertsource = VertexBuffer::new(f, &vb).unwrap();
-                    let (vert_shd_src, frag_shd_src) = load_shaders(shd).unwrap();
-                    let program = Program::from_source(f, &vert_shd_src, &frag_shd_src, None).unwrap();
-
-                    surface.draw(&vertsource, indsource, &program, &uniforms, &Default::default()).unwrap();
+                    surface.draw(vertices, indices, program, &uniforms, &params).unwrap();
                 }
             }
+            self.draw_calls += 1;
         }
+        self.batch.clear();
+    }
+}
+
+// World textures are uploaded bottom row first, so v goes up like everything else in the world
+fn load_texture<F: Facade>(f: &F, name: &str) -> Result<Texture2d, String> {
+    use image;
+    use std::path::Path;
+    use glium::texture::RawImage2d;
+
+    let path = Path::new("data").join("textures").join(name);
+    let img = match image::open(&path) {
+        Ok(img) => img.to_rgba(),
+        Err(e) => return Err(format!("Failed to load texture {}: {}", path.display(), e))
+    };
+    let dims = img.dimensions();
+    Ok(Texture2d::new(f, RawImage2d::from_raw_rgba_reversed(img.into_raw(), dims)).unwrap())
+}
+
+// Stands in for textures that couldn't be loaded, so whatever uses them still shows up
+fn white_texture<F: Facade>(f: &F) -> Texture2d {
+    use glium::texture::RawImage2d;
+
+    Texture2d::new(f, RawImage2d::from_raw_rgba(vec![255u8; 4], (1, 1))).unwrap()
+}
+
+// Left Bottom Right Top of the box around bounds, once it's gone through m
+fn transform_bounds(m: Matrix4<f32>, b: [f32; 4]) -> [f32; 4] {
+    use cgmath::Vector4;
+
+    let mut out = [::std::f32::INFINITY, ::std::f32::INFINITY, ::std::f32::NEG_INFINITY, ::std::f32::NEG_INFINITY];
+    for &(x, y) in [(b[0], b[1]), (b[2], b[1]), (b[2], b[3]), (b[0], b[3])].iter() {
+        let p = m * Vector4::new(x, y, 0.0, 1.0);
+        out = [out[0].min(p.x), out[1].min(p.y), out[2].max(p.x), out[3].max(p.y)];
+    }
+    out
+}
+
+// The world a view can see (with view_m standing in for its matrix), boxed up.
+// None if the view can't be undone (zoomed to 0), since then there's no telling.
+fn visible_area(view: &View, view_m: Matrix4<f32>) -> Option<[f32; 4]> {
+    use cgmath::SquareMatrix;
+
+    view_m.invert().map(|inverse| transform_bounds(inverse, [0.0, 0.0, view.viewport_size.0, view.viewport_size.1]))
+}
+
+fn overlaps(a: [f32; 4], b: [f32; 4]) -> bool {
+    a[0] < b[2] && b[0] < a[2] && a[1] < b[3] && b[1] < a[3]
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+// How a draw mixes with what's already there
+pub enum BlendMode {
+    Opaque,
+    Alpha,
+    Additive, // Light on light. Fire, sparks, lasers...
+}
+
+impl BlendMode {
+    fn blend(&self) -> ::glium::Blend {
+        use glium::{Blend, BlendingFunction, LinearBlendingFactor};
+
+        match *self {
+            BlendMode::Opaque => Default::default(),
+            BlendMode::Alpha => Blend::alpha_blending(),
+            BlendMode::Additive => Blend {
+                color: BlendingFunction::Addition {
+                    source: LinearBlendingFactor::SourceAlpha,
+                    destination: LinearBlendingFactor::One,
+                },
+                alpha: BlendingFunction::Addition {
+                    source: LinearBlendingFactor::One,
+                    destination: LinearBlendingFactor::One,
+                },
+                constant_value: (0.0, 0.0, 0.0, 0.0),
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+// What draws have to agree on to be batched together
+struct BatchKey {
+    shader: String,
+    texture: Option<String>,
+    blend: BlendMode,
+}
+
+// The key for draws that can be batched at all
+fn batch_key(inst: &RenderInstruction) -> Option<BatchKey> {
+    match *inst {
+        // Sprites have see-through bits, and plain shapes don't
+        RenderInstruction::Draw(_, _, ref texture, ref shd, _) => Some(BatchKey {
+            shader: shd.clone(),
+            texture: texture.clone(),
+            blend: if texture.is_some() { BlendMode::Alpha } else { BlendMode::Opaque },
+        }),
+        RenderInstruction::DrawWorld(_, _, ref texture, blend) => Some(BatchKey {
+            // The tile shader is a plain textured one, so it does for anything else with a texture
+            shader: if texture.is_some() { "tiles" } else { "basic" }.into(),
+            texture: texture.clone(),
+            blend: blend,
+        }),
+        _ => None
+    }
+}
+
+// Draws that share a shader, texture and blending, squashed into one
+#[derive(Default)]
+struct Batch {
+    key: Option<BatchKey>,
+    vertices: Vec<Vertex>,
+    indices: Vec<Index>,
+}
+
+impl Batch {
+    // Moves the vertices into world space on the way in, since each draw had its own model matrix
+    fn add(&mut self, vertices: &[Vertex], indices: Option<&[Index]>, model_m: Matrix4<f32>) {
+        use cgmath::Vector4;
+
+        let base = self.vertices.len() as Index;
+        for v in vertices {
+            let p = model_m * Vector4::new(v.position[0], v.position[1], 0.0, 1.0);
+            self.vertices.push(Vertex {
+                position: [p.x, p.y],
+                ..*v
+            });
+        }
+        match indices {
+            Some(indices) => self.indices.extend(indices.iter().map(|i| base + i)),
+            None => self.indices.extend((0..vertices.len() as Index).map(|i| base + i)),
+        }
+    }
+
+    fn clear(&mut self) {
+        self.vertices.clear();
+        self.indices.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::channel;
+    use cgmath::{Point2, Vector2};
+    use super::{View, Renderer, visible_area};
+
+    fn assert_close(a: Point2<f32>, b: Point2<f32>) {
+        assert!((a.x - b.x).abs() < 0.001 && (a.y - b.y).abs() < 0.001, "{:?} isn't {:?}", a, b);
+    }
+
+    #[test]
+    fn flips_y() {
+        let view = View::new(800.0, 600.0);
+        assert_close(view.screen_to_world(0.0, 0.0).unwrap(), Point2::new(0.0, 600.0));
+        assert_close(view.screen_to_world(0.0, 600.0).unwrap(), Point2::new(0.0, 0.0));
+        assert_close(view.screen_to_world(200.0, 100.0).unwrap(), Point2::new(200.0, 500.0));
+    }
+
+    #[test]
+    fn zooms() {
+        let mut view = View::new(800.0, 600.0);
+        view.zoom = Vector2::new(2.0, 4.0);
+        // The middle stays put, and everything else is closer to it than it looks
+        assert_close(view.screen_to_world(400.0, 300.0).unwrap(), Point2::new(400.0, 300.0));
+        assert_close(view.screen_to_world(600.0, 100.0).unwrap(), Point2::new(500.0, 350.0));
+
+        view.zoom = Vector2::new(0.0, 1.0);
+        assert!(view.screen_to_world(400.0, 300.0).is_none());
+    }
+
+    #[test]
+    fn rotates() {
+        let mut view = View::new(800.0, 600.0);
+        view.rotation = 90.0;
+        // Turning the view left turns the world right, so what was up is now to the right
+        assert_close(view.screen_to_world(500.0, 300.0).unwrap(), Point2::new(400.0, 400.0));
+        assert_close(view.screen_to_world(400.0, 200.0).unwrap(), Point2::new(300.0, 300.0));
+    }
+
+    #[test]
+    fn moved_viewports() {
+        let mut view = View::new(800.0, 600.0);
+        view.center = Point2::new(0.0, 0.0);
+        view.set_viewport(100.0, 50.0, 200.0, 100.0);
+        // Viewport pixels, so where the viewport sits doesn't matter
+        assert_close(view.screen_to_world(100.0, 50.0).unwrap(), Point2::new(0.0, 0.0));
+        assert_close(view.screen_to_world(0.0, 100.0).unwrap(), Point2::new(-100.0, -50.0));
+    }
+
+    #[test]
+    fn renderer_finds_the_view() {
+        let (_, r) = channel();
+        let mut renderer = Renderer::new(r, (800, 600));
+        assert_close(renderer.screen_to_world(100.0, 100.0).unwrap().1, Point2::new(100.0, 500.0));
+
+        renderer.split_screen(&["left", "right"]);
+        let (name, p) = renderer.screen_to_world(100.0, 300.0).unwrap();
+        assert_eq!(name, "left");
+        assert_close(p, Point2::new(100.0, 300.0));
+        // Each half looks at its own middle
+        let (name, p) = renderer.screen_to_world(600.0, 100.0).unwrap();
+        assert_eq!(name, "right");
+        assert_close(p, Point2::new(200.0, 500.0));
+
+        assert!(renderer.screen_to_world(900.0, 100.0).is_none());
+        renderer.view_named_mut("right").unwrap().zoom = Vector2::new(0.0, 0.0);
+        assert!(renderer.screen_to_world(600.0, 100.0).is_none());
+    }
+
+    #[test]
+    fn removing_views_keeps_the_selection() {
+        let (_, r) = channel();
+        let mut renderer = Renderer::new(r, (800, 600));
+        renderer.split_screen(&["left", "right"]);
+        renderer.selected = renderer.find("right");
+        renderer.remove_view("left");
+        assert_eq!(renderer.selected, renderer.find("right"));
+        assert!(renderer.selected.is_some());
+        renderer.remove_view("right");
+        assert_eq!(renderer.selected, None);
+    }
+
+    #[test]
+    fn visible_areas() {
+        let mut view = View::new(800.0, 600.0);
+        view.zoom = Vector2::new(2.0, 2.0);
+        let seen = visible_area(&view, view.matrix()).unwrap();
+        assert_close(Point2::new(seen[0], seen[1]), Point2::new(200.0, 150.0));
+        assert_close(Point2::new(seen[2], seen[3]), Point2::new(600.0, 450.0));
+        view.zoom = Vector2::new(1.0, 0.0);
+        assert_eq!(visible_area(&view, view.matrix()), None);
     }
 }